@@ -0,0 +1,37 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::error;
+
+use crate::utils::avatar_utils::{cached_avatar, default_avatar};
+
+/// Cached Discord avatar, the default one when it is missing. ETag and Last-Modified come from the file
+/// so browsers revalidate instead of downloading it again
+#[get("/api/avatars/{discord_id}/{size}")]
+pub async fn avatar(req: HttpRequest, path: web::Path<(String, u32)>) -> HttpResponse {
+    let (discord_id, size) = path.into_inner();
+
+    let (file_path, cache_control) = match cached_avatar(&discord_id, size) {
+        Some(file_path) => (file_path, "public, max-age=3600"),
+        // Short lived so the real avatar shows up soon after being fetched
+        None => match default_avatar(&discord_id, size) {
+            Ok(file_path) => (file_path, "public, max-age=300"),
+            Err(err) => {
+                error!("Can't generate default avatar: {}", err);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+    };
+
+    match NamedFile::open_async(&file_path).await {
+        Ok(file) => {
+            let mut response: HttpResponse = file.into_response(&req);
+            response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+            response
+        }
+        Err(err) => {
+            error!("Can't open avatar {}: {}", file_path.display(), err);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
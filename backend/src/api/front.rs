@@ -1,20 +1,47 @@
 use actix_session::Session;
+use actix_web::cookie::Cookie;
+use actix_web::http::header::{ContentEncoding, ContentType};
 use actix_web::{get, post, web, HttpResponse, Responder};
+use lazy_static::lazy_static;
+use log::{error, info};
 use mongodb::bson::{doc, to_bson, Document};
 use mongodb::Collection;
 use serde::Deserialize;
 use serenity::futures::TryStreamExt;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, MutexGuard};
 use uuid::Uuid;
 
-use crate::utils::auth_utils::is_auth_valid;
-use crate::utils::webhook_utils::{send_scena_comment_notif, send_scena_fiche_notif};
-use crate::{is_rate_limited, AppData};
-use shared::fiche_rp::{FicheRP, FicheState, ReviewMessage};
+use crate::utils::auth_utils::{hide_private_fiches, is_auth_valid, is_hidden_archive, is_hidden_draft, is_lead, is_staff};
+use crate::utils::db_utils::{claim_fiche, delete_draft, delete_review_message, edit_review_message, find_fiche_owner, find_notifications, find_search_candidates, find_session_account, find_terms_acceptances, get_website_meta, insert_notifications, mark_notifications_read, push_fiche_image, push_lifecycle_change, push_review_message, record_terms_acceptance, release_claim, remove_fiche_image, remove_session, revoke_session, revoke_sessions, set_fiche_votes, update_draft};
+use crate::utils::discord_utils::{compute_role_drift, sync_fiche_roles};
+use crate::utils::event_utils::{event_stream, publish_event};
+use crate::utils::export_utils::{fiche_to_html, fiche_to_markdown, fiche_to_pdf, ExportFormat, ExportedReview};
+use crate::utils::image_utils::{process_upload, remove_fiche_images, remove_image, store_image, ImageRejection, ProcessedImage};
+use crate::utils::privacy_utils::{delete_account, export_personal_data};
+use crate::utils::webhook_utils::{send_mention_notif, send_scena_comment_notif, send_scena_fiche_notif};
+use crate::{is_rate_limited, AppData, CONFIG};
+use shared::discord::User;
+use shared::events::{EventScope, LiveEventKind};
+use shared::fiche_rp::{CharacterStatus, FicheImage, FicheRP, FicheState, ImageKind, LifecycleChange, MessageDeletion, MessageEdit, ReviewClaim, ReviewMessage, ReviewQueue};
+use shared::legal::{LegalVersions, TermsAcceptance};
+use shared::mentions::{extract_mentions, MentionNotification};
 use shared::permissions::DiscordRole;
-use shared::user::FrontAccount;
+use shared::quota::QuotaOverview;
+use shared::search::{parse_terms, SearchHit};
+use shared::stats::StaffStats;
+use shared::user::{Account, AuthAccount, FrontAccount, SessionInfo};
 use shared::website_meta::WebsiteMeta;
 
+/// Body of the refusal of fiche submissions, the frontend asks for the acceptance on authentication
+const TERMS_NOT_ACCEPTED: &str = "The latest terms of use and privacy policy must be accepted before submitting a fiche";
+
+lazy_static! {
+    /// Held from the quota check to the write of the fiche, so two submissions can't both take the last place.
+    /// Quotas span every account, a single document update can't enforce them (the backend runs as a single instance)
+    static ref QUOTA_LOCK: Mutex<()> = Mutex::new(());
+}
+
 #[derive(Deserialize, Clone)]
 struct FrontQuery {
     pub auth_id: String,
@@ -22,6 +49,73 @@ struct FrontQuery {
     pub user_id: Option<String>,
 }
 
+#[derive(Deserialize, Clone)]
+struct ExportQuery {
+    pub auth_id: String,
+    pub fiche_id: String,
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub include_reviews: bool,
+}
+
+#[derive(Deserialize, Clone)]
+struct VoteQuery {
+    pub auth_id: String,
+    pub fiche_id: String,
+    pub approve: bool,
+}
+
+#[derive(Deserialize, Clone)]
+struct MessageQuery {
+    pub auth_id: String,
+    pub fiche_id: String,
+    pub message_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct UploadQuery {
+    pub auth_id: String,
+    pub fiche_id: String,
+    pub kind: ImageKind,
+}
+
+#[derive(Deserialize, Clone)]
+struct ImageQuery {
+    pub auth_id: String,
+    pub fiche_id: String,
+    pub image_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct SearchQuery {
+    pub auth_id: String,
+    pub query: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct SessionQuery {
+    pub auth_id: String,
+    pub session_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct TermsQuery {
+    pub auth_id: String,
+    pub cgu: u32,
+    pub privacy: u32,
+}
+
+#[derive(Deserialize, Clone)]
+struct QueueQuery {
+    pub auth_id: String,
+    pub queue: ReviewQueue,
+}
+
+const STATS_WEEKS: usize = 12;
+const MAX_DRAFTS: usize = 10;
+const MAX_SEARCH_HITS: usize = 50;
+const MAX_NOTIFICATIONS: i64 = 50;
+
 //TODO: FORCE PERMISSION CHECK
 
 #[get("/api/front/retrieve_auth_account")]
@@ -30,11 +124,18 @@ pub async fn retrieve_auth_account(front_query: web::Query<FrontQuery>, session:
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id
+            "sessions.auth_id" : &front_query.auth_id
         };
         let front_account: FrontAccount = accounts.find_one(query).await.expect("Can't retrieve accounts").unwrap();
+        let terms_accepted: bool = match find_terms_acceptances(&app_data.dbclient, &front_account.discord_user.id).await {
+            Ok(acceptances) => CONFIG.legal.is_accepted(&acceptances),
+            Err(err) => {
+                error!("Can't read the terms acceptances of {}: {}", front_account.discord_user.id, err);
+                return HttpResponse::InternalServerError().body("Failed to read the terms acceptances");
+            }
+        };
 
-        HttpResponse::Ok().json(&front_account)
+        HttpResponse::Ok().json(&AuthAccount { account: front_account, terms_accepted })
     } else {
         HttpResponse::Unauthorized().body("")
     };
@@ -59,21 +160,59 @@ pub async fn submit_ficherp(front_query: web::Query<FrontQuery>, mut ficherp: we
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id
+            "sessions.auth_id" : &front_query.auth_id
         };
 
-        ficherp.id = Uuid::now_v7().to_string();
         ficherp.state = FicheState::Waiting;
+        ficherp.claim = None;
+        ficherp.votes = vec![];
+        ficherp.lifecycle = vec![];
+        ficherp.messages = vec![];
+
+        if let Err(violation) = CONFIG.markdown.check_fiche(&ficherp) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        CONFIG.markdown.sanitize_fiche(&mut ficherp);
+        ficherp.refresh_search_terms();
 
         let user_account = accounts.find_one(query.clone()).await.unwrap().expect("Can't retrieve user!");
+        if let Some(refusal) = terms_refusal(&app_data.dbclient, &user_account.discord_user.id).await {
+            return refusal;
+        }
 
-        let update = doc! {
-            "$push": { "fiches": to_bson(&ficherp.clone()).unwrap() }
+        let _quota_guard = match check_quotas(&app_data.dbclient, &user_account.discord_user.id, &ficherp, front_query.fiche_id.as_deref()).await {
+            Ok(guard) => guard,
+            Err(refusal) => return refusal,
+        };
+
+        // Images only come from the upload route, a submitted draft keeps its own
+        ficherp.images = front_query.fiche_id.as_ref()
+                                    .and_then(|draft_id| user_account.fiches.iter().find(|fiche| &fiche.id == draft_id))
+                                    .map(|draft| draft.images.clone())
+                                    .unwrap_or_default();
+
+        // A submitted draft keeps its id and replaces itself, anything else is a new fiche
+        let (query, update) = if let Some(draft_id) = front_query.fiche_id.clone() {
+            ficherp.id = draft_id;
+            (doc! {
+                "sessions.auth_id" : &front_query.auth_id,
+                "fiches": {
+                    "$elemMatch": {"id": &ficherp.id, "state": to_bson(&FicheState::Draft).unwrap()}
+                }
+            }, doc! {
+                "$set": { "fiches.$": to_bson(&ficherp.clone()).unwrap() }
+            })
+        } else {
+            ficherp.id = Uuid::now_v7().to_string();
+            (query, doc! {
+                "$push": { "fiches": to_bson(&ficherp.clone()).unwrap() }
+            })
         };
 
         match accounts.update_one(query, update).await {
             Ok(update_result) => {
                 if update_result.matched_count > 0 {
+                    publish_event(LiveEventKind::Submitted, &user_account.discord_user.id, &ficherp.id, EventScope::Everyone);
                     send_scena_fiche_notif(ficherp.0, user_account.discord_user).await;
                     HttpResponse::Ok().body("Fiche inserted successfully")
                 } else {
@@ -87,48 +226,138 @@ pub async fn submit_ficherp(front_query: web::Query<FrontQuery>, mut ficherp: we
     };
 }
 
+/// Autosave of a fiche being written, creates the draft when `fiche_id` is missing and returns its id
+#[post("/api/front/save_draft")]
+pub async fn submit_draft(front_query: web::Query<FrontQuery>, mut draft: web::Json<FicheRP>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        draft.state = FicheState::Draft;
+        draft.claim = None;
+        draft.votes = vec![];
+        draft.lifecycle = vec![];
+        draft.search_terms = vec![];
+        draft.messages = vec![];
+        draft.version = vec![];
+        draft.submission_date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        if let Err(violation) = CONFIG.markdown.check_fiche(&draft) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        CONFIG.markdown.sanitize_fiche(&mut draft);
+
+        if let Some(draft_id) = front_query.fiche_id.clone() {
+            draft.id = draft_id;
+            return match update_draft(&app_data.dbclient, &front_query.auth_id, &draft).await {
+                Ok(true) => HttpResponse::Ok().json(&draft.id),
+                Ok(false) => HttpResponse::NotFound().body("Draft not found"),
+                Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+            };
+        }
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query.clone()).await.unwrap().expect("Can't retrieve user!");
+        if user_account.fiches.iter().filter(|fiche| fiche.state == FicheState::Draft).count() >= MAX_DRAFTS {
+            return HttpResponse::Conflict().body("Too many drafts, submit or delete some of them");
+        }
+
+        draft.id = Uuid::now_v7().to_string();
+        draft.images = vec![];
+        let update = doc! {
+            "$push": { "fiches": to_bson(&draft.clone()).unwrap() }
+        };
+
+        match accounts.update_one(query, update).await {
+            Ok(update_result) if update_result.matched_count > 0 => HttpResponse::Ok().json(&draft.id),
+            Ok(_) => HttpResponse::NotFound().body("Account not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[post("/api/front/delete_draft")]
+pub async fn submit_draft_deletion(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let Some(fiche_id) = front_query.fiche_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
+
+        match delete_draft(&app_data.dbclient, &front_query.auth_id, &fiche_id).await {
+            Ok(true) => {
+                if let Err(err) = remove_fiche_images(&fiche_id) {
+                    error!("Can't remove the images of draft {}: {}", fiche_id, err);
+                }
+                HttpResponse::Ok().body("Draft deleted")
+            }
+            Ok(false) => HttpResponse::NotFound().body("Draft not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
 #[post("/api/front/submit_ficherp_admin")]
 pub async fn submit_ficherp_admin(front_query: web::Query<FrontQuery>, mut ficherp: web::Json<FicheRP>, app_data: web::Data<AppData>) -> impl Responder {
     return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
-
-        let meta: Collection<WebsiteMeta> = app_data.dbclient.database("visualis-website").collection("website-meta");
-        let whitelist: Vec<String> = meta.find_one(Document::new()).await.expect("Can't retrieve accounts").unwrap().whitelist;
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id
+            "sessions.auth_id" : &front_query.auth_id
         };
 
         let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
 
-        if let Some(roles) = DiscordRole::from_role_ids(&user_account.discord_roles) {
-            if whitelist.contains(&user_account.discord_user.id) || roles.iter().filter(|user_role| { **user_role == DiscordRole::PlatformAdmin || **user_role == DiscordRole::Admin || **user_role == DiscordRole::LeadScenarist || **user_role == DiscordRole::LeadMed || **user_role == DiscordRole::Scenarist }).count() > 0 || user_account.fiches.iter().filter(|fiche| &fiche.id == &front_query.fiche_id.clone().unwrap()).count() > 0 {
-                let query = doc! {
-                    "discord_user.id" : &front_query.user_id
-                };
+        // The fiche is accepted straight away, which is a lead decision
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+        let Some(owner_id) = front_query.user_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
 
-                ficherp.id = Uuid::now_v7().to_string();
-                ficherp.state = FicheState::Accepted;
+        ficherp.id = Uuid::now_v7().to_string();
+        ficherp.state = FicheState::Accepted;
+        ficherp.claim = None;
+        ficherp.votes = vec![];
+        ficherp.images = vec![];
 
-                let update = doc! {
-                "$push": { "fiches": to_bson(&ficherp.into_inner()).unwrap() }
-                };
+        if let Err(violation) = CONFIG.markdown.check_fiche(&ficherp) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        CONFIG.markdown.sanitize_fiche(&mut ficherp);
+        ficherp.refresh_search_terms();
 
-                match accounts.update_one(query, update).await {
-                    Ok(update_result) => {
-                        if update_result.matched_count > 0 {
-                            HttpResponse::Ok().body("Fiche inserted successfully")
-                        } else {
-                            HttpResponse::NotFound().body("Account not found")
-                        }
-                    }
-                    Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        let _quota_guard = match check_quotas(&app_data.dbclient, &owner_id, &ficherp, None).await {
+            Ok(guard) => guard,
+            Err(refusal) => return refusal,
+        };
+
+        let query = doc! {
+            "discord_user.id" : &owner_id
+        };
+        let update = doc! {
+            "$push": { "fiches": to_bson(&ficherp.clone()).unwrap() }
+        };
+
+        match accounts.update_one(query, update).await {
+            Ok(update_result) => {
+                if update_result.matched_count > 0 {
+                    // Same roles as a fiche accepted after its review, nothing is revoked from the other fiches
+                    let submitted: FicheRP = FicheRP { state: FicheState::Waiting, ..ficherp.clone() };
+                    sync_fiche_roles(owner_id.clone(), &submitted, ficherp.clone(), vec![]).await;
+                    publish_event(LiveEventKind::Submitted, &owner_id, &ficherp.id, EventScope::Everyone);
+                    HttpResponse::Ok().body("Fiche inserted successfully")
+                } else {
+                    HttpResponse::NotFound().body("Account not found")
                 }
-            } else {
-                HttpResponse::Unauthorized().body("")
             }
-        } else {
-            HttpResponse::Unauthorized().body("")
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
         }
     } else {
         HttpResponse::Unauthorized().body("")
@@ -146,11 +375,34 @@ pub async fn submit_ficherp_modif(front_query: web::Query<FrontQuery>, mut fiche
             return HttpResponse::TooManyRequests().body("Rate limit exceeded. Try again later.");
         }
 
+        if let Err(violation) = CONFIG.markdown.check_fiche(&ficherp) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        CONFIG.markdown.sanitize_fiche(&mut ficherp);
+        ficherp.refresh_search_terms();
+
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id,
-            "fiches.id": &front_query.fiche_id
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+        if let Some(refusal) = terms_refusal(&app_data.dbclient, &user_account.discord_user.id).await {
+            return refusal;
+        }
+
+        // The modified fiche goes back to `Waiting` and takes a slot, even when the previous version (e.g. refused) didn't
+        let _quota_guard = match check_quotas(&app_data.dbclient, &user_account.discord_user.id, &ficherp, front_query.fiche_id.as_deref()).await {
+            Ok(guard) => guard,
+            Err(refusal) => return refusal,
+        };
+
+        // Drafts are submitted through submit_ficherp, a modification would skip the notification
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id,
+            "fiches": {
+                "$elemMatch": {"id": &front_query.fiche_id, "state": {"$ne": to_bson(&FicheState::Draft).unwrap()}}
+            }
         };
         let update = doc! {
             "$set": {
@@ -160,12 +412,16 @@ pub async fn submit_ficherp_modif(front_query: web::Query<FrontQuery>, mut fiche
                 "fiches.$.description": to_bson(&ficherp.description).unwrap(),
                 "fiches.$.lore": to_bson(&ficherp.lore).unwrap(),
                 "fiches.$.version": to_bson(&ficherp.version).unwrap(),
+                "fiches.$.search_terms": to_bson(&ficherp.search_terms).unwrap(),
+                // Votes approved the previous version
+                "fiches.$.votes": [],
             }
         };
 
         match accounts.update_one(query, update).await {
             Ok(update_result) => {
                 if update_result.matched_count > 0 {
+                    publish_event(LiveEventKind::Updated, &user_account.discord_user.id, &front_query.fiche_id.clone().unwrap_or_default(), EventScope::Everyone);
                     HttpResponse::Ok().body("Comment inserted successfully")
                 } else {
                     HttpResponse::NotFound().body("Account not found")
@@ -192,6 +448,10 @@ pub async fn submit_comment(front_query: web::Query<FrontQuery>, mut comment: we
         if front_query.fiche_id.is_none() {
             return HttpResponse::BadRequest().body("");
         }
+        if let Err(violation) = CONFIG.markdown.check_comment(&comment.content) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        comment.content = CONFIG.markdown.sanitize(&comment.content);
 
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
         let meta: Collection<WebsiteMeta> = app_data.dbclient.database("visualis-website").collection("website-meta");
@@ -199,43 +459,188 @@ pub async fn submit_comment(front_query: web::Query<FrontQuery>, mut comment: we
         let whitelist: Vec<String> = meta.find_one(Document::new()).await.expect("Can't retrieve accounts").unwrap().whitelist;
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id
+            "sessions.auth_id" : &front_query.auth_id
         };
 
         let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
 
-        if let Some(roles) = DiscordRole::from_role_ids(&user_account.discord_roles) {
-            if whitelist.contains(&user_account.discord_user.id) || roles.iter().filter(|user_role| { **user_role == DiscordRole::PlatformAdmin || **user_role == DiscordRole::Admin || **user_role == DiscordRole::LeadScenarist || **user_role == DiscordRole::LeadMed || **user_role == DiscordRole::Scenarist }).count() > 0 || user_account.fiches.iter().filter(|fiche| &fiche.id == &front_query.fiche_id.clone().unwrap()).count() > 0 {
-                let query = doc! {
-                    "fiches.id": &front_query.fiche_id
-                };
-                let update = if comment.set_state == FicheState::Comment {
-                    doc! {
-                        "$push": {"fiches.$.messages": to_bson(&comment.clone()).unwrap()}
-                    }
-                } else {
-                    doc! {
-                        "$set": {"fiches.$.state": to_bson(&comment.set_state.clone()).unwrap()},
-                        "$push": {"fiches.$.messages": to_bson(&comment.clone()).unwrap()}
-                    }
-                };
-                match accounts.update_one(query, update).await {
-                    Ok(update_result) => {
-                        if update_result.matched_count > 0 {
-                            send_scena_comment_notif(user_account.fiches.iter().filter(|fiche| fiche.id.eq(&front_query.fiche_id.clone().unwrap())).nth(0).unwrap().clone(), comment.0, user_account.discord_user).await;
-
-                            HttpResponse::Ok().body("Comment inserted successfully")
-                        } else {
-                            HttpResponse::NotFound().body("Account not found")
-                        }
-                    }
-                    Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        let fiche_id: String = front_query.fiche_id.clone().unwrap();
+        let is_reviewer: bool = is_staff(&user_account, &whitelist);
+        if !is_reviewer && !user_account.fiches.iter().any(|fiche| fiche.id == fiche_id) {
+            return HttpResponse::Unauthorized().body("");
+        }
+        // Owners can discuss their fiche, deciding on it is left to the staff
+        if !is_reviewer && comment.set_state != FicheState::Comment {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == fiche_id).unwrap().clone();
+
+        if ficherp.state == FicheState::Draft {
+            return HttpResponse::BadRequest().body("The fiche is a draft");
+        }
+
+        // CONFORME is reached through the reviewer votes, then a lead takes the final decision
+        match comment.set_state {
+            FicheState::Draft => return HttpResponse::BadRequest().body(""),
+            FicheState::StaffValidated => return HttpResponse::BadRequest().body("A fiche becomes CONFORME through the reviewer votes"),
+            FicheState::Accepted if !is_lead(&user_account, &whitelist) => return HttpResponse::Unauthorized().body(""),
+            FicheState::Accepted if ficherp.state != FicheState::StaffValidated => return HttpResponse::Conflict().body("The approval quorum is not reached"),
+            _ => {}
+        }
+
+        if let Some(parent_id) = &comment.parent_id {
+            let Some(parent) = ficherp.messages.iter().find(|message| &message.id == parent_id) else {
+                return HttpResponse::NotFound().body("Message not found");
+            };
+            if comment.set_state != FicheState::Comment {
+                return HttpResponse::BadRequest().body("A reply can't change the state of the fiche");
+            }
+            if parent.is_private && !is_reviewer {
+                return HttpResponse::Unauthorized().body("");
+            }
+            // A reply stays as private as the message it answers
+            comment.is_private |= parent.is_private;
+        }
+
+        // Edits and deletions rely on the author, it is never taken from the client
+        comment.discord_id = user_account.discord_user.id.clone();
+        comment.id = Uuid::now_v7().to_string();
+        comment.edits = vec![];
+        comment.deletion = None;
+        comment.refresh_search_terms();
+
+        match push_review_message(&app_data.dbclient, &fiche_id, &comment).await {
+            Ok(true) => {
+                if comment.set_state != FicheState::Comment {
+                    let decided: FicheRP = FicheRP { state: comment.set_state.clone(), ..ficherp.clone() };
+                    sync_fiche_roles(owner_account.discord_user.id.clone(), &ficherp, decided, owner_account.fiches.clone()).await;
                 }
-            } else {
-                HttpResponse::Unauthorized().body("")
+                let kind: LiveEventKind = match comment.set_state {
+                    FicheState::Comment => LiveEventKind::Comment,
+                    _ => LiveEventKind::StateChanged(comment.set_state.clone()),
+                };
+                // The state of a fiche is public, only a private comment alone stays among the staff
+                let scope: EventScope = if comment.is_private && comment.set_state == FicheState::Comment { EventScope::Staff } else { EventScope::Everyone };
+                publish_event(kind, &owner_account.discord_user.id, &fiche_id, scope);
+                notify_mentions(&app_data.dbclient, &owner_account.discord_user.id, &ficherp, &comment, extract_mentions(&comment.content), &user_account.discord_user).await;
+                send_scena_comment_notif(ficherp, comment.0, user_account.discord_user).await;
+
+                HttpResponse::Ok().body("Comment inserted successfully")
             }
-        } else {
-            HttpResponse::Unauthorized().body("")
+            Ok(false) => HttpResponse::NotFound().body("Account not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Author edit of a review message, the previous content stays visible in the edit history
+#[post("/api/front/edit_comment")]
+pub async fn submit_comment_edit(message_query: web::Query<MessageQuery>, mut content: web::Json<String>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*message_query.auth_id, app_data.dbclient.clone()).await {
+        let max_requests = 10;
+        let time_window = Duration::from_secs(300);
+
+        if is_rate_limited(&message_query.auth_id, max_requests, time_window, &app_data) {
+            return HttpResponse::TooManyRequests().body("Rate limit exceeded. Try again later.");
+        }
+        if content.trim().is_empty() {
+            return HttpResponse::BadRequest().body("Empty comment");
+        }
+        if let Err(violation) = CONFIG.markdown.check_comment(&content) {
+            return HttpResponse::PayloadTooLarge().body(violation.to_string());
+        }
+        *content = CONFIG.markdown.sanitize(&content);
+
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &message_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &message_query.fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == message_query.fiche_id).unwrap();
+        let Some(message) = ficherp.messages.iter().find(|message| message.id == message_query.message_id) else {
+            return HttpResponse::NotFound().body("Message not found");
+        };
+
+        if !message.can_be_edited_by(&user_account.discord_user.id) {
+            return HttpResponse::Unauthorized().body("");
+        }
+        if message.content == *content {
+            return HttpResponse::Ok().body("Nothing to edit");
+        }
+
+        let edit: MessageEdit = MessageEdit {
+            content: message.content.clone(),
+            date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        };
+        match edit_review_message(&app_data.dbclient, &message_query.fiche_id, &message_query.message_id, &user_account.discord_user.id, &content, &edit).await {
+            Ok(true) => {
+                let scope: EventScope = if message.is_private { EventScope::Staff } else { EventScope::Everyone };
+                publish_event(LiveEventKind::Comment, &owner_account.discord_user.id, &message_query.fiche_id, scope);
+
+                // Only the mentions added by the edit are notified
+                let previous_mentions: Vec<String> = extract_mentions(&message.content);
+                let new_mentions: Vec<String> = extract_mentions(&content).into_iter().filter(|id| !previous_mentions.contains(id)).collect();
+                notify_mentions(&app_data.dbclient, &owner_account.discord_user.id, ficherp, message, new_mentions, &user_account.discord_user).await;
+                HttpResponse::Ok().body("Comment edited")
+            }
+            Ok(false) => HttpResponse::NotFound().body("Message not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Soft deletion of a review message by its author or the staff, the state it set is kept
+#[post("/api/front/delete_comment")]
+pub async fn submit_comment_deletion(message_query: web::Query<MessageQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*message_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &message_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &message_query.fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == message_query.fiche_id).unwrap();
+        let Some(message) = ficherp.messages.iter().find(|message| message.id == message_query.message_id) else {
+            return HttpResponse::NotFound().body("Message not found");
+        };
+
+        if !message.can_be_deleted_by(&user_account.discord_user.id, is_staff(&user_account, &whitelist)) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let deletion: MessageDeletion = MessageDeletion {
+            discord_id: user_account.discord_user.id.clone(),
+            date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        };
+        match delete_review_message(&app_data.dbclient, &message_query.fiche_id, &message_query.message_id, &deletion).await {
+            Ok(true) => {
+                let scope: EventScope = if message.is_private { EventScope::Staff } else { EventScope::Everyone };
+                publish_event(LiveEventKind::Comment, &owner_account.discord_user.id, &message_query.fiche_id, scope);
+                HttpResponse::Ok().body("Comment deleted")
+            }
+            Ok(false) => HttpResponse::NotFound().body("Message not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
         }
     } else {
         HttpResponse::Unauthorized().body("")
@@ -248,7 +653,7 @@ pub async fn retrieve_accounts(front_query: web::Query<FrontQuery>, session: Ses
         let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
 
         let query = doc! {
-            "auth_id" : &front_query.auth_id
+            "sessions.auth_id" : &front_query.auth_id
         };
 
         let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
@@ -257,6 +662,8 @@ pub async fn retrieve_accounts(front_query: web::Query<FrontQuery>, session: Ses
 
         let mut vec_front_accounts: Vec<FrontAccount> = accounts.find(Document::new()).await.expect("Can't retrieve accounts").try_collect().await.expect("Can't set account into vec");
 
+        hide_private_fiches(&mut vec_front_accounts, &user_account.discord_user.id, is_staff(&user_account, &whitelist));
+
         if let Some(roles) = DiscordRole::from_role_ids(&user_account.discord_roles) {
             if whitelist.contains(&user_account.discord_user.id) || roles.iter().filter(|user_role| { **user_role == DiscordRole::PlatformAdmin || **user_role == DiscordRole::Admin || **user_role == DiscordRole::LeadScenarist || **user_role == DiscordRole::LeadMed || **user_role == DiscordRole::Scenarist }).count() > 0 {
                 HttpResponse::Ok().json(&vec_front_accounts)
@@ -264,6 +671,7 @@ pub async fn retrieve_accounts(front_query: web::Query<FrontQuery>, session: Ses
                 vec_front_accounts.iter_mut().for_each(|account| {
                     account.fiches.iter_mut().for_each(|fiche| {
                         fiche.messages.retain(|message| !message.is_private);
+                        fiche.messages.iter_mut().for_each(ReviewMessage::redact);
                     });
                 });
                 HttpResponse::Ok().json(&vec_front_accounts)
@@ -272,6 +680,7 @@ pub async fn retrieve_accounts(front_query: web::Query<FrontQuery>, session: Ses
             vec_front_accounts.iter_mut().for_each(|account| {
                 account.fiches.iter_mut().for_each(|fiche| {
                     fiche.messages.retain(|message| !message.is_private);
+                    fiche.messages.iter_mut().for_each(ReviewMessage::redact);
                 });
             });
             HttpResponse::Ok().json(&vec_front_accounts)
@@ -279,4 +688,934 @@ pub async fn retrieve_accounts(front_query: web::Query<FrontQuery>, session: Ses
     } else {
         HttpResponse::Unauthorized().body("")
     };
-}
\ No newline at end of file
+}
+
+#[get("/api/front/retrieve_role_drift")]
+pub async fn retrieve_role_drift(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let meta: Collection<WebsiteMeta> = app_data.dbclient.database("visualis-website").collection("website-meta");
+        let whitelist: Vec<String> = meta.find_one(Document::new()).await.expect("Can't retrieve accounts").unwrap().whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        match compute_role_drift(app_data.dbclient.clone()).await {
+            Ok(drifts) => HttpResponse::Ok().json(&drifts),
+            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_job_statuses")]
+pub async fn retrieve_job_statuses(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        HttpResponse::Ok().json(app_data.scheduler.statuses())
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[post("/api/front/claim_fiche")]
+pub async fn submit_fiche_claim(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let Some(fiche_id) = front_query.fiche_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_staff(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let claim: ReviewClaim = new_claim(user_account.discord_user.id.clone(), None);
+        match claim_fiche(&app_data.dbclient, &fiche_id, &claim, false).await {
+            Ok(true) => {
+                publish_claim_event(&app_data.dbclient, &fiche_id).await;
+                HttpResponse::Ok().json(&claim)
+            }
+            Ok(false) => claim_refusal(&app_data.dbclient, &fiche_id).await,
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[post("/api/front/unclaim_fiche")]
+pub async fn submit_fiche_unclaim(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let Some(fiche_id) = front_query.fiche_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == fiche_id).unwrap();
+
+        let is_claimant: bool = ficherp.claim.as_ref().is_some_and(|claim| claim.discord_id == user_account.discord_user.id);
+        if !is_claimant && !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        match release_claim(&app_data.dbclient, &fiche_id).await {
+            Ok(_) => {
+                publish_event(LiveEventKind::Updated, &owner_account.discord_user.id, &fiche_id, EventScope::Staff);
+                HttpResponse::Ok().body("Claim released")
+            }
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Leads assign a fiche to a reviewer (`user_id`), overriding any current claim
+#[post("/api/front/assign_fiche")]
+pub async fn submit_fiche_assign(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let (Some(fiche_id), Some(reviewer_id)) = (front_query.fiche_id.clone(), front_query.user_id.clone()) else {
+            return HttpResponse::BadRequest().body("");
+        };
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let query = doc! {
+            "discord_user.id" : &reviewer_id
+        };
+        match accounts.find_one(query).await {
+            Ok(Some(reviewer_account)) if is_staff(&reviewer_account, &whitelist) => {}
+            Ok(Some(_)) => return HttpResponse::BadRequest().body("The assignee is not a reviewer"),
+            _ => return HttpResponse::NotFound().body("Account not found"),
+        }
+
+        let claim: ReviewClaim = new_claim(reviewer_id, Some(user_account.discord_user.id.clone()));
+        match claim_fiche(&app_data.dbclient, &fiche_id, &claim, true).await {
+            Ok(true) => {
+                publish_claim_event(&app_data.dbclient, &fiche_id).await;
+                HttpResponse::Ok().json(&claim)
+            }
+            Ok(false) => claim_refusal(&app_data.dbclient, &fiche_id).await,
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Vote of a reviewer on a fiche, the fiche moves to (or back from) `StaffValidated` following the quorum of its job family
+#[post("/api/front/vote_fiche")]
+pub async fn submit_fiche_vote(vote_query: web::Query<VoteQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*vote_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &vote_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_staff(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &vote_query.fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        if owner_account.discord_user.id == user_account.discord_user.id {
+            return HttpResponse::Forbidden().body("Can't vote on your own fiche");
+        }
+        let mut ficherp: FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == vote_query.fiche_id).unwrap().clone();
+        if !ficherp.is_reviewable() {
+            return HttpResponse::Conflict().body("The fiche is not waiting for a review");
+        }
+
+        let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        if !ficherp.cast_vote(&user_account.discord_user.id, vote_query.approve, now) {
+            return HttpResponse::Ok().json(ficherp.vote_tally(&CONFIG.approval_quorum));
+        }
+        let state: FicheState = ficherp.state_after_votes(&CONFIG.approval_quorum);
+
+        match set_fiche_votes(&app_data.dbclient, &ficherp.id, &ficherp.votes, &ficherp.state, &state).await {
+            Ok(true) => {
+                let kind: LiveEventKind = if state != ficherp.state { LiveEventKind::StateChanged(state) } else { LiveEventKind::Updated };
+                publish_event(kind, &owner_account.discord_user.id, &ficherp.id, EventScope::Everyone);
+                HttpResponse::Ok().json(ficherp.vote_tally(&CONFIG.approval_quorum))
+            }
+            Ok(false) => HttpResponse::Conflict().body("The fiche changed in the meantime, try again"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Retirement, death, archiving or comeback of an accepted character, only active characters keep their job roles
+#[post("/api/front/set_character_status")]
+pub async fn submit_character_status(front_query: web::Query<FrontQuery>, mut change: web::Json<LifecycleChange>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let Some(fiche_id) = front_query.fiche_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
+        if change.reason.trim().is_empty() {
+            return HttpResponse::BadRequest().body("A reason is required");
+        }
+
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == fiche_id).unwrap().clone();
+
+        let is_owner: bool = owner_account.discord_user.id == user_account.discord_user.id;
+        if !change.status.can_be_set_by(is_owner, is_staff(&user_account, &whitelist), is_lead(&user_account, &whitelist)) {
+            return HttpResponse::Unauthorized().body("");
+        }
+        if ficherp.state != FicheState::Accepted {
+            return HttpResponse::Conflict().body("Only accepted characters have a lifecycle");
+        }
+        if ficherp.character_status() == change.status {
+            return HttpResponse::Conflict().body("The character already has this status");
+        }
+        // A comeback takes a slot again
+        let _quota_guard = if change.status == CharacterStatus::Active {
+            match check_quotas(&app_data.dbclient, &owner_account.discord_user.id, &ficherp, Some(&ficherp.id)).await {
+                Ok(guard) => Some(guard),
+                Err(refusal) => return refusal,
+            }
+        } else {
+            None
+        };
+
+        change.date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        change.set_by = user_account.discord_user.id.clone();
+
+        match push_lifecycle_change(&app_data.dbclient, &fiche_id, &change).await {
+            Ok(true) => {
+                let mut changed: FicheRP = ficherp.clone();
+                changed.lifecycle.push(change.0.clone());
+                sync_fiche_roles(owner_account.discord_user.id.clone(), &ficherp, changed, owner_account.fiches.clone()).await;
+                // Players stop seeing an archived character, they are not told about it either
+                let scope: EventScope = if change.status == CharacterStatus::Archived { EventScope::StaffAndOwner } else { EventScope::Everyone };
+                publish_event(LiveEventKind::Updated, &owner_account.discord_user.id, &fiche_id, scope);
+                HttpResponse::Ok().body("Character status updated")
+            }
+            Ok(false) => HttpResponse::NotFound().body("Fiche not found"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_quota_overview")]
+pub async fn retrieve_quota_overview(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let all_accounts: Vec<FrontAccount> = accounts.find(Document::new()).await.expect("Can't retrieve accounts").try_collect().await.expect("Can't set account into vec");
+        let all_fiches: Vec<&FicheRP> = all_accounts.iter().flat_map(|account| account.fiches.iter()).collect();
+
+        HttpResponse::Ok().json(QuotaOverview {
+            rules: CONFIG.quotas.clone(),
+            usages: CONFIG.quotas.usages(&all_fiches),
+        })
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_markdown_rules")]
+pub async fn retrieve_markdown_rules(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        HttpResponse::Ok().json(&CONFIG.markdown)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_legal_versions")]
+pub async fn retrieve_legal_versions(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        HttpResponse::Ok().json(CONFIG.legal)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Records the acceptance of the texts the user read, refused when a newer version was published in the meantime
+#[post("/api/front/accept_terms")]
+pub async fn submit_terms_acceptance(terms_query: web::Query<TermsQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*terms_query.auth_id, app_data.dbclient.clone()).await {
+        let versions: LegalVersions = LegalVersions { cgu: terms_query.cgu, privacy: terms_query.privacy };
+        if versions != CONFIG.legal {
+            return HttpResponse::Conflict().body("A newer version of the terms was published");
+        }
+
+        let acceptance: TermsAcceptance = TermsAcceptance {
+            versions,
+            date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        };
+        match record_terms_acceptance(&app_data.dbclient, &terms_query.auth_id, &acceptance).await {
+            Ok(true) => HttpResponse::Ok().body("Terms accepted"),
+            Ok(false) => HttpResponse::NotFound().body("Account not found"),
+            Err(err) => {
+                error!("Can't record terms acceptance: {}", err);
+                HttpResponse::InternalServerError().body("Failed to record acceptance")
+            }
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_approval_quorum")]
+pub async fn retrieve_approval_quorum(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        HttpResponse::Ok().json(&CONFIG.approval_quorum)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Ids of the fiches in a review queue of the requesting reviewer
+#[get("/api/front/retrieve_review_queue")]
+pub async fn retrieve_review_queue(queue_query: web::Query<QueueQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*queue_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &queue_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_staff(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let all_accounts: Vec<FrontAccount> = accounts.find(Document::new()).await.expect("Can't retrieve accounts").try_collect().await.expect("Can't set account into vec");
+        let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let fiche_ids: Vec<String> = all_accounts.iter()
+                                                 .flat_map(|account| account.fiches.iter())
+                                                 .filter(|fiche| queue_query.queue.matches(fiche, &user_account.discord_user.id, now))
+                                                 .map(|fiche| fiche.id.clone())
+                                                 .collect();
+        HttpResponse::Ok().json(&fiche_ids)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_staff_stats")]
+pub async fn retrieve_staff_stats(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let meta: Collection<WebsiteMeta> = app_data.dbclient.database("visualis-website").collection("website-meta");
+        let whitelist: Vec<String> = meta.find_one(Document::new()).await.expect("Can't retrieve accounts").unwrap().whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        let all_accounts: Vec<FrontAccount> = accounts.find(Document::new()).await.expect("Can't retrieve accounts").try_collect().await.expect("Can't set account into vec");
+        let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        HttpResponse::Ok().json(StaffStats::compute(&all_accounts, now, STATS_WEEKS))
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Search over the name, description and lore of the fiches, and their review messages for the staff.
+/// Terms are word prefixes and accents are ignored, the fiches are looked up through the index of their folded words.
+#[get("/api/front/search")]
+pub async fn retrieve_search(search_query: web::Query<SearchQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*search_query.auth_id, app_data.dbclient.clone()).await {
+        let terms: Vec<String> = parse_terms(&search_query.query);
+        if terms.is_empty() {
+            return HttpResponse::BadRequest().body("Empty search");
+        }
+
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &search_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+        let is_staff: bool = is_staff(&user_account, &whitelist);
+
+        let candidates: Vec<FrontAccount> = match find_search_candidates(&app_data.dbclient, &terms, is_staff).await {
+            Ok(candidates) => candidates,
+            Err(_) => return HttpResponse::InternalServerError().body("Failed to search accounts"),
+        };
+
+        let mut hits: Vec<SearchHit> = candidates.iter().flat_map(|account| {
+            account.fiches.iter()
+                .filter(|fiche| fiche.state != FicheState::Draft && !is_hidden_archive(fiche, &account.discord_user.id, &user_account.discord_user.id, is_staff))
+                .filter_map(|fiche| fiche.search(&account.discord_user.id, &terms, is_staff))
+                .collect::<Vec<SearchHit>>()
+        }).collect();
+        hits.sort_by(|hit, other| other.score.cmp(&hit.score));
+        hits.truncate(MAX_SEARCH_HITS);
+
+        HttpResponse::Ok().json(&hits)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Server-Sent Events stream of the changes on the fiches the user can see, `EventSource` can't send headers hence the auth_id in the query
+#[get("/api/front/events")]
+pub async fn retrieve_events(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+        let is_staff: bool = is_staff(&user_account, &whitelist);
+
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            // Compression and nginx would both hold the events back in their buffers
+            .insert_header(ContentEncoding::Identity)
+            .insert_header(("X-Accel-Buffering", "no"))
+            .streaming(event_stream(app_data.dbclient.clone(), front_query.auth_id.clone(), user_account.discord_user.id, is_staff))
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Portrait or gallery image of a fiche, uploaded by its owner as the raw request body. A new portrait replaces the previous one
+#[post("/api/front/upload_image")]
+pub async fn submit_image(upload_query: web::Query<UploadQuery>, body: web::Bytes, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*upload_query.auth_id, app_data.dbclient.clone()).await {
+        let max_requests = 10;
+        let time_window = Duration::from_secs(600);
+
+        if is_rate_limited(&upload_query.auth_id, max_requests, time_window, &app_data) {
+            return HttpResponse::TooManyRequests().body("Rate limit exceeded. Try again later.");
+        }
+
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &upload_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let Some(ficherp) = user_account.fiches.iter().find(|fiche| fiche.id == upload_query.fiche_id) else {
+            return HttpResponse::NotFound().body("Fiche not found");
+        };
+        if upload_query.kind == ImageKind::Gallery && ficherp.gallery().len() >= CONFIG.images.max_gallery_images {
+            return HttpResponse::Conflict().body(format!("The gallery is full: {} images at most", CONFIG.images.max_gallery_images));
+        }
+
+        // Decoding and resizing are CPU bound, they must not hold an actix worker
+        let processed: ProcessedImage = match web::block(move || process_upload(&body, &CONFIG.images)).await {
+            Ok(Ok(processed)) => processed,
+            Ok(Err(rejection @ ImageRejection::TooLarge(_))) => return HttpResponse::PayloadTooLarge().body(rejection.to_string()),
+            Ok(Err(rejection)) => return HttpResponse::UnsupportedMediaType().body(rejection.to_string()),
+            Err(_) => return HttpResponse::InternalServerError().body("Failed to process the image"),
+        };
+
+        let image: FicheImage = FicheImage {
+            id: Uuid::now_v7().to_string(),
+            kind: upload_query.kind,
+            width: processed.width,
+            height: processed.height,
+            uploaded_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        };
+        if let Err(err) = store_image(&ficherp.id, &image.id, &processed) {
+            error!("Can't store image {} of fiche {}: {}", image.id, ficherp.id, err);
+            return HttpResponse::InternalServerError().body("Failed to store the image");
+        }
+
+        match push_fiche_image(&app_data.dbclient, &ficherp.id, &image, CONFIG.images.max_gallery_images).await {
+            Ok(true) => {
+                if let Some(previous) = ficherp.portrait().filter(|_| image.kind == ImageKind::Portrait) {
+                    delete_fiche_image(&app_data.dbclient, &ficherp.id, &previous.id).await;
+                }
+                publish_event(LiveEventKind::Updated, &user_account.discord_user.id, &ficherp.id, image_event_scope(&ficherp));
+                HttpResponse::Ok().json(&image.id)
+            }
+            result => {
+                let _ = remove_image(&ficherp.id, &image.id);
+                match result {
+                    // Another upload took the last place since the check above
+                    Ok(_) if image.kind == ImageKind::Gallery => HttpResponse::Conflict().body(format!("The gallery is full: {} images at most", CONFIG.images.max_gallery_images)),
+                    Ok(_) => HttpResponse::NotFound().body("Fiche not found"),
+                    Err(_) => HttpResponse::InternalServerError().body("Failed to update account"),
+                }
+            }
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Removal of a fiche image by the owner, or by the staff for moderation
+#[post("/api/front/delete_image")]
+pub async fn submit_image_deletion(image_query: web::Query<ImageQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*image_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &image_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &image_query.fiche_id).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == image_query.fiche_id).unwrap();
+
+        if owner_account.discord_user.id != user_account.discord_user.id && (ficherp.state == FicheState::Draft || !is_staff(&user_account, &whitelist)) {
+            return HttpResponse::Unauthorized().body("");
+        }
+        if !ficherp.images.iter().any(|image| image.id == image_query.image_id) {
+            return HttpResponse::NotFound().body("Image not found");
+        }
+
+        delete_fiche_image(&app_data.dbclient, &ficherp.id, &image_query.image_id).await;
+        publish_event(LiveEventKind::Updated, &owner_account.discord_user.id, &ficherp.id, image_event_scope(&ficherp));
+        HttpResponse::Ok().body("Image deleted")
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/retrieve_notifications")]
+pub async fn retrieve_notifications(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        match find_notifications(&app_data.dbclient, &user_account.discord_user.id, MAX_NOTIFICATIONS).await {
+            Ok(notifications) => HttpResponse::Ok().json(notifications),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to retrieve notifications"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[post("/api/front/read_notifications")]
+pub async fn submit_notifications_read(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        match mark_notifications_read(&app_data.dbclient, &user_account.discord_user.id).await {
+            Ok(_) => HttpResponse::Ok().body("Notifications read"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to update notifications"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Ends the session of this device. Expired sessions can log out too, only the cookie is needed
+#[post("/api/front/logout")]
+pub async fn submit_logout(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    if let Err(err) = remove_session(&app_data.dbclient, &front_query.auth_id).await {
+        error!("Can't remove session: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to log out");
+    }
+
+    HttpResponse::Ok().cookie(auth_cookie_removal()).body("Logged out")
+}
+
+#[get("/api/front/retrieve_sessions")]
+pub async fn retrieve_sessions(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let user_account: Account = find_session_account(&app_data.dbclient, &front_query.auth_id).await.unwrap().expect("Can't retrieve user!");
+        let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let mut sessions: Vec<SessionInfo> = user_account.sessions.iter()
+            .filter(|session| session.expires_at > now)
+            .map(|session| session.info(&front_query.auth_id))
+            .collect();
+        sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        HttpResponse::Ok().json(sessions)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[post("/api/front/revoke_session")]
+pub async fn submit_session_revoke(session_query: web::Query<SessionQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*session_query.auth_id, app_data.dbclient.clone()).await {
+        match revoke_session(&app_data.dbclient, &session_query.auth_id, &session_query.session_id).await {
+            Ok(true) => HttpResponse::Ok().body("Session revoked"),
+            Ok(false) => HttpResponse::NotFound().body("No such session"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to revoke session"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Logs an account out of every device, for compromised accounts
+#[post("/api/front/revoke_account_sessions")]
+pub async fn submit_account_sessions_revoke(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let Some(user_id) = front_query.user_id.clone() else {
+            return HttpResponse::BadRequest().body("");
+        };
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        if !is_lead(&user_account, &whitelist) {
+            return HttpResponse::Unauthorized().body("");
+        }
+
+        match revoke_sessions(&app_data.dbclient, &user_id).await {
+            Ok(true) => {
+                info!("{} revoked every session of {}", user_account.discord_user.id, user_id);
+                HttpResponse::Ok().body("Sessions revoked")
+            }
+            Ok(false) => HttpResponse::NotFound().body("No such account"),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to revoke sessions"),
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Everything stored about the user as a JSON download, the export is audited
+#[get("/api/front/retrieve_personal_data")]
+pub async fn retrieve_personal_data(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let user_account: Account = find_session_account(&app_data.dbclient, &front_query.auth_id).await.unwrap().expect("Can't retrieve user!");
+
+        match export_personal_data(&app_data.dbclient, &user_account, &user_account.discord_user.id).await {
+            Ok(personal_data) => HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"donnees-{}.json\"", user_account.discord_user.id)))
+                .body(serde_json::to_string_pretty(&personal_data).unwrap()),
+            Err(err) => {
+                error!("Can't export the data of {}: {}", user_account.discord_user.id, err);
+                HttpResponse::InternalServerError().body("Failed to export data")
+            }
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Deletes the account of the user, see `delete_account` for what is removed and what is anonymised
+#[post("/api/front/delete_account")]
+pub async fn submit_account_deletion(front_query: web::Query<FrontQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*front_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &front_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        match delete_account(&app_data.dbclient, &user_account.discord_user.id, &user_account.discord_user.id).await {
+            Ok(Some(_)) => HttpResponse::Ok().cookie(auth_cookie_removal()).body("Account deleted"),
+            Ok(None) => HttpResponse::NotFound().body("No such account"),
+            Err(err) => {
+                error!("Can't delete the account of {}: {}", user_account.discord_user.id, err);
+                HttpResponse::InternalServerError().body("Failed to delete account")
+            }
+        }
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+#[get("/api/front/export_fiche")]
+pub async fn export_fiche(export_query: web::Query<ExportQuery>, app_data: web::Data<AppData>) -> impl Responder {
+    return if is_auth_valid(&*export_query.auth_id, app_data.dbclient.clone()).await {
+        let accounts: Collection<FrontAccount> = app_data.dbclient.database("visualis-website").collection("account");
+
+        let query = doc! {
+            "sessions.auth_id" : &export_query.auth_id
+        };
+        let user_account = accounts.find_one(query).await.unwrap().expect("Can't retrieve user!");
+
+        let query = doc! {
+            "fiches.id": &export_query.fiche_id
+        };
+        let owner_account: FrontAccount = match accounts.find_one(query).await {
+            Ok(Some(account)) => account,
+            _ => return HttpResponse::NotFound().body("Fiche not found"),
+        };
+        let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == export_query.fiche_id).unwrap();
+        let is_viewer_staff: bool = is_staff(&user_account, &get_website_meta(&app_data.dbclient).await.whitelist);
+        if is_hidden_draft(ficherp, &owner_account.discord_user.id, &user_account.discord_user.id) || is_hidden_archive(ficherp, &owner_account.discord_user.id, &user_account.discord_user.id, is_viewer_staff) {
+            return HttpResponse::NotFound().body("Fiche not found");
+        }
+
+        let vec_front_accounts: Vec<FrontAccount> = if export_query.include_reviews {
+            accounts.find(Document::new()).await.expect("Can't retrieve accounts").try_collect().await.expect("Can't set account into vec")
+        } else {
+            vec![]
+        };
+        // Private messages never leave the site
+        let reviews: Vec<ExportedReview> = ficherp.messages.iter()
+                                                  .filter(|message| export_query.include_reviews && !message.is_private && !message.is_deleted())
+                                                  .map(|message| ExportedReview {
+                                                      author: vec_front_accounts.iter()
+                                                                                .find(|account| account.discord_user.id == message.discord_id)
+                                                                                .map(|account| account.discord_user.global_name.clone())
+                                                                                .unwrap_or("Inconnu".to_string()),
+                                                      message,
+                                                  })
+                                                  .collect();
+
+        let body: Vec<u8> = match export_query.format {
+            ExportFormat::Html => fiche_to_html(ficherp, &owner_account.discord_user, &reviews, &CONFIG.markdown).into_bytes(),
+            ExportFormat::Markdown => fiche_to_markdown(ficherp, &owner_account.discord_user, &reviews).into_bytes(),
+            ExportFormat::Pdf => match fiche_to_pdf(ficherp, &owner_account.discord_user, &reviews) {
+                Ok(bytes) => bytes,
+                Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+            },
+        };
+
+        HttpResponse::Ok()
+            .content_type(export_query.format.content_type())
+            .insert_header(("Content-Disposition", format!("inline; filename=\"fiche-{}.{}\"", ficherp.id, export_query.format.extension())))
+            .body(body)
+    } else {
+        HttpResponse::Unauthorized().body("")
+    };
+}
+
+/// Checks a fiche of `owner_id` against the slot limits and job quotas, `replaced_fiche_id` is the fiche it replaces (draft, modification).
+/// The returned guard has to be kept until the fiche is written, the refusal is the response to send
+async fn check_quotas(dbclient: &mongodb::Client, owner_id: &str, ficherp: &FicheRP, replaced_fiche_id: Option<&str>) -> Result<MutexGuard<'static, ()>, HttpResponse> {
+    let guard: MutexGuard<'static, ()> = QUOTA_LOCK.lock().await;
+    let accounts: Collection<FrontAccount> = dbclient.database("visualis-website").collection("account");
+    let all_accounts: Vec<FrontAccount> = match async { accounts.find(Document::new()).await?.try_collect::<Vec<FrontAccount>>().await }.await {
+        Ok(all_accounts) => all_accounts,
+        Err(err) => {
+            error!("Can't read the accounts to check the quotas: {}", err);
+            return Err(HttpResponse::InternalServerError().body("Failed to check the quotas"));
+        }
+    };
+
+    let other_fiches = || all_accounts.iter().flat_map(|account| account.fiches.iter().map(move |fiche| (account, fiche))).filter(|(_, fiche)| Some(fiche.id.as_str()) != replaced_fiche_id);
+    let owner_fiches: Vec<&FicheRP> = other_fiches().filter(|(account, _)| account.discord_user.id == owner_id).map(|(_, fiche)| fiche).collect();
+    let all_fiches: Vec<&FicheRP> = other_fiches().map(|(_, fiche)| fiche).collect();
+
+    match CONFIG.quotas.check(ficherp, &owner_fiches, &all_fiches) {
+        Ok(()) => Ok(guard),
+        Err(violation) => Err(HttpResponse::Conflict().body(violation.to_string())),
+    }
+}
+
+/// Notifies the mentioned users who can read the message: the staff, and the owner of the fiche when it is not private.
+/// The author mentioning themselves is ignored
+async fn notify_mentions(dbclient: &mongodb::Client, owner_id: &str, ficherp: &FicheRP, message: &ReviewMessage, mentioned_ids: Vec<String>, author: &User) {
+    let mentioned_ids: Vec<String> = mentioned_ids.into_iter().filter(|id| *id != author.id).collect();
+    if mentioned_ids.is_empty() {
+        return;
+    }
+
+    let accounts: Collection<FrontAccount> = dbclient.database("visualis-website").collection("account");
+    let whitelist: Vec<String> = get_website_meta(dbclient).await.whitelist;
+    let query = doc! {
+        "discord_user.id": {"$in": &mentioned_ids}
+    };
+    // The message is already saved, a failure here only costs the notifications
+    let mentioned_accounts: Vec<FrontAccount> = match async { accounts.find(query).await?.try_collect::<Vec<FrontAccount>>().await }.await {
+        Ok(mentioned_accounts) => mentioned_accounts,
+        Err(err) => {
+            error!("Can't retrieve the mentioned accounts: {}", err);
+            return;
+        }
+    };
+
+    let date: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let notifications: Vec<MentionNotification> = mentioned_accounts.iter()
+        .filter(|account| is_staff(account, &whitelist) || (account.discord_user.id == owner_id && !message.is_private))
+        .map(|account| MentionNotification {
+            id: Uuid::now_v7().to_string(),
+            discord_id: account.discord_user.id.clone(),
+            author_id: author.id.clone(),
+            owner_id: owner_id.to_string(),
+            fiche_id: ficherp.id.clone(),
+            message_id: message.id.clone(),
+            date,
+            read: false,
+        })
+        .collect();
+
+    if let Err(err) = insert_notifications(dbclient, &notifications).await {
+        error!("Can't store the mention notifications: {}", err);
+        return;
+    }
+    notifications.iter().for_each(|notification| {
+        publish_event(LiveEventKind::Mention, &notification.discord_id, &notification.fiche_id, EventScope::Owner);
+    });
+    if !notifications.is_empty() {
+        send_mention_notif(ficherp.clone(), notifications.into_iter().map(|notification| notification.discord_id).collect(), author.clone()).await;
+    }
+}
+
+/// Drops an image from its fiche then from the disk, a failure only leaves an orphan file behind
+async fn delete_fiche_image(dbclient: &mongodb::Client, fiche_id: &str, image_id: &str) {
+    if let Err(err) = remove_fiche_image(dbclient, fiche_id, image_id).await {
+        error!("Can't remove image {} from fiche {}: {}", image_id, fiche_id, err);
+        return;
+    }
+    if let Err(err) = remove_image(fiche_id, image_id) {
+        error!("Can't delete the files of image {}: {}", image_id, err);
+    }
+}
+
+/// Image changes reach the viewers of the fiche only, drafts and archived characters are hidden from the other players
+fn image_event_scope(ficherp: &FicheRP) -> EventScope {
+    if ficherp.state == FicheState::Draft {
+        EventScope::Owner
+    } else if ficherp.character_status() == CharacterStatus::Archived {
+        EventScope::StaffAndOwner
+    } else {
+        EventScope::Everyone
+    }
+}
+
+/// Claims are staff business, the owner is looked up only to address the event
+async fn publish_claim_event(dbclient: &mongodb::Client, fiche_id: &str) {
+    if let Ok(Some(owner_account)) = find_fiche_owner(dbclient, fiche_id).await {
+        publish_event(LiveEventKind::Updated, &owner_account.discord_user.id, fiche_id, EventScope::Staff);
+    }
+}
+
+/// Refusal of a submission when the current terms are not accepted or the acceptances can't be read
+async fn terms_refusal(dbclient: &mongodb::Client, discord_id: &str) -> Option<HttpResponse> {
+    match find_terms_acceptances(dbclient, discord_id).await {
+        Ok(acceptances) if CONFIG.legal.is_accepted(&acceptances) => None,
+        Ok(_) => Some(HttpResponse::Forbidden().body(TERMS_NOT_ACCEPTED)),
+        Err(err) => {
+            error!("Can't read the terms acceptances of {}: {}", discord_id, err);
+            Some(HttpResponse::InternalServerError().body("Failed to read the terms acceptances"))
+        }
+    }
+}
+
+/// Why `claim_fiche` matched nothing: a missing fiche, one out of the review or a claim held by someone else.
+/// Drafts are answered as missing, the staff doesn't know about them
+async fn claim_refusal(dbclient: &mongodb::Client, fiche_id: &str) -> HttpResponse {
+    match find_fiche_owner(dbclient, fiche_id).await {
+        Ok(Some(owner)) if owner.fiches.iter().any(|fiche| fiche.id == fiche_id && fiche.state == FicheState::Draft) => HttpResponse::NotFound().body("Fiche not found"),
+        Ok(Some(owner)) if owner.fiches.iter().any(|fiche| fiche.id == fiche_id && !fiche.is_claimable()) => HttpResponse::Conflict().body("Fiche is not awaiting a review"),
+        Ok(Some(_)) => HttpResponse::Conflict().body("Fiche already claimed by another reviewer"),
+        Ok(None) => HttpResponse::NotFound().body("Fiche not found"),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to retrieve fiche"),
+    }
+}
+
+/// Expired `auth_id` cookie, the browser drops it
+fn auth_cookie_removal() -> Cookie<'static> {
+    let mut auth_cookie: Cookie = Cookie::new("auth_id", "");
+    auth_cookie.set_domain(&CONFIG.domain);
+    auth_cookie.set_path("/");
+    auth_cookie.make_removal();
+    auth_cookie
+}
+
+fn new_claim(discord_id: String, assigned_by: Option<String>) -> ReviewClaim {
+    let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    ReviewClaim {
+        discord_id,
+        claimed_at: now,
+        expires_at: now + CONFIG.claim_duration_hours * 3600,
+        assigned_by,
+    }
+}
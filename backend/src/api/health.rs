@@ -0,0 +1,41 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use log::warn;
+use mongodb::bson::doc;
+
+use crate::utils::metrics_utils::{is_scrape_authorized, render_metrics};
+use crate::{AppData, CONFIG};
+
+/// Liveness, the process is up and serving requests
+#[get("/healthz")]
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+/// Readiness, the database answers
+#[get("/readyz")]
+pub async fn readyz(app_data: web::Data<AppData>) -> impl Responder {
+    match app_data.dbclient.database("visualis-website").run_command(doc! {"ping": 1}).await {
+        Ok(_) => HttpResponse::Ok().body("ready"),
+        Err(err) => {
+            warn!("Readiness check failed: {}", err);
+            HttpResponse::ServiceUnavailable().body("database unavailable")
+        }
+    }
+}
+
+/// Prometheus scrape, only for the holder of `metrics_token`. The gauges are cached, see `refresh_fiche_gauges`
+#[get("/metrics")]
+pub async fn metrics(req: HttpRequest) -> impl Responder {
+    if CONFIG.metrics_token.is_empty() {
+        return HttpResponse::NotFound().body("");
+    }
+    let authorization: Option<&str> = req.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if !is_scrape_authorized(authorization, &CONFIG.metrics_token) {
+        return HttpResponse::Unauthorized().body("");
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render_metrics())
+}
@@ -0,0 +1,78 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL, X_CONTENT_TYPE_OPTIONS};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::error;
+
+use shared::fiche_rp::{CharacterStatus, FicheRP, FicheState};
+use shared::user::{Account, FrontAccount};
+
+use crate::utils::auth_utils::{has_staff_role, is_auth_valid, is_hidden_archive, is_hidden_draft};
+use crate::utils::db_utils::{find_fiche_owner, find_session_account, get_website_meta};
+use crate::utils::image_utils::image_path;
+use crate::AppData;
+
+/// Stored image of a fiche, `{file_name}` is `<image id>.webp` or `<image id>_thumb.webp`.
+/// The images of a draft or of an archived character are only served to the viewers of the fiche (the auth cookie is sent
+/// along by the browser) and never cached, the others never change once stored since image ids are not reused
+#[get("/api/images/{fiche_id}/{file_name}")]
+pub async fn fiche_image(req: HttpRequest, path: web::Path<(String, String)>, app_data: web::Data<AppData>) -> HttpResponse {
+    let (fiche_id, file_name) = path.into_inner();
+    let Some(image_name) = file_name.strip_suffix(".webp") else {
+        return HttpResponse::NotFound().finish();
+    };
+    let (image_id, thumbnail): (&str, bool) = match image_name.strip_suffix("_thumb") {
+        Some(image_id) => (image_id, true),
+        None => (image_name, false),
+    };
+
+    let owner_account: FrontAccount = match find_fiche_owner(&app_data.dbclient, &fiche_id).await {
+        Ok(Some(owner_account)) => owner_account,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!("Can't retrieve the owner of fiche {}: {}", fiche_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let ficherp: &FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == fiche_id).unwrap();
+    // Files left behind by a removal are not served either
+    if !ficherp.images.iter().any(|image| image.id == image_id) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    // Drafts and archived characters are hidden from some viewers and can stop being hidden, they are never cached
+    let is_hideable: bool = ficherp.state == FicheState::Draft || ficherp.character_status() == CharacterStatus::Archived;
+    let cache_control: &'static str = if is_hideable {
+        let viewer: Option<Account> = match req.cookie("auth_id") {
+            Some(cookie) if is_auth_valid(cookie.value(), app_data.dbclient.clone()).await => find_session_account(&app_data.dbclient, cookie.value()).await.ok().flatten(),
+            _ => None,
+        };
+        let whitelist: Vec<String> = get_website_meta(&app_data.dbclient).await.whitelist;
+        let is_hidden: bool = viewer.is_none_or(|viewer| {
+            is_hidden_draft(ficherp, &owner_account.discord_user.id, &viewer.discord_user.id)
+                || is_hidden_archive(ficherp, &owner_account.discord_user.id, &viewer.discord_user.id, has_staff_role(&viewer.discord_user.id, &viewer.discord_roles, &whitelist))
+        });
+        if is_hidden {
+            return HttpResponse::NotFound().finish();
+        }
+        "private, no-store"
+    } else {
+        "public, max-age=31536000, immutable"
+    };
+
+    let file_path = match image_path(&fiche_id, image_id, thumbnail) {
+        Ok(file_path) => file_path,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+    match NamedFile::open_async(&file_path).await {
+        Ok(file) => {
+            let mut response: HttpResponse = file.into_response(&req);
+            response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+            response.headers_mut().insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+            response
+        }
+        Err(err) => {
+            error!("Can't open image {}: {}", file_path.display(), err);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use log::{error, info};
+use mongodb::bson::Document;
+use serde::Deserialize;
+use serde_json::Value;
+use serenity::all::{CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId, Http};
+use serenity::futures::TryStreamExt;
+use serenity::interactions_endpoint::Verifier;
+
+use shared::fiche_rp::{FicheRP, FicheState};
+use shared::user::FrontAccount;
+
+use crate::utils::auth_utils::{has_staff_role, is_hidden_archive, is_hidden_draft};
+use crate::utils::db_utils::{account_collection, meta_collection};
+use crate::{AppData, CONFIG};
+
+const PING: u8 = 1;
+const APPLICATION_COMMAND: u8 = 2;
+const MAX_CONTENT_LENGTH: usize = 1900;
+
+/// Subset of the Discord interaction payload, only what the slash commands need.
+#[derive(Deserialize)]
+pub struct InteractionPayload {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub data: Option<InteractionCommandData>,
+    pub member: Option<InteractionMember>,
+    pub user: Option<InteractionUser>,
+}
+
+#[derive(Deserialize)]
+pub struct InteractionCommandData {
+    pub name: String,
+    #[serde(default)]
+    pub options: Vec<InteractionOption>,
+}
+
+#[derive(Deserialize)]
+pub struct InteractionOption {
+    pub name: String,
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub options: Vec<InteractionOption>,
+}
+
+#[derive(Deserialize)]
+pub struct InteractionMember {
+    pub user: InteractionUser,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct InteractionUser {
+    pub id: String,
+}
+
+#[post("/api/discord/interactions")]
+pub async fn interactions(req: HttpRequest, body: web::Bytes, app_data: web::Data<AppData>) -> impl Responder {
+    let signature: &str = req.headers().get("X-Signature-Ed25519").and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let timestamp: &str = req.headers().get("X-Signature-Timestamp").and_then(|value| value.to_str().ok()).unwrap_or_default();
+
+    if !verify_signature(&CONFIG.discord_public_key, signature, timestamp, &body) {
+        return HttpResponse::Unauthorized().body("invalid request signature");
+    }
+
+    let payload: InteractionPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(_) => return HttpResponse::BadRequest().body(""),
+    };
+
+    if payload.kind == PING {
+        return HttpResponse::Ok().json(CreateInteractionResponse::Pong);
+    }
+    if payload.kind != APPLICATION_COMMAND {
+        return HttpResponse::BadRequest().body("");
+    }
+
+    // Discord shows a failed interaction for anything but a response, the user gets a message instead
+    match load_accounts(&app_data.dbclient).await {
+        Ok((vec_front_accounts, whitelist)) => HttpResponse::Ok().json(answer_command(&payload, &vec_front_accounts, &whitelist)),
+        Err(err) => {
+            error!("Can't answer interaction: {}", err);
+            HttpResponse::Ok().json(message_response("L'intranet est momentanément indisponible, réessayez plus tard.".to_string()))
+        }
+    }
+}
+
+async fn load_accounts(dbclient: &mongodb::Client) -> mongodb::error::Result<(Vec<FrontAccount>, Vec<String>)> {
+    let whitelist: Vec<String> = meta_collection(dbclient).find_one(Document::new()).await?.unwrap_or_default().whitelist;
+    let accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+    Ok((accounts, whitelist))
+}
+
+pub fn verify_signature(public_key: &str, signature: &str, timestamp: &str, body: &[u8]) -> bool {
+    let Some(key) = hex::decode(public_key).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+        return false;
+    };
+    match Verifier::try_new(key) {
+        Ok(verifier) => verifier.verify(signature, timestamp, body).is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub fn answer_command(payload: &InteractionPayload, accounts: &Vec<FrontAccount>, whitelist: &Vec<String>) -> CreateInteractionResponse {
+    let caller_id: &str = payload.member.as_ref().map(|member| member.user.id.as_str())
+                                 .or(payload.user.as_ref().map(|user| user.id.as_str()))
+                                 .unwrap_or_default();
+    let caller_roles: Vec<String> = payload.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+
+    let content: String = match payload.data.as_ref() {
+        Some(data) => {
+            let sub_command: Option<&InteractionOption> = data.options.first();
+            match (data.name.as_str(), sub_command.map(|option| option.name.as_str())) {
+                ("fiche", Some("voir")) => {
+                    let target_id: Option<&str> = sub_command.unwrap().options.iter()
+                                                             .find(|option| option.name == "utilisateur")
+                                                             .and_then(|option| option.value.as_ref())
+                                                             .and_then(|value| value.as_str());
+                    match target_id.and_then(|id| accounts.iter().find(|account| account.discord_user.id == id)) {
+                        Some(account) => {
+                            let is_caller_staff: bool = has_staff_role(caller_id, &caller_roles, whitelist);
+                            let fiches: Vec<FicheRP> = account.fiches.iter()
+                                                              .filter(|fiche| !is_hidden_draft(fiche, &account.discord_user.id, caller_id) && !is_hidden_archive(fiche, &account.discord_user.id, caller_id, is_caller_staff))
+                                                              .cloned()
+                                                              .collect();
+                            format!("**Fiches de {} :**\n{}", account.discord_user.global_name, format_fiches(&fiches))
+                        }
+                        None => "Aucun compte intranet pour cet utilisateur.".to_string(),
+                    }
+                }
+                ("fiche", Some("statut")) => {
+                    match accounts.iter().find(|account| account.discord_user.id == caller_id) {
+                        Some(account) => format!("**Vos fiches :**\n{}", format_fiches(&account.fiches)),
+                        None => "Vous n'avez pas encore de compte sur l'intranet.".to_string(),
+                    }
+                }
+                ("fiches", Some("en-attente")) => {
+                    if has_staff_role(caller_id, &caller_roles, whitelist) {
+                        let mut waiting: Vec<(&FrontAccount, &FicheRP)> = accounts.iter()
+                                                                                  .flat_map(|account| account.fiches.iter().map(move |fiche| (account, fiche)))
+                                                                                  .filter(|(_, fiche)| fiche.state == FicheState::Waiting)
+                                                                                  .collect();
+                        waiting.sort_by_key(|(_, fiche)| fiche.submission_date);
+
+                        if waiting.is_empty() {
+                            "Aucune fiche en attente.".to_string()
+                        } else {
+                            let lines: Vec<String> = waiting.iter()
+                                                            .map(|(account, fiche)| format!("- **{}** ({}) de {} — <t:{}:R>", fiche.name, fiche.job, account.discord_user.global_name, fiche.submission_date))
+                                                            .collect();
+                            format!("**{} fiche(s) en attente :**\n{}", waiting.len(), lines.join("\n"))
+                        }
+                    } else {
+                        "Cette commande est réservée au staff.".to_string()
+                    }
+                }
+                _ => "Commande inconnue.".to_string(),
+            }
+        }
+        None => "Commande inconnue.".to_string(),
+    };
+
+    message_response(content)
+}
+
+fn message_response(content: String) -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(truncate_content(content)).ephemeral(true))
+}
+
+pub async fn register_commands() {
+    actix_rt::spawn(async move {
+        let Ok(guild_id) = CONFIG.guild_id.parse::<u64>() else {
+            error!("Can't register slash commands, invalid guild id {}", CONFIG.guild_id);
+            return;
+        };
+        let http: Http = Http::new(&CONFIG.bot_token);
+
+        let commands: Vec<CreateCommand> = vec![
+            CreateCommand::new("fiche").description("Consulter les fiches RP")
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "voir", "Voir les fiches d'un joueur")
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "utilisateur", "Joueur").required(true)))
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "statut", "Statut de vos fiches")),
+            CreateCommand::new("fiches").description("Gestion des fiches RP")
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "en-attente", "Fiches en attente de traitement")),
+        ];
+
+        match GuildId::new(guild_id).set_commands(&http, commands).await {
+            Ok(commands) => info!("Registered {} slash commands", commands.len()),
+            Err(err) => error!("Can't register slash commands: {}", err),
+        }
+    });
+}
+
+fn format_fiches(fiches: &Vec<FicheRP>) -> String {
+    if fiches.is_empty() {
+        return "Aucune fiche.".to_string();
+    }
+    fiches.iter()
+          .map(|fiche| format!("- **{}** ({}) : {} — <t:{}:d>", fiche.name, fiche.job, fiche.state.get_text(), fiche.submission_date))
+          .collect::<Vec<String>>()
+          .join("\n")
+}
+
+fn truncate_content(content: String) -> String {
+    if content.chars().count() <= MAX_CONTENT_LENGTH {
+        return content;
+    }
+    let mut truncated: String = content.chars().take(MAX_CONTENT_LENGTH).collect();
+    truncated.push_str("\n…");
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use serde_json::json;
+
+    use shared::discord::User;
+    use shared::fixtures;
+
+    use super::*;
+
+    const SCENARIST_ROLE: &str = "1143509784591605841";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn sign(body: &[u8], timestamp: &str) -> String {
+        hex::encode(signing_key().sign(&[timestamp.as_bytes(), body].concat()).to_bytes())
+    }
+
+    fn accounts() -> Vec<FrontAccount> {
+        vec![FrontAccount {
+            discord_user: User {
+                id: "42".to_string(),
+                global_name: "Roger".to_string(),
+                avatar: "".to_string(),
+            },
+            discord_roles: vec![],
+            fiches: vec![FicheRP {
+                id: "fiche".to_string(),
+                name: "Roger Dupont".to_string(),
+                submission_date: 1720000000,
+                ..fixtures::fiche(FicheState::Waiting)
+            }, FicheRP {
+                id: "draft".to_string(),
+                name: "Roger Brouillon".to_string(),
+                ..fixtures::fiche(FicheState::Draft)
+            }],
+            creation_date: 0,
+            banned: false,
+            needs_relogin: false,
+        }]
+    }
+
+    fn content_of(response: CreateInteractionResponse) -> String {
+        serde_json::to_value(response).unwrap()["data"]["content"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn signed_ping() {
+        let public_key: String = hex::encode(signing_key().verifying_key().to_bytes());
+        let body: Vec<u8> = serde_json::to_vec(&json!({"type": 1})).unwrap();
+        let signature: String = sign(&body, "1720000000");
+
+        assert!(verify_signature(&public_key, &signature, "1720000000", &body));
+        assert!(!verify_signature(&public_key, &signature, "1720000001", &body));
+        assert!(!verify_signature(&public_key, &signature, "1720000000", b"{\"type\":2}"));
+        assert!(!verify_signature("not a key", &signature, "1720000000", &body));
+
+        let payload: InteractionPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload.kind, PING);
+        assert_eq!(serde_json::to_value(CreateInteractionResponse::Pong).unwrap()["type"], 1);
+    }
+
+    #[test]
+    fn fiche_voir() {
+        let payload: InteractionPayload = serde_json::from_value(json!({
+            "type": 2,
+            "data": {"name": "fiche", "options": [{"name": "voir", "type": 1, "options": [{"name": "utilisateur", "type": 6, "value": "42"}]}]},
+            "member": {"user": {"id": "7"}, "roles": []}
+        })).unwrap();
+
+        let content: String = content_of(answer_command(&payload, &accounts(), &vec!["7".to_string()]));
+        assert!(content.contains("Roger Dupont"));
+        assert!(content.contains(FicheState::Waiting.get_text()));
+        // Drafts stay private, even for the staff
+        assert!(!content.contains("Roger Brouillon"));
+
+        let payload: InteractionPayload = serde_json::from_value(json!({
+            "type": 2,
+            "data": {"name": "fiche", "options": [{"name": "statut", "type": 1}]},
+            "member": {"user": {"id": "42"}, "roles": []}
+        })).unwrap();
+        assert!(content_of(answer_command(&payload, &accounts(), &vec![])).contains("Roger Brouillon"));
+    }
+
+    #[test]
+    fn fiches_en_attente_requires_staff() {
+        let payload = |roles: Vec<&str>| -> InteractionPayload {
+            serde_json::from_value(json!({
+                "type": 2,
+                "data": {"name": "fiches", "options": [{"name": "en-attente", "type": 1}]},
+                "member": {"user": {"id": "7"}, "roles": roles}
+            })).unwrap()
+        };
+
+        assert!(content_of(answer_command(&payload(vec![]), &accounts(), &vec![])).contains("réservée au staff"));
+        assert!(content_of(answer_command(&payload(vec![SCENARIST_ROLE]), &accounts(), &vec![])).contains("Roger Dupont"));
+        assert!(content_of(answer_command(&payload(vec![]), &accounts(), &vec!["7".to_string()])).contains("1 fiche(s) en attente"));
+    }
+}
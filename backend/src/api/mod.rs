@@ -1,2 +1,6 @@
 pub mod oauth2;
-pub mod front;
\ No newline at end of file
+pub mod front;
+pub mod interactions;
+pub mod health;
+pub mod avatars;
+pub mod images;
\ No newline at end of file
@@ -19,10 +19,12 @@ use serde_json::Value;
 use uuid::Uuid;
 
 use shared::discord::{DiscordAuthorizationInformation, GuildMember};
-use shared::user::Account;
+use shared::user::{Account, AuthSession};
 
-use crate::utils::auth_utils::{is_auth_valid, is_user_registered, update_account_discord, update_auth_id, update_token};
+use crate::utils::auth_utils::{is_auth_valid, is_user_registered, new_session, update_account_discord, update_token};
 use crate::utils::config_utils::Oauth2Client;
+use crate::utils::crypto_utils::TOKEN_CIPHER;
+use crate::utils::db_utils::{add_session, find_session_account};
 use crate::{AppData, CONFIG};
 
 #[derive(Deserialize, Debug, Clone)]
@@ -36,10 +38,16 @@ pub async fn auth(req: HttpRequest, session: Session, app_data: web::Data<AppDat
 
     if let Some(cookie) = req.cookie("auth_id") {
         if is_auth_valid(cookie.value(), app_data.dbclient.clone()).await {
-            update_account_discord(cookie.value(), app_data.dbclient.clone(), &app_data.reqwest_client).await;
-            return actix_web::HttpResponse::Found()
-                .append_header((header::LOCATION, oauth2_info.redirect_url_egui.clone()))
-                .finish();
+            // An account flagged for a new login goes through Discord again to get a fresh token
+            let account: Option<Account> = find_session_account(&app_data.dbclient, cookie.value()).await.ok().flatten().filter(|account| !account.needs_relogin);
+            if let Some(account) = account {
+                if let Err(err) = update_account_discord(&account.discord_user.id, app_data.dbclient.clone(), &app_data.reqwest_client).await {
+                    error!("Discord update failed: {}", err);
+                }
+                return actix_web::HttpResponse::Found()
+                    .append_header((header::LOCATION, oauth2_info.redirect_url_egui.clone()))
+                    .finish();
+            }
         }
     }
 
@@ -78,7 +86,7 @@ pub async fn auth(req: HttpRequest, session: Session, app_data: web::Data<AppDat
 }
 
 #[get("/api/oauth2/callback")]
-pub async fn callback(callback_data: web::Query<OAuth2Callback>, session: Session, app_data: web::Data<AppData>) -> impl Responder {
+pub async fn callback(req: HttpRequest, callback_data: web::Query<OAuth2Callback>, session: Session, app_data: web::Data<AppData>) -> impl Responder {
     let mut client_id_value: String = String::new();
     let mut pkce_verifier_value: String = String::new();
     let oauth2_info: &Oauth2Client = &CONFIG.oauth2client.clone();
@@ -106,21 +114,29 @@ pub async fn callback(callback_data: web::Query<OAuth2Callback>, session: Sessio
             let accounts: Collection<Account> = app_data.dbclient.database("visualis-website").collection("account");
             let authorization_information: DiscordAuthorizationInformation = discord_autho_response.json().await.expect("Can't parse authorization_information json");
 
+            let time_now: u64 = SystemTime::now()
+                .duration_since(UNIX_EPOCH).expect("invalid time")
+                .as_secs();
+            // Each device logging in gets its own session
+            let user_agent: &str = req.headers().get(header::USER_AGENT).and_then(|user_agent| user_agent.to_str().ok()).unwrap_or_default();
+            let auth_session: AuthSession = new_session(user_agent, time_now);
+
             if is_user_registered(&authorization_information.user.id, app_data.dbclient.clone()).await {
                 info!("User already registered, updating token...");
 
-                let auth_id: String = Uuid::now_v7().to_string();
-
-                update_auth_id(&authorization_information.user.id, &auth_id, app_data.dbclient.clone()).await;
+                if let Err(err) = add_session(&app_data.dbclient, &authorization_information.user.id, &auth_session, time_now).await {
+                    error!("Can't add a session for {}: {}", authorization_information.user.id, err);
+                    return actix_web::HttpResponse::InternalServerError().body("");
+                }
 
-                let mut auth_cookie: Cookie = Cookie::new("auth_id", &auth_id);
+                let mut auth_cookie: Cookie = Cookie::new("auth_id", &auth_session.auth_id);
                 auth_cookie.set_secure(true);
                 auth_cookie.set_domain(&CONFIG.domain);
                 auth_cookie.set_path("/");
                 auth_cookie.set_same_site(SameSite::Strict);
                 auth_cookie.set_expires(OffsetDateTime::now_utc() + Duration::weeks(4));
 
-                update_token(&auth_id, &authorization_information.user.id, token_response.clone(), app_data.dbclient.clone(), &app_data.reqwest_client).await;
+                update_token(&authorization_information.user.id, token_response.clone(), app_data.dbclient.clone(), &app_data.reqwest_client).await;
                 info!("Token updated for {}({})",authorization_information.user.global_name.clone(), authorization_information.user.id.clone());
 
                 return actix_web::HttpResponse::Found()
@@ -129,7 +145,7 @@ pub async fn callback(callback_data: web::Query<OAuth2Callback>, session: Sessio
             }
 
             let discord_guild_member_response: Response = app_data.reqwest_client
-                .get("https://discord.com/api/users/@me/guilds/1031296063056924714/member")
+                .get(format!("https://discord.com/api/users/@me/guilds/{}/member", CONFIG.guild_id))
                 .bearer_auth(token_response.clone().access_token().secret())
                 .send()
                 .await.expect("Can't get token_response");
@@ -147,21 +163,21 @@ pub async fn callback(callback_data: web::Query<OAuth2Callback>, session: Sessio
 
             let guild_member: GuildMember = serde_json::from_value(member_json).unwrap();
 
-            let time_now: u64 = SystemTime::now()
-                .duration_since(UNIX_EPOCH).expect("invalid time")
-                .as_secs();
-
-            let auth_id: String = Uuid::now_v7().to_string();
+            let auth_id: String = auth_session.auth_id.clone();
 
             let authenticated_user = Account {
                 discord_user: authorization_information.user,
                 discord_roles: guild_member.roles,
-                auth_id: auth_id.clone(),
-                token: token_response.clone(),
+                sessions: vec![auth_session],
+                token: TOKEN_CIPHER.encrypt_token(&token_response),
                 last_renewal: time_now,
                 fiches: vec![],
                 creation_date: time_now,
                 banned: false,
+                needs_relogin: false,
+                terms_acceptances: vec![],
+                sync_failures: 0,
+                next_sync: 0,
             };
             accounts.insert_one(authenticated_user).await.expect("Can't insert new user");
 
@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use log::info;
+use mongodb::bson::doc;
+use serde_json::Value;
+
+use shared::fiche_rp::{FicheRP, FicheState};
+use shared::user::Account;
+
+use crate::utils::archive_utils::{export_archive, import_archive, Archive, ImportReport};
+use crate::utils::avatar_utils::{sync_avatars, AvatarSyncReport};
+use crate::utils::config_utils::Configuration;
+use crate::utils::db_utils::{account_collection, find_fiche_owner, migrate, reencrypt_tokens, revoke_sessions, set_account_banned, set_fiche_state, whitelist_add, whitelist_remove};
+use crate::utils::discord_utils::{add_fiche_roles, compute_role_drift, remove_fiche_roles};
+use crate::utils::privacy_utils::{delete_account, export_personal_data, PersonalData};
+use crate::{init_mongo, CONFIG};
+
+/// Actor recorded in the audit log for the commands run on the server
+const CLI_ACTOR: &str = "cli";
+
+#[derive(Parser)]
+#[command(version, about = "Backend de l'intranet Project Visualis")]
+pub struct Cli {
+    /// Configuration file, every key can also be set with a VISUALIS__<KEY> environment variable
+    #[arg(long, global = true, env = "VISUALIS_CONFIG", default_value = "data/config.json")]
+    pub config: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the HTTP server (default)
+    Serve,
+    /// Apply pending database migrations
+    Migrate,
+    /// Load and validate the configuration
+    CheckConfig,
+    /// Write an example configuration file generated from the configuration struct (stdout when no path is given)
+    ConfigExample { path: Option<PathBuf> },
+    /// Print a new random key for `token_keys`
+    GenerateTokenKey,
+    /// Re-encrypt every stored OAuth token with the first key of `token_keys`, run it after putting a new key first
+    RotateTokenKeys,
+    /// Manage the platform admin whitelist
+    Whitelist {
+        #[command(subcommand)]
+        action: WhitelistAction,
+    },
+    /// Manage fiches
+    Fiche {
+        #[command(subcommand)]
+        action: FicheAction,
+    },
+    /// Manage accounts
+    Account {
+        #[command(subcommand)]
+        action: AccountAction,
+    },
+    /// Report differences between accepted fiches and the Discord roles members hold
+    RoleDrift,
+    /// Write a versioned JSON archive of every account, fiche, notification, audit entry, uploaded image and the website meta (OAuth tokens excluded)
+    Export { path: PathBuf },
+    /// Fetch the avatars missing from the cache and remove the ones of deleted accounts
+    Avatars {
+        /// Fetch every avatar again, even the up to date ones
+        #[arg(long)]
+        force: bool,
+    },
+    /// Restore an archive written by `export`
+    Import {
+        path: PathBuf,
+        /// Only check the archive and print what would be restored
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WhitelistAction {
+    Add { discord_id: String },
+    Remove { discord_id: String },
+}
+
+#[derive(Subcommand)]
+pub enum FicheAction {
+    /// Force the state of a fiche (Waiting, RequestModification, StaffValidated, Accepted, Refused)
+    SetState {
+        fiche_id: String,
+        #[arg(value_parser = parse_fiche_state)]
+        state: FicheState,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AccountAction {
+    Ban { discord_id: String },
+    Unban { discord_id: String },
+    /// Log the account out of every device
+    RevokeSessions { discord_id: String },
+    /// Write everything stored about the account to a JSON file, for data access requests received by mail
+    ExportData { discord_id: String, path: PathBuf },
+    /// Delete the account, its review messages on other fiches are anonymised
+    Delete { discord_id: String },
+}
+
+pub async fn run_command(command: Command) -> Result<()> {
+    if let Command::CheckConfig = command {
+        info!("Configuration is valid: listening on {}:{}, domain {}, {} role mapping(s)", CONFIG.address, CONFIG.port, CONFIG.domain, CONFIG.role_mappings.len());
+        return Ok(());
+    }
+
+    let dbclient: mongodb::Client = init_mongo().await;
+
+    match command {
+        Command::Serve | Command::CheckConfig | Command::ConfigExample { .. } | Command::GenerateTokenKey => unreachable!("handled by main"),
+        Command::Migrate => {
+            let version: u32 = migrate(&dbclient).await?;
+            info!("Database is at schema version {}", version);
+        }
+        Command::Whitelist { action } => match action {
+            WhitelistAction::Add { discord_id } => {
+                whitelist_add(&dbclient, &discord_id).await?;
+                info!("{} added to the whitelist", discord_id);
+            }
+            WhitelistAction::Remove { discord_id } => {
+                whitelist_remove(&dbclient, &discord_id).await?;
+                info!("{} removed from the whitelist", discord_id);
+            }
+        },
+        Command::Fiche { action } => match action {
+            FicheAction::SetState { fiche_id, state } => {
+                let Some(owner_account) = find_fiche_owner(&dbclient, &fiche_id).await? else {
+                    bail!("No fiche with id {}", fiche_id);
+                };
+                let ficherp: FicheRP = owner_account.fiches.iter().find(|fiche| fiche.id == fiche_id).unwrap().clone();
+
+                set_fiche_state(&dbclient, &fiche_id, &state).await?;
+                match state {
+                    FicheState::Accepted => add_fiche_roles(&owner_account.discord_user.id, &ficherp).await,
+                    FicheState::Refused => remove_fiche_roles(&owner_account.discord_user.id, &ficherp, &owner_account.fiches).await,
+                    _ => {}
+                }
+                info!("Fiche {} of {} is now {}", fiche_id, owner_account.discord_user.global_name, state.get_text());
+            }
+        },
+        Command::Account { action } => match action {
+            AccountAction::Ban { discord_id } => set_banned(&dbclient, &discord_id, true).await?,
+            AccountAction::Unban { discord_id } => set_banned(&dbclient, &discord_id, false).await?,
+            AccountAction::RevokeSessions { discord_id } => {
+                if !revoke_sessions(&dbclient, &discord_id).await? {
+                    bail!("No account with discord id {}", discord_id);
+                }
+                info!("Every session of {} revoked", discord_id);
+            }
+            AccountAction::ExportData { discord_id, path } => {
+                let Some(account) = account_collection::<Account>(&dbclient).find_one(doc! {"discord_user.id": &discord_id}).await? else {
+                    bail!("No account with discord id {}", discord_id);
+                };
+                let personal_data: PersonalData = export_personal_data(&dbclient, &account, CLI_ACTOR).await?;
+                fs::write(&path, serde_json::to_string_pretty(&personal_data)?)?;
+                info!("Data of {} exported to {}", discord_id, path.display());
+            }
+            AccountAction::Delete { discord_id } => {
+                let Some(report) = delete_account(&dbclient, &discord_id, CLI_ACTOR).await? else {
+                    bail!("No account with discord id {}", discord_id);
+                };
+                info!("Account {} deleted: {} fiche(s), {} message(s) anonymised, {} notification(s)", discord_id, report.fiches, report.anonymised_messages, report.deleted_notifications);
+            }
+        },
+        Command::RoleDrift => {
+            let drifts = compute_role_drift(dbclient.clone()).await?;
+            drifts.iter().for_each(|drift| {
+                println!("{} ({}) missing: {:?} unexpected: {:?}", drift.global_name, drift.discord_id, drift.missing, drift.unexpected);
+            });
+            info!("{} account(s) with role drift", drifts.len());
+        }
+        Command::Export { path } => {
+            let archive: Archive = export_archive(&dbclient, SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()).await?;
+            fs::write(&path, serde_json::to_string_pretty(&archive)?)?;
+            info!("Exported {} account(s), {} notification(s), {} audit entrie(s) and {} image(s) to {}",
+                archive.accounts.len(), archive.notifications.len(), archive.audit.len(), archive.images.len(), path.display());
+        }
+        Command::RotateTokenKeys => {
+            let rewritten: usize = reencrypt_tokens(&dbclient).await?;
+            info!("{} OAuth token(s) re-encrypted, the previous keys can be removed once no token uses them anymore", rewritten);
+        }
+        Command::Avatars { force } => {
+            let report: AvatarSyncReport = sync_avatars(&dbclient, &reqwest::Client::new(), force).await?;
+            info!("{} avatar(s) fetched, {} failed, {} removed", report.fetched, report.failed, report.removed);
+        }
+        Command::Import { path, dry_run } => {
+            let archive: Archive = Archive::parse(&fs::read_to_string(&path)?)?;
+            let report: ImportReport = import_archive(&dbclient, &archive, dry_run).await?;
+
+            info!("{}{} account(s) created, {} updated, {} fiche(s), {} message(s), {} version(s), {} notification(s), {} audit entrie(s), {} image(s) from {}",
+                if dry_run { "[dry run] " } else { "" }, report.created_accounts, report.updated_accounts, report.fiches, report.messages, report.versions,
+                report.notifications, report.audit_entries, report.images, path.display());
+        }
+    }
+    Ok(())
+}
+
+async fn set_banned(dbclient: &mongodb::Client, discord_id: &String, banned: bool) -> Result<()> {
+    if !set_account_banned(dbclient, discord_id, banned).await? {
+        bail!("No account with discord id {}", discord_id);
+    }
+    info!("Account {} banned: {}", discord_id, banned);
+    Ok(())
+}
+
+pub fn write_config_example(path: Option<PathBuf>) -> Result<()> {
+    let example: String = serde_json::to_string_pretty(&Configuration::example())?;
+    match path {
+        Some(path) => fs::write(path, example + "\n")?,
+        None => println!("{}", example),
+    }
+    Ok(())
+}
+
+fn parse_fiche_state(state: &str) -> std::result::Result<FicheState, String> {
+    serde_json::from_value(Value::String(state.to_string())).map_err(|_| format!("unknown fiche state {}", state))
+}
@@ -1,6 +1,6 @@
 use std::fs::create_dir_all;
 use std::thread::sleep;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
 use actix_cors::Cors;
 use actix_files::Files;
@@ -8,36 +8,49 @@ use actix_session::config::CookieContentSecurity;
 use actix_session::storage::CookieSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
+use actix_web::dev::Service;
 use actix_web::http::header::HeaderName;
 use actix_web::middleware::{Compress, Logger};
-use actix_web::rt::time;
 use actix_web::web::Data;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use anyhow::Result;
-use config::{Config, File};
+use clap::Parser;
 use dashmap::DashMap;
 use env_logger::Env;
 use lazy_static::lazy_static;
-use log::{error, info, warn};
-use mongodb::bson::{bson, doc, Document};
-use mongodb::{Collection, Cursor};
+use once_cell::sync::OnceCell;
+use log::{error, info};
+use mongodb::bson::{bson, doc};
 use oauth2::basic::{BasicClient, BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse, BasicTokenResponse, BasicTokenType};
-use oauth2::{AuthUrl, Client, ClientId, ClientSecret, RedirectUrl, StandardRevocableToken, TokenResponse, TokenUrl};
-use serenity::futures::{StreamExt, TryStreamExt};
-use uuid::Uuid;
+use oauth2::{AuthUrl, Client, ClientId, ClientSecret, RedirectUrl, StandardRevocableToken, TokenUrl};
+use serenity::futures::{FutureExt, StreamExt};
 
-use shared::user::Account;
-
-use crate::api::front::{retrieve_accounts, retrieve_auth_account, retrieve_whitelist, submit_comment, submit_ficherp, submit_ficherp_admin, submit_ficherp_modif};
+use crate::api::front::{export_fiche, retrieve_accounts, retrieve_approval_quorum, retrieve_auth_account, retrieve_events, retrieve_job_statuses, retrieve_legal_versions, retrieve_markdown_rules, retrieve_notifications, retrieve_personal_data, retrieve_quota_overview, retrieve_review_queue, retrieve_role_drift, retrieve_search, retrieve_sessions, retrieve_staff_stats, retrieve_whitelist, submit_account_deletion, submit_account_sessions_revoke, submit_character_status, submit_comment, submit_comment_deletion, submit_comment_edit, submit_draft, submit_draft_deletion, submit_fiche_assign, submit_fiche_claim, submit_fiche_unclaim, submit_fiche_vote, submit_ficherp, submit_ficherp_admin, submit_ficherp_modif, submit_image, submit_image_deletion, submit_logout, submit_notifications_read, submit_session_revoke, submit_terms_acceptance};
+use crate::api::avatars::avatar;
+use crate::api::images::fiche_image;
+use crate::api::health::{healthz, metrics, readyz};
+use crate::api::interactions::{interactions, register_commands};
 use crate::api::oauth2::{auth, callback};
-use crate::utils::auth_utils::{renew_token, update_account_discord, update_auth_id};
+use crate::cli::{run_command, write_config_example, Cli, Command};
+use crate::utils::auth_utils::sync_accounts;
+use crate::utils::avatar_utils::{sync_avatars, AvatarSyncReport, AVATAR_DIR};
 use crate::utils::config_utils::{Configuration, Oauth2Client};
+use crate::utils::crypto_utils::generate_token_key;
+use crate::utils::db_utils::migrate;
+use crate::utils::image_utils::IMAGE_DIR;
+use crate::utils::metrics_utils::{record_request, refresh_fiche_gauges, RATE_LIMIT_REJECTIONS};
+use crate::utils::scheduler_utils::Scheduler;
 
 mod api;
+mod cli;
 mod utils;
 
+const MAX_JSON_PAYLOAD: usize = 1024 * 1024;
+
+static LOADED_CONFIG: OnceCell<Configuration> = OnceCell::new();
+
 lazy_static! {
-     pub static ref CONFIG: Configuration = Config::builder().add_source(File::with_name("data/config.json")).build().expect("[ERROR] config.json not found or invalid.").try_deserialize::<Configuration>().unwrap();
+     pub static ref CONFIG: &'static Configuration = LOADED_CONFIG.get().expect("[ERROR] configuration used before being loaded");
 }
 
 struct AppData {
@@ -45,6 +58,7 @@ struct AppData {
     dbclient: mongodb::Client,
     reqwest_client: reqwest::Client,
     rate_limit_map: DashMap<String, RateLimitData>,
+    scheduler: Scheduler,
 }
 
 pub struct RateLimitData {
@@ -61,25 +75,75 @@ async fn main() -> Result<()> {
 
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
-    info!("Starting backend version {} on branch {} and compiled at {}", GIT_TAG.unwrap_or_else(|| "unknown"), GIT_BRANCH.unwrap_or_else(|| "unknown"), BUILD_TIMESTAMP.unwrap_or_else(|| "unknown"));
+    let cli: Cli = Cli::parse();
+    let command: Command = cli.command.unwrap_or(Command::Serve);
+
+    if let Command::ConfigExample { path } = command {
+        return write_config_example(path);
+    }
+    if let Command::GenerateTokenKey = command {
+        println!("{}", generate_token_key());
+        return Ok(());
+    }
+
+    match Configuration::load(&cli.config) {
+        Ok(configuration) => {
+            let _ = LOADED_CONFIG.set(configuration);
+        }
+        Err(errors) => {
+            error!("Invalid configuration ({}), {} problem(s) found:", cli.config.display(), errors.len());
+            errors.iter().for_each(|err| error!("  - {}", err));
+            std::process::exit(1);
+        }
+    }
+
+    match command {
+        Command::Serve => {
+            info!("Starting backend version {} on branch {} and compiled at {}", GIT_TAG.unwrap_or_else(|| "unknown"), GIT_BRANCH.unwrap_or_else(|| "unknown"), BUILD_TIMESTAMP.unwrap_or_else(|| "unknown"));
+            serve().await
+        }
+        command => run_command(command).await,
+    }
+}
 
-    match create_dir_all("data/cache/avatars") {
+async fn serve() -> Result<()> {
+    match create_dir_all(AVATAR_DIR) {
         Ok(_) => info!("Created cache folder for avatars"),
         Err(err) => error!("Can't create cache folder for avatars :{}",err)
     }
+    if let Err(err) = create_dir_all(IMAGE_DIR) {
+        error!("Can't create folder for fiche images: {}", err)
+    }
 
     let dbclient: mongodb::Client = init_mongo().await;
+    migrate(&dbclient).await?;
 
     let app_data = Data::new(AppData {
         client_map: DashMap::new(),
         dbclient: dbclient.clone(),
         reqwest_client: reqwest::Client::new(),
         rate_limit_map: Default::default(),
+        scheduler: Scheduler::default(),
     });
 
-    update_token_thread(dbclient.clone(), app_data.reqwest_client.clone()).await;
+    schedule_jobs(&app_data.scheduler, dbclient.clone(), app_data.reqwest_client.clone());
+
+    if !CONFIG.discord_public_key.is_empty() {
+        register_commands().await;
+    }
+
     HttpServer::new(move || {
         App::new()
+            .wrap_fn(|req, srv| {
+                let start: Instant = Instant::now();
+                srv.call(req).map(move |res| {
+                    if let Ok(res) = &res {
+                        let route: String = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                        record_request(res.request().method().as_str(), &route, res.status().as_u16(), start.elapsed());
+                    }
+                    res
+                })
+            })
             .wrap(Logger::default())  // Global middlewares
             .wrap(Cors::default()
                 .allowed_origin("http://localhost:8080")
@@ -93,8 +157,39 @@ async fn main() -> Result<()> {
             .service(submit_ficherp)
             .service(submit_ficherp_admin)
             .service(submit_comment)
+            .service(submit_comment_edit)
+            .service(submit_comment_deletion)
             .service(submit_ficherp_modif)
             .service(retrieve_whitelist)
+            .service(retrieve_role_drift)
+            .service(retrieve_job_statuses)
+            .service(retrieve_staff_stats)
+            .service(submit_fiche_claim)
+            .service(submit_fiche_unclaim)
+            .service(submit_fiche_assign)
+            .service(retrieve_review_queue)
+            .service(submit_fiche_vote)
+            .service(retrieve_approval_quorum)
+            .service(retrieve_markdown_rules)
+            .service(retrieve_legal_versions)
+            .service(submit_terms_acceptance)
+            .service(submit_draft)
+            .service(submit_draft_deletion)
+            .service(submit_character_status)
+            .service(retrieve_quota_overview)
+            .service(retrieve_events)
+            .service(retrieve_search)
+            .service(retrieve_notifications)
+            .service(submit_notifications_read)
+            .service(submit_logout)
+            .service(retrieve_sessions)
+            .service(submit_session_revoke)
+            .service(submit_account_sessions_revoke)
+            .service(retrieve_personal_data)
+            .service(submit_account_deletion)
+            .service(submit_image)
+            .service(submit_image_deletion)
+            .service(export_fiche)
             .wrap({
                 SessionMiddleware::builder(CookieSessionStore::default(), Key::from(&[0; 64]))
                     .cookie_secure(false)
@@ -104,9 +199,18 @@ async fn main() -> Result<()> {
             .wrap(Compress::default())
             .service(auth)
             .service(callback)
-            .service(Files::new("/api/cache/avatars", "data/cache/avatars").index_file("index.html"))
+            .service(healthz)
+            .service(readyz)
+            .service(metrics)
+            .service(interactions)
+            .service(avatar)
+            .service(fiche_image)
             .service(Files::new("/", "dist").index_file("index.html"))
             .app_data(app_data.clone())
+            // Fiche modifications carry every previous version, the markdown limits are checked per field
+            .app_data(web::JsonConfig::default().limit(MAX_JSON_PAYLOAD))
+            // Raw bodies are only used by the image uploads
+            .app_data(web::PayloadConfig::new(CONFIG.images.max_upload_bytes))
     })
         .bind((CONFIG.address.clone(), CONFIG.port))
         .map_err(anyhow::Error::msg)?
@@ -120,38 +224,30 @@ pub async fn init_mongo() -> mongodb::Client {
     mongodb::Client::with_uri_str(uri).await.expect("[ERROR] Can't connect to mongodb server!")
 }
 
-async fn update_token_thread(dbclient: mongodb::Client, http_client: reqwest::Client) {
-    actix_rt::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(3600)); // check every hour (3600s)
-        let oauth2_info: &Oauth2Client = &CONFIG.oauth2client.clone();
-        //IMPORTANT: The urls should NOT have "/" appended to the end, the lib will crash if so
-        let oauth_client =
-            BasicClient::new(
-                ClientId::new(oauth2_info.client_id.clone()),
-                Some(ClientSecret::new(oauth2_info.client_secret.clone())),
-                AuthUrl::new(oauth2_info.auth_url.clone()).unwrap(),
-                Some(TokenUrl::new(oauth2_info.token_url.clone()).unwrap()))
-                // Set the URL the user will be redirected to after the authorization process.
-                .set_redirect_uri(RedirectUrl::new(oauth2_info.redirect_url.clone()).unwrap());
-        loop {
-            interval.tick().await;
-
-            let account_collection: Collection<Account> = dbclient.clone().database("visualis-website").collection("account");
-            let mut accounts_cursor: Cursor<Account> = account_collection.find(Document::new()).await.expect("Can't get all account");
-
-            while let Some(mut account) = accounts_cursor.try_next().await.expect("Can't iterate over collection") {
-                let time_passed_since_renew: i64 = (account.last_renewal + account.token.expires_in().unwrap().as_secs()) as i64 - (SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64);
-
-                if time_passed_since_renew <= 0 {
-                    warn!("Can't renew token for {}({}) since it has expired", account.discord_user.global_name, account.discord_user.id);
-                    update_auth_id(&account.discord_user.id, &Uuid::now_v7().to_string(), dbclient.clone()).await;
-                } else if time_passed_since_renew <= 86400 { //renew when one day or less is left
-                    info!("Renewing token for {}({})", account.discord_user.global_name, account.discord_user.id);
-                    renew_token(account.token.access_token().secret(), account.token.refresh_token().unwrap(), dbclient.clone(), oauth_client.clone()).await;
-                } else {
-                    update_account_discord(&account.auth_id, dbclient.clone(), &http_client).await;
-                }
-            }
+/// Background jobs: token renewal with the Discord sync every hour, avatar cache once a day, fiche gauges every minute
+fn schedule_jobs(scheduler: &Scheduler, dbclient: mongodb::Client, http_client: reqwest::Client) {
+    let oauth2_info: &Oauth2Client = &CONFIG.oauth2client;
+    //IMPORTANT: The urls should NOT have "/" appended to the end, the lib will crash if so
+    let oauth_client: BasicClient =
+        BasicClient::new(
+            ClientId::new(oauth2_info.client_id.clone()),
+            Some(ClientSecret::new(oauth2_info.client_secret.clone())),
+            AuthUrl::new(oauth2_info.auth_url.clone()).unwrap(),
+            Some(TokenUrl::new(oauth2_info.token_url.clone()).unwrap()))
+            // Set the URL the user will be redirected to after the authorization process.
+            .set_redirect_uri(RedirectUrl::new(oauth2_info.redirect_url.clone()).unwrap());
+
+    let (sync_dbclient, sync_http_client) = (dbclient.clone(), http_client.clone());
+    scheduler.spawn("account_sync", Duration::from_secs(3600), move || sync_accounts(sync_dbclient.clone(), sync_http_client.clone(), oauth_client.clone()));
+
+    let gauges_dbclient: mongodb::Client = dbclient.clone();
+    scheduler.spawn("fiche_gauges", Duration::from_secs(60), move || refresh_fiche_gauges(gauges_dbclient.clone()));
+
+    scheduler.spawn("avatar_sync", Duration::from_secs(86400), move || {
+        let (dbclient, http_client) = (dbclient.clone(), http_client.clone());
+        async move {
+            let report: AvatarSyncReport = sync_avatars(&dbclient, &http_client, false).await?;
+            Ok(format!("{} fetched, {} failed, {} removed", report.fetched, report.failed, report.removed))
         }
     });
 }
@@ -169,6 +265,7 @@ pub fn is_rate_limited(
 
     if rate_limit_data.first_request_time.elapsed() < time_window {
         if rate_limit_data.request_count >= max_requests {
+            RATE_LIMIT_REJECTIONS.inc();
             true
         } else {
             rate_limit_data.request_count += 1;
@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use mongodb::bson::{doc, to_bson, Document};
+use oauth2::basic::{BasicTokenResponse, BasicTokenType};
+use oauth2::{AccessToken, EmptyExtraTokenFields};
+use serde::{Deserialize, Serialize};
+use serenity::futures::TryStreamExt;
+
+use shared::audit::AuditEntry;
+use shared::legal::TermsAcceptance;
+use shared::mentions::MentionNotification;
+use shared::user::{Account, FrontAccount};
+use shared::website_meta::WebsiteMeta;
+
+use crate::utils::db_utils::{account_collection, audit_collection, get_website_meta, meta_collection, migrate, notification_collection, SCHEMA_VERSION};
+use crate::utils::image_utils::{read_image, store_image, ProcessedImage};
+
+/// Version of the archive layout itself, bumped when `Archive` changes in a non backward compatible way
+pub const ARCHIVE_VERSION: u32 = 2;
+/// Oldest layout still restored, the collections it lacks are left untouched
+const OLDEST_ARCHIVE_VERSION: u32 = 1;
+
+/// Full backup of the website data: accounts with their fiches, notifications, audit trail and uploaded images.
+/// OAuth tokens and auth ids are never exported, accounts restored on an empty database have to log in again.
+/// Avatars are a cache of Discord and are fetched again by the avatar job.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct Archive {
+    pub archive_version: u32,
+    pub schema_version: u32,
+    pub exported_at: u64,
+    pub website_meta: WebsiteMeta,
+    pub accounts: Vec<ArchivedAccount>,
+    #[serde(default)]
+    pub notifications: Vec<MentionNotification>,
+    #[serde(default)]
+    pub audit: Vec<AuditEntry>,
+    #[serde(default)]
+    pub images: Vec<ArchivedImage>,
+}
+
+/// An account with its terms acceptances, which `FrontAccount` leaves out. Flattened, the layout is the one of the
+/// archives holding the acceptances in `FrontAccount`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct ArchivedAccount {
+    #[serde(flatten)]
+    pub account: FrontAccount,
+    #[serde(default)]
+    pub terms_acceptances: Vec<TermsAcceptance>,
+}
+
+/// Files of a fiche image, base64 encoded WebP
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct ArchivedImage {
+    pub fiche_id: String,
+    pub image_id: String,
+    pub full: String,
+    pub thumbnail: String,
+}
+
+#[derive(Default, PartialEq, Debug)]
+pub struct ImportReport {
+    pub created_accounts: usize,
+    pub updated_accounts: usize,
+    pub fiches: usize,
+    pub messages: usize,
+    pub versions: usize,
+    pub notifications: usize,
+    pub audit_entries: usize,
+    pub images: usize,
+}
+
+impl Archive {
+    pub fn new(website_meta: WebsiteMeta, accounts: Vec<ArchivedAccount>, exported_at: u64) -> Self {
+        Archive {
+            archive_version: ARCHIVE_VERSION,
+            schema_version: website_meta.schema_version,
+            exported_at,
+            website_meta,
+            accounts,
+            notifications: vec![],
+            audit: vec![],
+            images: vec![],
+        }
+    }
+
+    /// Parses an archive and refuses the ones this backend can't restore
+    pub fn parse(json: &str) -> Result<Self> {
+        let archive: Archive = serde_json::from_str(json)?;
+
+        if !(OLDEST_ARCHIVE_VERSION..=ARCHIVE_VERSION).contains(&archive.archive_version) {
+            bail!("Unsupported archive version {} (expected {} to {})", archive.archive_version, OLDEST_ARCHIVE_VERSION, ARCHIVE_VERSION);
+        }
+        if archive.schema_version > SCHEMA_VERSION {
+            bail!("Archive schema version {} is newer than this backend ({})", archive.schema_version, SCHEMA_VERSION);
+        }
+        Ok(archive)
+    }
+
+    /// What the import would restore. Fails on images that can't be restored, before anything is written
+    pub fn plan_import(&self, existing_ids: &HashSet<String>) -> Result<ImportReport> {
+        let mut report: ImportReport = ImportReport::default();
+
+        self.accounts.iter().map(|archived| &archived.account).for_each(|account| {
+            if existing_ids.contains(&account.discord_user.id) {
+                report.updated_accounts += 1;
+            } else {
+                report.created_accounts += 1;
+            }
+            report.fiches += account.fiches.len();
+            report.messages += account.fiches.iter().map(|fiche| fiche.messages.len()).sum::<usize>();
+            report.versions += account.fiches.iter().map(|fiche| fiche.version.len()).sum::<usize>();
+        });
+        report.notifications = self.notifications.len();
+        report.audit_entries = self.audit.len();
+
+        for image in &self.images {
+            self.processed_image(image)?;
+            report.images += 1;
+        }
+        Ok(report)
+    }
+
+    /// Decoded files of an archived image, which has to belong to an archived fiche
+    fn processed_image(&self, image: &ArchivedImage) -> Result<ProcessedImage> {
+        let fiche_image = self.accounts.iter()
+                              .flat_map(|archived| archived.account.fiches.iter())
+                              .filter(|fiche| fiche.id == image.fiche_id)
+                              .flat_map(|fiche| fiche.images.iter())
+                              .find(|fiche_image| fiche_image.id == image.image_id)
+                              .ok_or_else(|| anyhow!("Image {} of fiche {} is not referenced by any fiche", image.image_id, image.fiche_id))?;
+        Ok(ProcessedImage {
+            full: STANDARD.decode(&image.full).with_context(|| format!("Image {} is not valid base64", image.image_id))?,
+            thumbnail: STANDARD.decode(&image.thumbnail).with_context(|| format!("Thumbnail {} is not valid base64", image.image_id))?,
+            width: fiche_image.width,
+            height: fiche_image.height,
+        })
+    }
+}
+
+/// Fails when an image referenced by a fiche is missing from the disk, an archive without it wouldn't be complete
+pub async fn export_archive(dbclient: &mongodb::Client, exported_at: u64) -> Result<Archive> {
+    let accounts: Vec<ArchivedAccount> = account_collection::<Account>(dbclient).find(Document::new()).await?
+                                                                          .try_collect::<Vec<Account>>().await?
+                                                                          .iter()
+                                                                          .map(|account| ArchivedAccount { account: account.front(), terms_acceptances: account.terms_acceptances.clone() })
+                                                                          .collect();
+    let notifications: Vec<MentionNotification> = notification_collection(dbclient).find(Document::new()).await?.try_collect().await?;
+    let audit: Vec<AuditEntry> = audit_collection(dbclient).find(Document::new()).await?.try_collect().await?;
+
+    let mut images: Vec<ArchivedImage> = vec![];
+    for fiche in accounts.iter().flat_map(|archived| archived.account.fiches.iter()) {
+        for image in &fiche.images {
+            let (full, thumbnail) = read_image(&fiche.id, &image.id).with_context(|| format!("Can't read image {} of fiche {}", image.id, fiche.id))?;
+            images.push(ArchivedImage {
+                fiche_id: fiche.id.clone(),
+                image_id: image.id.clone(),
+                full: STANDARD.encode(full),
+                thumbnail: STANDARD.encode(thumbnail),
+            });
+        }
+    }
+
+    Ok(Archive {
+        notifications,
+        audit,
+        images,
+        ..Archive::new(get_website_meta(dbclient).await, accounts, exported_at)
+    })
+}
+
+/// Restores an archive. Existing accounts keep their session and token, only their website data is replaced.
+/// With `dry_run` the database is left untouched and only the report is computed.
+pub async fn import_archive(dbclient: &mongodb::Client, archive: &Archive, dry_run: bool) -> Result<ImportReport> {
+    let existing_ids: HashSet<String> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?
+                                                                                   .try_collect::<Vec<FrontAccount>>().await?
+                                                                                   .into_iter()
+                                                                                   .map(|account| account.discord_user.id)
+                                                                                   .collect();
+    let report: ImportReport = archive.plan_import(&existing_ids)?;
+    if dry_run {
+        return Ok(report);
+    }
+
+    let meta_update = doc! {
+        "$set": {
+            "whitelist": to_bson(&archive.website_meta.whitelist)?,
+            "schema_version": archive.schema_version
+        }
+    };
+    meta_collection(dbclient).update_one(Document::new(), meta_update).upsert(true).await?;
+
+    for ArchivedAccount { account, terms_acceptances } in &archive.accounts {
+        if existing_ids.contains(&account.discord_user.id) {
+            let query = doc! {
+                "discord_user.id": &account.discord_user.id
+            };
+            let update = doc! {
+                "$set": {
+                    "discord_user": to_bson(&account.discord_user)?,
+                    "discord_roles": to_bson(&account.discord_roles)?,
+                    "fiches": to_bson(&account.fiches)?,
+                    "creation_date": to_bson(&account.creation_date)?,
+                    "banned": account.banned,
+                    "terms_acceptances": to_bson(terms_acceptances)?
+                }
+            };
+            account_collection::<Account>(dbclient).update_one(query, update).await?;
+        } else {
+            account_collection::<Account>(dbclient).insert_one(restored_account(account, terms_acceptances)).await?;
+        }
+    }
+
+    for notification in &archive.notifications {
+        notification_collection(dbclient).replace_one(doc! {"id": &notification.id}, notification).upsert(true).await?;
+    }
+    for entry in &archive.audit {
+        audit_collection(dbclient).replace_one(doc! {"id": &entry.id}, entry).upsert(true).await?;
+    }
+    for image in &archive.images {
+        store_image(&image.fiche_id, &image.image_id, &archive.processed_image(image)?)?;
+    }
+
+    // Older archives are brought up to date by the regular migrations
+    migrate(dbclient).await?;
+    Ok(report)
+}
+
+/// An account without any usable token, the renewal job sees it as expired and the owner has to log in again
+fn restored_account(account: &FrontAccount, terms_acceptances: &[TermsAcceptance]) -> Account {
+    let mut token: BasicTokenResponse = BasicTokenResponse::new(AccessToken::new(String::new()), BasicTokenType::Bearer, EmptyExtraTokenFields {});
+    token.set_expires_in(Some(&Duration::ZERO));
+
+    Account {
+        discord_user: account.discord_user.clone(),
+        discord_roles: account.discord_roles.clone(),
+        sessions: vec![],
+        token,
+        last_renewal: 0,
+        fiches: account.fiches.clone(),
+        creation_date: account.creation_date,
+        banned: account.banned,
+        needs_relogin: true,
+        terms_acceptances: terms_acceptances.to_vec(),
+        sync_failures: 0,
+        next_sync: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::discord::User;
+    use shared::fiche_rp::{FicheImage, FicheRP, FicheState, FicheVersion, ImageKind, Job, ReviewMessage, ScienceRank, ScienceRole};
+    use shared::fixtures;
+    use shared::legal::LegalVersions;
+
+    use super::*;
+
+    fn archive() -> Archive {
+        let fiche: FicheRP = FicheRP {
+            id: "fiche".to_string(),
+            name: "Roger".to_string(),
+            job: Job::Science(ScienceRole::Researcher(ScienceRank::Senior)),
+            description: "Grand".to_string(),
+            lore: "Né à Paris".to_string(),
+            submission_date: 1720000100,
+            messages: vec![ReviewMessage {
+                content: "Très bien".to_string(),
+                date: 1720000200,
+                is_private: true,
+                ..fixtures::review_message("2", FicheState::Accepted)
+            }],
+            version: vec![FicheVersion {
+                name: "Roger".to_string(),
+                job: Job::ClassD,
+                description: "Petit".to_string(),
+                lore: "".to_string(),
+                submission_date: 1720000000,
+            }],
+            images: vec![FicheImage { id: "image".to_string(), kind: ImageKind::Portrait, width: 16, height: 8, uploaded_at: 1720000300 }],
+            ..fixtures::fiche(FicheState::Accepted)
+        };
+        let account: FrontAccount = FrontAccount {
+            discord_user: User { id: "1".to_string(), global_name: "Roger".to_string(), avatar: "abc".to_string() },
+            discord_roles: vec!["1031296063056924718".to_string()],
+            fiches: vec![fiche],
+            creation_date: 1710000000,
+            banned: true,
+            needs_relogin: false,
+        };
+        let meta: WebsiteMeta = WebsiteMeta { whitelist: vec!["2".to_string()], schema_version: SCHEMA_VERSION };
+
+        Archive {
+            notifications: vec![MentionNotification {
+                id: "notification".to_string(),
+                discord_id: "1".to_string(),
+                author_id: "2".to_string(),
+                owner_id: "1".to_string(),
+                fiche_id: "fiche".to_string(),
+                message_id: "message".to_string(),
+                date: 1720000200,
+                read: false,
+            }],
+            images: vec![ArchivedImage {
+                fiche_id: "fiche".to_string(),
+                image_id: "image".to_string(),
+                full: STANDARD.encode(b"RIFF full"),
+                thumbnail: STANDARD.encode(b"RIFF thumb"),
+            }],
+            ..Archive::new(meta, vec![ArchivedAccount {
+                account,
+                terms_acceptances: vec![TermsAcceptance { versions: LegalVersions::default(), date: 1710000000 }],
+            }, ArchivedAccount::default()], 1730000000)
+        }
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let archive: Archive = archive();
+        let json: String = serde_json::to_string_pretty(&archive).unwrap();
+
+        assert!(!json.contains("access_token") && !json.contains("auth_id"));
+        assert!(Archive::parse(&json).unwrap() == archive);
+
+        // Same layout as when the acceptances were part of `FrontAccount`
+        let value: serde_json::Value = serde_json::to_value(&archive).unwrap();
+        assert_eq!(value["accounts"][0]["terms_acceptances"][0]["date"], 1710000000);
+        assert_eq!(value["accounts"][0]["discord_user"]["id"], "1");
+    }
+
+    #[test]
+    fn archive_version_checks() {
+        let mut archive: Archive = archive();
+        archive.schema_version = SCHEMA_VERSION + 1;
+        assert!(Archive::parse(&serde_json::to_string(&archive).unwrap()).is_err());
+
+        archive.schema_version = SCHEMA_VERSION;
+        archive.archive_version = ARCHIVE_VERSION + 1;
+        assert!(Archive::parse(&serde_json::to_string(&archive).unwrap()).is_err());
+
+        // First layout, without notifications, audit nor images
+        let mut old: serde_json::Value = serde_json::to_value(&archive).unwrap();
+        ["notifications", "audit", "images"].iter().for_each(|field| {
+            old.as_object_mut().unwrap().remove(*field);
+        });
+        old["archive_version"] = 1.into();
+        assert!(Archive::parse(&old.to_string()).unwrap().images.is_empty());
+    }
+
+    #[test]
+    fn import_dry_run() {
+        let parsed: Archive = Archive::parse(&serde_json::to_string(&archive()).unwrap()).unwrap();
+        let report: ImportReport = parsed.plan_import(&HashSet::from(["1".to_string()])).unwrap();
+
+        assert_eq!(
+            report,
+            ImportReport { created_accounts: 1, updated_accounts: 1, fiches: 1, messages: 1, versions: 1, notifications: 1, audit_entries: 0, images: 1 }
+        );
+        let image: ProcessedImage = parsed.processed_image(&parsed.images[0]).unwrap();
+        assert_eq!((image.full.as_slice(), image.width, image.height), (b"RIFF full".as_slice(), 16, 8));
+
+        let mut corrupted: Archive = archive();
+        corrupted.images[0].thumbnail = "not base64!".to_string();
+        assert!(corrupted.plan_import(&HashSet::new()).is_err());
+
+        let mut orphan: Archive = archive();
+        orphan.images[0].image_id = "other".to_string();
+        assert!(orphan.plan_import(&HashSet::new()).is_err());
+    }
+}
@@ -1,31 +1,93 @@
-use std::fs;
 use std::io::BufReader;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use log::{error, info};
+use anyhow::anyhow;
+use log::{error, info, warn};
 use mongodb::bson::{doc, to_bson, Document};
 use mongodb::Collection;
 use oauth2::basic::{BasicClient, BasicTokenResponse};
-use oauth2::reqwest::async_http_client;
-use oauth2::{RefreshToken, TokenResponse};
+use oauth2::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use oauth2::{HttpRequest, HttpResponse, RefreshToken, TokenResponse};
 use octocrab::Error::Encoder;
 use reqwest::{Client, Response};
 use serde_json::Value;
+use serenity::futures::{stream, StreamExt, TryStreamExt};
 use uuid::Uuid;
 
 use shared::discord::{DiscordAuthorizationInformation, GuildMember, User};
-use shared::user::Account;
+use shared::fiche_rp::{CharacterStatus, FicheRP, FicheState};
+use shared::permissions::DiscordRole;
+use shared::user::{Account, AuthSession, FrontAccount};
 
+use crate::utils::avatar_utils::refresh_avatar;
+use crate::utils::crypto_utils::TOKEN_CIPHER;
+use crate::utils::db_utils::{account_collection, record_sync_failure, record_sync_success, revoke_sessions, set_needs_relogin};
+use crate::utils::metrics_utils::TOKEN_RENEWALS;
 use crate::CONFIG;
 
+/// Accounts synced at the same time by the background job, Discord rate limits the API per application
+const MAX_CONCURRENT_SYNCS: usize = 4;
+/// Tokens are renewed when they expire within this delay
+const RENEW_BEFORE_SECS: i64 = 86400;
+/// Consecutive failures after which the user is asked to log in again
+pub const MAX_SYNC_FAILURES: u32 = 5;
+/// Lifetime of a session and of its cookie
+pub const SESSION_LIFETIME_SECS: u64 = 4 * 7 * 86400;
+/// User agents are only displayed in the session list, longer ones are cut
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
 pub async fn is_auth_valid(auth_id: &str, client: mongodb::Client) -> bool {
     let accounts: Collection<Account> = client.database("visualis-website").collection("account");
+    let time_now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).expect("invalid time").as_secs();
     let query = doc! {
-        "auth_id" : auth_id
+        "sessions" : { "$elemMatch": { "auth_id": auth_id, "expires_at": { "$gt": time_now as i64 } } },
+        "banned" : { "$ne": true }
     };
     accounts.count_documents(query).await.unwrap() > 0
 }
 
+/// Session of a new login, its auth_id goes in the cookie
+pub fn new_session(user_agent: &str, time_now: u64) -> AuthSession {
+    AuthSession {
+        id: Uuid::now_v7().to_string(),
+        auth_id: Uuid::now_v7().to_string(),
+        created_at: time_now,
+        expires_at: time_now + SESSION_LIFETIME_SECS,
+        user_agent: user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect(),
+    }
+}
+
+pub fn is_staff(account: &FrontAccount, whitelist: &Vec<String>) -> bool {
+    has_staff_role(&account.discord_user.id, &account.discord_roles, whitelist)
+}
+
+/// Staff check from the raw Discord ids, for the callers without an intranet account (e.g. the slash commands)
+pub fn has_staff_role(discord_id: &str, role_ids: &Vec<String>, whitelist: &Vec<String>) -> bool {
+    whitelist.iter().any(|id| id == discord_id) || DiscordRole::from_role_ids(role_ids).unwrap_or_default().iter().any(|user_role| { *user_role == DiscordRole::PlatformAdmin || *user_role == DiscordRole::Admin || *user_role == DiscordRole::LeadScenarist || *user_role == DiscordRole::LeadMed || *user_role == DiscordRole::Scenarist })
+}
+
+pub fn is_lead(account: &FrontAccount, whitelist: &Vec<String>) -> bool {
+    whitelist.contains(&account.discord_user.id) || DiscordRole::from_role_ids(&account.discord_roles).unwrap_or_default().iter().any(|user_role| { *user_role == DiscordRole::PlatformAdmin || *user_role == DiscordRole::Admin || *user_role == DiscordRole::LeadScenarist || *user_role == DiscordRole::LeadMed })
+}
+
+/// Drafts are only visible to their owner, staff included
+pub fn is_hidden_draft(fiche: &FicheRP, owner_id: &str, viewer_id: &str) -> bool {
+    fiche.state == FicheState::Draft && owner_id != viewer_id
+}
+
+/// Archived characters are only visible to their owner and the staff
+pub fn is_hidden_archive(fiche: &FicheRP, owner_id: &str, viewer_id: &str, viewer_is_staff: bool) -> bool {
+    fiche.character_status() == CharacterStatus::Archived && !viewer_is_staff && owner_id != viewer_id
+}
+
+/// Removes the fiches `viewer_id` must not see: the drafts of the others and, for the players, their archived characters
+pub fn hide_private_fiches(accounts: &mut Vec<FrontAccount>, viewer_id: &str, viewer_is_staff: bool) {
+    accounts.iter_mut().for_each(|account| {
+        let owner_id: String = account.discord_user.id.clone();
+        account.fiches.retain(|fiche| !is_hidden_draft(fiche, &owner_id, viewer_id) && !is_hidden_archive(fiche, &owner_id, viewer_id, viewer_is_staff));
+    });
+}
+
 pub async fn is_user_registered(discord_id: &String, client: mongodb::Client) -> bool {
     let accounts: Collection<Account> = client.database("visualis-website").collection("account");
     let query = doc! {
@@ -33,7 +95,7 @@ pub async fn is_user_registered(discord_id: &String, client: mongodb::Client) ->
     };
     accounts.count_documents(query).await.unwrap() > 0
 }
-pub async fn update_token(auth_id: &str, discord_id: &String, token_response: BasicTokenResponse, client: mongodb::Client, reqwest_client: &Client) {
+pub async fn update_token(discord_id: &String, token_response: BasicTokenResponse, client: mongodb::Client, reqwest_client: &Client) {
     let accounts: Collection<Account> = client.database("visualis-website").collection("account");
     let query = doc! {
         "discord_user.id" : discord_id
@@ -43,65 +105,47 @@ pub async fn update_token(auth_id: &str, discord_id: &String, token_response: Ba
 
     let update_doc = doc! {
         "$set": {
-            "token": to_bson(&token_response).unwrap(),
+            "token": to_bson(&TOKEN_CIPHER.encrypt_token(&token_response)).unwrap(),
             "last_renewal": to_bson(&time_now).unwrap(),
+            // A new login fixes whatever the background sync was failing on
+            "needs_relogin": false,
+            "sync_failures": 0,
+            "next_sync": 0,
         }
     };
     match accounts.update_one(query, update_doc).await {
         Ok(_) => info!("Updated token for account {}", discord_id),
         Err(err) => error!("Failed to update token for account {}: \n{}", discord_id, err),
     }
-    update_account_discord(auth_id, client, reqwest_client).await;
-}
-
-pub async fn update_auth_id(discord_id: &String, auth_id: &String, client: mongodb::Client) {
-    let accounts: Collection<Account> = client.database("visualis-website").collection("account");
-    let query = doc! {
-        "discord_user.id" : discord_id
-    };
-    let update_doc = doc! {
-        "$set": {
-            "auth_id": to_bson(&auth_id).unwrap(),
-        }
-    };
-    match accounts.update_one(query, update_doc).await {
-        Ok(_) => {
-            info!("auth_id updated for {}", discord_id);
-        }
-        Err(err) => {
-            error!("auth_id update for {} failed: {}", discord_id, err);
-        }
-    };
+    if let Err(err) = update_account_discord(discord_id, client, reqwest_client).await {
+        error!("Discord update for {} failed: {}", discord_id, err);
+    }
 }
 
-pub async fn update_account_discord(auth_id: &str, client: mongodb::Client, reqwest_client: &Client) {
+pub async fn update_account_discord(discord_id: &str, client: mongodb::Client, reqwest_client: &Client) -> anyhow::Result<()> {
     let accounts: Collection<Account> = client.database("visualis-website").collection("account");
     let query: Document = doc! {
-        "auth_id" : auth_id
+        "discord_user.id" : discord_id
     };
 
-    let account: Account = accounts.find_one(query.clone()).await.expect("Can't retrieve account to update").unwrap();
-    let token: &String = account.token.access_token().secret();
+    let account: Account = accounts.find_one(query.clone()).await?.ok_or_else(|| anyhow!("no account to update"))?;
+    let decrypted_token: BasicTokenResponse = TOKEN_CIPHER.decrypt_token(&account.token)?;
+    let token: &String = decrypted_token.access_token().secret();
 
     let response: Response = reqwest_client
         .get("https://discord.com/api/oauth2/@me")
         .bearer_auth(token)
         .send()
-        .await.expect("Can't get token_response");
+        .await?;
 
-    let authorization_information: DiscordAuthorizationInformation = match response.json().await {
-        Ok(info) => info,
-        Err(e) => {
-            error!("Can't get DiscordAuthorizationInformation for id {}, error : {:?}", &account.discord_user.id,e);
-            return;
-        }
-    };
+    let authorization_information: DiscordAuthorizationInformation = response.json().await
+        .map_err(|err| anyhow!("Can't get DiscordAuthorizationInformation for id {}: {}", account.discord_user.id, err))?;
 
     let discord_guild_member_response: Response = reqwest_client
-        .get("https://discord.com/api/users/@me/guilds/1031296063056924714/member")
+        .get(format!("https://discord.com/api/users/@me/guilds/{}/member", CONFIG.guild_id))
         .bearer_auth(token)
         .send()
-        .await.expect("Can't get token_response");
+        .await?;
 
     let guild_member: GuildMember = discord_guild_member_response.json().await.unwrap_or(GuildMember {
         roles: vec![],
@@ -116,65 +160,189 @@ pub async fn update_account_discord(auth_id: &str, client: mongodb::Client, reqw
 
     let user: &User = &authorization_information.user;
 
-    if account.discord_user.avatar != user.avatar {
-        let avatar_url: String = format!("https://cdn.discordapp.com/avatars/{}/{}.webp?size=128", user.id, user.avatar);
-
-        let avatar_response: Response = reqwest_client.get(&avatar_url).send().await.expect("Can't get avatar");
-
-        match avatar_response.bytes().await {
-            Ok(bytes) => {
-                match fs::create_dir_all(format!("data/cache/avatars/{}", user.id)) {
-                    Ok(_) => {
-                        match fs::write(format!("data/cache/avatars/{}/image.webp", user.id), bytes) {
-                            Ok(_) => info!("Updated avatar for user {}", user.id),
-                            Err(err) => error!("Failed to update avatar for user {}: {}", user.id, err)
-                        }
-                    }
-                    Err(err) => error!("Can't create user id avatars directory: {}", err),
-                }
-            }
-            Err(err) => {
-                error!("Can't get avatar for user {}: {}", user.id, &err);
-            }
-        }
+    // Also fetches avatars missing from the cache, not only the ones whose hash changed
+    refresh_avatar(reqwest_client, user).await;
+
+    accounts.update_one(query, update_doc).await?;
+    info!("Discord info updated for {}({})", authorization_information.user.global_name, authorization_information.user.id);
+    Ok(())
+}
+
+/// Sends a token request of `oauth2` through the shared client, `oauth2` still uses the `http` 0.2 types
+async fn oauth_request(reqwest_client: &Client, request: HttpRequest) -> Result<HttpResponse, reqwest::Error> {
+    let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes()).expect("invalid method");
+    let mut request_builder = reqwest_client.request(method, request.url.as_str()).body(request.body);
+    for (name, value) in &request.headers {
+        request_builder = request_builder.header(name.as_str(), value.as_bytes());
     }
+    let response: Response = request_builder.send().await?;
 
-    match accounts.update_one(query, update_doc).await {
-        Ok(_) => info!("Discord info updated for {}({})",authorization_information.user.global_name, authorization_information.user.id),
-        Err(err) => error!("Discord update for {}({}) failed: \n{}", authorization_information.user.global_name,authorization_information.user.id, err),
+    let status_code: StatusCode = StatusCode::from_u16(response.status().as_u16()).expect("invalid status code");
+    let mut headers: HeaderMap = HeaderMap::new();
+    for (name, value) in response.headers() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_str().as_bytes()), HeaderValue::from_bytes(value.as_bytes())) {
+            headers.append(name, value);
+        }
     }
+    let body: Vec<u8> = response.bytes().await?.to_vec();
+    Ok(HttpResponse { status_code, headers, body })
 }
 
-pub async fn renew_token(old_token: &str, renew_token: &RefreshToken, client: mongodb::Client, oauth_client: BasicClient) {
+/// `renew_token` is the decrypted refresh token of the account
+pub async fn renew_token(discord_id: &str, renew_token: &RefreshToken, client: mongodb::Client, oauth_client: &BasicClient, reqwest_client: &Client) -> anyhow::Result<()> {
     let accounts: Collection<Account> = client.database("visualis-website").collection("account");
     let query: Document = doc! {
-        "token.access_token" : old_token
+        "discord_user.id" : discord_id
     };
-    let account: Account = accounts.find_one(query.clone()).await.expect("Can't find account to update").expect("Can't retrieve account document");
-
-    let token_result: BasicTokenResponse = match oauth_client
+    let token_result: BasicTokenResponse = oauth_client
         .exchange_refresh_token(renew_token)
-        .request_async(async_http_client)
-        .await {
-        Ok(token) => token,
-        Err(e) => {
-            error!("Failed to renew token: {:?}", e);
-            return;
-        }
-    };
+        .request_async(|request| oauth_request(reqwest_client, request))
+        .await
+        .map_err(|err| anyhow!("Failed to renew token: {:?}", err))?;
 
     let time_now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).expect("invalid time").as_secs();
 
     let update_doc: Document = doc! {
         "$set": {
-            "token": to_bson(&token_result).unwrap(),
+            "token": to_bson(&TOKEN_CIPHER.encrypt_token(&token_result)).unwrap(),
             "last_renewal": to_bson(&time_now).unwrap(),
         }
     };
 
-    match accounts.update_one(query, update_doc).await {
-        Err(err) => error!("Can't update account oauth2 token id:{}: {}", &account.discord_user.id, err),
-        _ => {}
+    accounts.update_one(query, update_doc).await?;
+    update_account_discord(discord_id, client, reqwest_client).await
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SyncOutcome {
+    Renewed,
+    Synced,
+    Expired,
+    Skipped,
+    Failed,
+}
+
+/// Renews the tokens expiring within a day and refreshes the Discord info of the other accounts, a few accounts at a time.
+/// Failures are recorded per account and retried with a backoff, returns a summary for the job status
+pub async fn sync_accounts(dbclient: mongodb::Client, reqwest_client: Client, oauth_client: BasicClient) -> anyhow::Result<String> {
+    let time_now: u64 = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let query: Document = doc! {
+        "next_sync": {"$not": {"$gt": time_now as i64}}
+    };
+    let accounts: Vec<Account> = account_collection::<Account>(&dbclient).find(query).await?.try_collect().await?;
+
+    let outcomes: Vec<SyncOutcome> = stream::iter(accounts.iter())
+        .map(|account| sync_account(account, time_now, &dbclient, &reqwest_client, &oauth_client))
+        .buffer_unordered(MAX_CONCURRENT_SYNCS)
+        .collect()
+        .await;
+
+    let count = |outcome: SyncOutcome| outcomes.iter().filter(|other| **other == outcome).count();
+    Ok(format!("{} renewed, {} synced, {} expired, {} skipped, {} failed", count(SyncOutcome::Renewed), count(SyncOutcome::Synced), count(SyncOutcome::Expired), count(SyncOutcome::Skipped), count(SyncOutcome::Failed)))
+}
+
+async fn sync_account(account: &Account, time_now: u64, dbclient: &mongodb::Client, reqwest_client: &Client, oauth_client: &BasicClient) -> SyncOutcome {
+    let discord_id: &String = &account.discord_user.id;
+    let remaining_secs: Option<i64> = account.token.expires_in().map(|expires_in| (account.last_renewal + expires_in.as_secs()) as i64 - time_now as i64);
+
+    // Nothing more can be done until the next login clears the flag, an expired account is only revoked once
+    if account.needs_relogin {
+        return SyncOutcome::Skipped;
+    }
+    if remaining_secs.is_some_and(|remaining_secs| remaining_secs <= 0) {
+        warn!("Can't renew token for {}({}) since it has expired", account.discord_user.global_name, discord_id);
+        TOKEN_RENEWALS.with_label_values(&["expired"]).inc();
+        if let Err(err) = revoke_sessions(dbclient, discord_id).await {
+            error!("Can't revoke the sessions of {}: {}", discord_id, err);
+        }
+        if let Err(err) = set_needs_relogin(dbclient, discord_id).await {
+            error!("Can't flag {} for a new login: {}", discord_id, err);
+        }
+        return SyncOutcome::Expired;
+    }
+
+    let result: anyhow::Result<SyncOutcome> = if remaining_secs.is_some_and(|remaining_secs| remaining_secs <= RENEW_BEFORE_SECS) {
+        info!("Renewing token for {}({})", account.discord_user.global_name, discord_id);
+        match TOKEN_CIPHER.decrypt_token(&account.token) {
+            Ok(token) => match token.refresh_token() {
+                Some(refresh_token) => renew_token(discord_id, refresh_token, dbclient.clone(), oauth_client, reqwest_client).await.map(|_| SyncOutcome::Renewed),
+                None => Err(anyhow!("no refresh token")),
+            },
+            Err(err) => Err(err),
+        }
+    } else {
+        update_account_discord(discord_id, dbclient.clone(), reqwest_client).await.map(|_| SyncOutcome::Synced)
+    };
+
+    match result {
+        Ok(outcome) => {
+            if outcome == SyncOutcome::Renewed {
+                TOKEN_RENEWALS.with_label_values(&["renewed"]).inc();
+            }
+            if account.sync_failures > 0 {
+                if let Err(err) = record_sync_success(dbclient, discord_id).await {
+                    error!("Can't reset the sync failures of {}: {}", discord_id, err);
+                }
+            }
+            outcome
+        }
+        Err(err) => {
+            let failures: u32 = account.sync_failures + 1;
+            warn!("Sync of {}({}) failed ({} in a row): {}", account.discord_user.global_name, discord_id, failures, err);
+            if remaining_secs.is_some_and(|remaining_secs| remaining_secs <= RENEW_BEFORE_SECS) {
+                TOKEN_RENEWALS.with_label_values(&["failed"]).inc();
+            }
+            if let Err(err) = record_sync_failure(dbclient, discord_id, failures, time_now + sync_backoff_secs(failures), failures >= MAX_SYNC_FAILURES).await {
+                error!("Can't record the sync failure of {}: {}", discord_id, err);
+            }
+            SyncOutcome::Failed
+        }
+    }
+}
+
+/// Delay before retrying an account whose sync failed, doubling from 10 minutes up to a day
+pub fn sync_backoff_secs(failures: u32) -> u64 {
+    (600u64 << failures.saturating_sub(1).min(16)).min(86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::fiche_rp::LifecycleChange;
+    use shared::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn draft_visibility() {
+        let archived: LifecycleChange = LifecycleChange { status: CharacterStatus::Archived, reason: "Inactif".to_string(), date: 10, set_by: "3".to_string() };
+        let account = |id: &str| FrontAccount {
+            discord_user: User { id: id.to_string(), ..User::default() },
+            fiches: vec![
+                FicheRP { id: format!("{}-draft", id), ..fixtures::fiche(FicheState::Draft) },
+                FicheRP { id: format!("{}-waiting", id), ..fixtures::fiche(FicheState::Waiting) },
+                FicheRP { id: format!("{}-archived", id), lifecycle: vec![archived.clone()], ..fixtures::fiche(FicheState::Accepted) },
+            ],
+            ..FrontAccount::default()
+        };
+        let fiche_ids = |accounts: &Vec<FrontAccount>| -> Vec<Vec<String>> {
+            accounts.iter().map(|account| account.fiches.iter().map(|fiche| fiche.id.clone()).collect()).collect()
+        };
+
+        let mut accounts: Vec<FrontAccount> = vec![account("1"), account("2")];
+        hide_private_fiches(&mut accounts, "1", false);
+        assert_eq!(fiche_ids(&accounts), vec![vec!["1-draft", "1-waiting", "1-archived"], vec!["2-waiting"]]);
+
+        let mut accounts: Vec<FrontAccount> = vec![account("1"), account("2")];
+        hide_private_fiches(&mut accounts, "3", true);
+        assert_eq!(fiche_ids(&accounts), vec![vec!["1-waiting", "1-archived"], vec!["2-waiting", "2-archived"]]);
+    }
+
+    #[test]
+    fn sync_backoff() {
+        assert_eq!(sync_backoff_secs(1), 600);
+        assert_eq!(sync_backoff_secs(2), 1200);
+        assert_eq!(sync_backoff_secs(4), 4800);
+        assert_eq!(sync_backoff_secs(9), 86400);
+        assert_eq!(sync_backoff_secs(u32::MAX), 86400);
     }
-    update_account_discord(&account.auth_id, client, &Client::new()).await;
 }
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, ErrorKind};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use log::{error, info, warn};
+use mongodb::bson::Document;
+use serenity::futures::TryStreamExt;
+
+use shared::discord::User;
+use shared::user::FrontAccount;
+
+use crate::utils::db_utils::account_collection;
+use crate::utils::image_utils::ignore_not_found;
+
+pub const AVATAR_DIR: &str = "data/cache/avatars";
+/// Sizes fetched from the Discord CDN, any other requested size is served with the closest larger one
+pub const AVATAR_SIZES: [u32; 3] = [32, 64, 128];
+/// Hash of the cached avatar, compared with the Discord one to know when it has to be fetched again
+const HASH_FILE: &str = "hash";
+const DEFAULT_DIR: &str = "default";
+/// Colours of the Discord default avatars
+const DEFAULT_COLORS: [[u8; 3]; 6] = [[88, 101, 242], [117, 126, 138], [59, 165, 92], [250, 166, 26], [237, 66, 69], [235, 69, 159]];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AvatarSyncReport {
+    pub fetched: usize,
+    pub failed: usize,
+    pub removed: usize,
+}
+
+/// Closest available size at least as large as the requested one
+pub fn avatar_size(requested: u32) -> u32 {
+    AVATAR_SIZES.iter().copied().find(|size| *size >= requested).unwrap_or(AVATAR_SIZES[AVATAR_SIZES.len() - 1])
+}
+
+/// Cached avatar of a user, None when it was never fetched or the user has no avatar
+pub fn cached_avatar(discord_id: &str, size: u32) -> Option<PathBuf> {
+    let path: PathBuf = avatar_dir(discord_id).ok()?.join(format!("{}.webp", avatar_size(size)));
+    path.is_file().then_some(path)
+}
+
+/// Whether the cached avatar matches the current Discord avatar hash
+pub fn is_avatar_current(user: &User) -> bool {
+    let Ok(dir) = avatar_dir(&user.id) else {
+        return false;
+    };
+    let cached_hash: String = fs::read_to_string(dir.join(HASH_FILE)).unwrap_or_default();
+    cached_hash == user.avatar && (user.avatar.is_empty() || AVATAR_SIZES.iter().all(|size| dir.join(format!("{}.webp", size)).is_file()))
+}
+
+/// Fetches every size of the avatar from the Discord CDN. Files are written next to the old ones then renamed,
+/// a reader never gets a partially written avatar
+pub async fn fetch_avatar(reqwest_client: &reqwest::Client, user: &User) -> Result<()> {
+    let dir: PathBuf = avatar_dir(&user.id)?;
+    fs::create_dir_all(&dir)?;
+
+    if user.avatar.is_empty() {
+        // No custom avatar, the default one is served
+        AVATAR_SIZES.iter().try_for_each(|size| ignore_not_found(fs::remove_file(dir.join(format!("{}.webp", size)))))?;
+    } else {
+        for size in AVATAR_SIZES {
+            let url: String = format!("https://cdn.discordapp.com/avatars/{}/{}.webp?size={}", user.id, user.avatar, size);
+            let response: reqwest::Response = reqwest_client.get(&url).send().await?;
+            if !response.status().is_success() {
+                bail!("Discord CDN answered {} for the avatar of {}", response.status(), user.id);
+            }
+            let bytes = response.bytes().await?;
+
+            let temporary: PathBuf = dir.join(format!("{}.webp.tmp", size));
+            fs::write(&temporary, &bytes)?;
+            fs::rename(&temporary, dir.join(format!("{}.webp", size)))?;
+        }
+    }
+    // Single file layout used before the sizes
+    ignore_not_found(fs::remove_file(dir.join("image.webp")))?;
+    fs::write(dir.join(HASH_FILE), &user.avatar)?;
+    Ok(())
+}
+
+/// Fetches the avatar when it changed or is missing, errors are only logged since the default avatar is served meanwhile
+pub async fn refresh_avatar(reqwest_client: &reqwest::Client, user: &User) -> bool {
+    if is_avatar_current(user) {
+        return false;
+    }
+    match fetch_avatar(reqwest_client, user).await {
+        Ok(_) => {
+            info!("Updated avatar for user {}", user.id);
+            true
+        }
+        Err(err) => {
+            error!("Can't update avatar for user {}: {}", user.id, err);
+            false
+        }
+    }
+}
+
+pub fn remove_avatar(discord_id: &str) -> std::io::Result<()> {
+    ignore_not_found(fs::remove_dir_all(avatar_dir(discord_id)?))
+}
+
+/// Removes the avatars of the accounts that no longer exist, returns how many were removed
+pub fn cleanup_avatars(known_ids: &HashSet<String>) -> std::io::Result<usize> {
+    let entries = match fs::read_dir(AVATAR_DIR) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut removed: usize = 0;
+    for entry in entries {
+        let name: String = entry?.file_name().to_string_lossy().to_string();
+        if is_discord_id(&name) && !known_ids.contains(&name) {
+            remove_avatar(&name)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Fetches the missing or outdated avatars of every account and removes the ones of deleted accounts.
+/// `force` fetches every avatar again
+pub async fn sync_avatars(dbclient: &mongodb::Client, reqwest_client: &reqwest::Client, force: bool) -> Result<AvatarSyncReport> {
+    let accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+    let mut report: AvatarSyncReport = AvatarSyncReport::default();
+
+    for account in &accounts {
+        let user: &User = &account.discord_user;
+        if !force && is_avatar_current(user) {
+            continue;
+        }
+        match fetch_avatar(reqwest_client, user).await {
+            Ok(_) => report.fetched += 1,
+            Err(err) => {
+                warn!("Can't fetch avatar for user {}: {}", user.id, err);
+                report.failed += 1;
+            }
+        }
+    }
+
+    // Fetching can take minutes, the accounts created meanwhile must keep their avatar
+    let known_ids: HashSet<String> = account_collection::<FrontAccount>(dbclient).distinct("discord_user.id", Document::new()).await?
+                                                                               .into_iter()
+                                                                               .filter_map(|id| id.as_str().map(str::to_string))
+                                                                               .collect();
+    report.removed = cleanup_avatars(&known_ids)?;
+    Ok(report)
+}
+
+/// Plain avatar coloured like the Discord default one of the user, generated once per colour and size
+pub fn default_avatar(discord_id: &str, size: u32) -> Result<PathBuf> {
+    let size: u32 = avatar_size(size);
+    let index: usize = (discord_id.parse::<u64>().unwrap_or_default() >> 22) as usize % DEFAULT_COLORS.len();
+    let dir: PathBuf = PathBuf::from(AVATAR_DIR).join(DEFAULT_DIR);
+    let path: PathBuf = dir.join(format!("{}_{}.webp", index, size));
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let [red, green, blue] = DEFAULT_COLORS[index];
+    let mut bytes: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(size, size, Rgba([red, green, blue, 255]))).write_to(&mut bytes, ImageFormat::WebP)?;
+
+    fs::create_dir_all(&dir)?;
+    let temporary: PathBuf = dir.join(format!("{}_{}.webp.tmp", index, size));
+    fs::write(&temporary, bytes.into_inner())?;
+    fs::rename(&temporary, &path)?;
+    Ok(path)
+}
+
+/// Discord ids end up in paths, anything but a snowflake is refused
+fn avatar_dir(discord_id: &str) -> std::io::Result<PathBuf> {
+    if !is_discord_id(discord_id) {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("invalid discord id {}", discord_id)));
+    }
+    Ok(PathBuf::from(AVATAR_DIR).join(discord_id))
+}
+
+fn is_discord_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avatar_sizes() {
+        assert_eq!(avatar_size(0), 32);
+        assert_eq!(avatar_size(33), 64);
+        assert_eq!(avatar_size(128), 128);
+        assert_eq!(avatar_size(4096), 128);
+
+        assert!(avatar_dir("../../etc").is_err());
+        assert!(avatar_dir("default").is_err());
+    }
+}
@@ -1,7 +1,24 @@
-use serde::Deserialize;
+use std::path::Path;
 
-#[derive(Deserialize)]
+use config::{Config, Environment, File};
+use oauth2::url::Url;
+use serde::{Deserialize, Serialize};
+use shared::fiche_rp::{ApprovalQuorum, Job, JobQuorum};
+use shared::legal::LegalVersions;
+use shared::markdown::MarkdownRules;
+use shared::quota::{DepartmentSlots, JobQuota, QuotaRules};
+
+use crate::utils::crypto_utils::decode_key;
+
+/// Prefix of the environment variables overriding the config file, nested keys are separated by `__`
+/// (e.g. `VISUALIS__BOT_TOKEN`, `VISUALIS__OAUTH2CLIENT__CLIENT_SECRET`)
+pub const ENV_PREFIX: &str = "VISUALIS";
+/// Placeholder of the example file, refused so the example can't be deployed as is
+const EXAMPLE_TOKEN_KEY: &str = "<run generate-token-key>";
+
+#[derive(Serialize, Deserialize)]
 #[derive(Clone)]
+#[serde(default)]
 pub struct Configuration {
     pub address: String,
     pub port: u16,
@@ -10,9 +27,30 @@ pub struct Configuration {
     pub bot_token: String,
     pub mongo_db_uri: String,
     pub oauth2client: Oauth2Client,
+    pub guild_id: String,
+    pub role_mappings: Vec<RoleMapping>,
+    pub discord_public_key: String,
+    /// How long a reviewer keeps a claimed or assigned fiche before others can take it
+    pub claim_duration_hours: u64,
+    /// Reviewer approvals moving a fiche to `StaffValidated`, a lead then accepts it
+    pub approval_quorum: ApprovalQuorum,
+    /// Character slots per user and global job quotas, checked on every submission
+    pub quotas: QuotaRules,
+    /// Length limits and image hosts of the markdown written in the fiches and review messages
+    pub markdown: MarkdownRules,
+    /// Limits of the portrait and gallery uploads
+    pub images: ImageRules,
+    /// Keys encrypting the OAuth tokens stored in the database. The first one encrypts, the others are only kept
+    /// to decrypt the tokens written before a rotation
+    pub token_keys: Vec<TokenKey>,
+    /// Current versions of `cgu.html` and `privacy.html`, bump one after editing the text so users accept it again
+    pub legal: LegalVersions,
+    /// Bearer token of the Prometheus scraper on `/metrics`, empty disables the endpoint
+    pub metrics_token: String,
 }
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 #[derive(Clone)]
+#[serde(default)]
 pub struct Oauth2Client {
     pub client_id: String,
     pub client_secret: String,
@@ -20,4 +58,328 @@ pub struct Oauth2Client {
     pub redirect_url: String,
     pub redirect_url_egui: String,
     pub token_url: String,
-}
\ No newline at end of file
+}
+
+/// Uploaded images are decoded and re-encoded, the dimensions bound both the work and the stored files
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+#[serde(default)]
+pub struct ImageRules {
+    pub max_upload_bytes: usize,
+    /// Larger images are refused before being decoded
+    pub max_dimension: u32,
+    /// Stored images are downscaled to fit in this square
+    pub stored_dimension: u32,
+    pub thumbnail_dimension: u32,
+    pub max_gallery_images: usize,
+}
+
+/// `key` is 32 bytes of base64, `backend generate-token-key` prints a new one
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct TokenKey {
+    pub id: String,
+    pub key: String,
+}
+
+/// Discord roles granted by the bot to the owner of an accepted fiche.
+/// `job`, `role` and `rank` are the variant names of the shared job enums (e.g. "Security", "Gunsmith", "Sgt"),
+/// a missing `role` or `rank` matches any value.
+#[derive(Serialize, Deserialize)]
+#[derive(Clone)]
+pub struct RoleMapping {
+    pub job: String,
+    pub role: Option<String>,
+    pub rank: Option<String>,
+    pub role_ids: Vec<String>,
+}
+
+impl RoleMapping {
+    pub fn matches(&self, job: &Job) -> bool {
+        self.job == job.family_key()
+            && self.role.as_deref().is_none_or(|role| job.role_key() == Some(role))
+            && self.rank.as_deref().is_none_or(|rank| job.rank_key() == Some(rank))
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Configuration {
+            address: "0.0.0.0".to_string(),
+            port: 8080,
+            domain: String::new(),
+            scena_webhook: String::new(),
+            bot_token: String::new(),
+            mongo_db_uri: String::new(),
+            oauth2client: Oauth2Client::default(),
+            guild_id: "1031296063056924714".to_string(),
+            role_mappings: vec![],
+            discord_public_key: String::new(),
+            claim_duration_hours: 72,
+            approval_quorum: ApprovalQuorum::default(),
+            quotas: QuotaRules::default(),
+            markdown: MarkdownRules::default(),
+            images: ImageRules::default(),
+            token_keys: vec![],
+            legal: LegalVersions::default(),
+            metrics_token: String::new(),
+        }
+    }
+}
+
+impl Default for ImageRules {
+    fn default() -> Self {
+        ImageRules {
+            max_upload_bytes: 8 * 1024 * 1024,
+            max_dimension: 8000,
+            stored_dimension: 1600,
+            thumbnail_dimension: 256,
+            max_gallery_images: 8,
+        }
+    }
+}
+
+impl ImageRules {
+    /// Memory the decoder may allocate: an 8 bits RGBA image of `max_dimension` pixels on both sides
+    pub fn max_decoded_bytes(&self) -> u64 {
+        u64::from(self.max_dimension).pow(2) * 4
+    }
+}
+
+impl Default for Oauth2Client {
+    fn default() -> Self {
+        Oauth2Client {
+            client_id: String::new(),
+            client_secret: String::new(),
+            auth_url: "https://discord.com/oauth2/authorize".to_string(),
+            redirect_url: String::new(),
+            redirect_url_egui: String::new(),
+            token_url: "https://discord.com/api/oauth2/token".to_string(),
+        }
+    }
+}
+
+impl Configuration {
+    /// Reads the config file (optional, env only setups are fine) then applies the environment overrides.
+    /// Returns every problem found instead of stopping at the first one.
+    pub fn load(path: &Path) -> Result<Configuration, Vec<String>> {
+        let configuration: Configuration = Config::builder()
+            .add_source(File::from(path).required(false))
+            .add_source(Environment::with_prefix(ENV_PREFIX).separator("__").try_parsing(true))
+            .build()
+            .and_then(|config| config.try_deserialize::<Configuration>())
+            .map_err(|err| vec![format!("{}: {}", path.display(), err)])?;
+
+        let errors: Vec<String> = configuration.validate();
+        if errors.is_empty() {
+            Ok(configuration)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors: Vec<String> = vec![];
+
+        [("domain", &self.domain), ("bot_token", &self.bot_token), ("mongo_db_uri", &self.mongo_db_uri), ("oauth2client.client_id", &self.oauth2client.client_id), ("oauth2client.client_secret", &self.oauth2client.client_secret)]
+            .iter()
+            .filter(|(_, value)| value.trim().is_empty())
+            .for_each(|(key, _)| errors.push(format!("{} is missing", key)));
+
+        if !self.mongo_db_uri.is_empty() && !self.mongo_db_uri.starts_with("mongodb://") && !self.mongo_db_uri.starts_with("mongodb+srv://") {
+            errors.push("mongo_db_uri must start with mongodb:// or mongodb+srv://".to_string());
+        }
+
+        [("scena_webhook", &self.scena_webhook), ("oauth2client.auth_url", &self.oauth2client.auth_url), ("oauth2client.token_url", &self.oauth2client.token_url), ("oauth2client.redirect_url", &self.oauth2client.redirect_url), ("oauth2client.redirect_url_egui", &self.oauth2client.redirect_url_egui)]
+            .iter()
+            .for_each(|(key, value)| {
+                if value.is_empty() {
+                    errors.push(format!("{} is missing", key));
+                } else if Url::parse(value).is_err() {
+                    errors.push(format!("{} is not a valid url ({})", key, value));
+                }
+            });
+
+        // The oauth2 lib crashes on these urls when they end with a "/"
+        [("oauth2client.auth_url", &self.oauth2client.auth_url), ("oauth2client.token_url", &self.oauth2client.token_url), ("oauth2client.redirect_url", &self.oauth2client.redirect_url)]
+            .iter()
+            .filter(|(_, value)| value.ends_with('/'))
+            .for_each(|(key, _)| errors.push(format!("{} must not end with \"/\"", key)));
+
+        if !is_snowflake(&self.guild_id) {
+            errors.push(format!("guild_id is not a discord id ({})", self.guild_id));
+        }
+
+        self.role_mappings.iter().enumerate().for_each(|(index, mapping)| {
+            if mapping.role_ids.is_empty() {
+                errors.push(format!("role_mappings[{}] has no role_ids", index));
+            }
+            mapping.role_ids.iter().filter(|role_id| !is_snowflake(role_id)).for_each(|role_id| {
+                errors.push(format!("role_mappings[{}] role id {} is not a discord id", index, role_id));
+            });
+        });
+
+        if self.claim_duration_hours == 0 {
+            errors.push("claim_duration_hours must be greater than 0".to_string());
+        }
+
+        if self.approval_quorum.default_approvals == 0 {
+            errors.push("approval_quorum.default_approvals must be greater than 0".to_string());
+        }
+        self.approval_quorum.job_families.iter().enumerate().filter(|(_, quorum)| quorum.approvals == 0).for_each(|(index, _)| {
+            errors.push(format!("approval_quorum.job_families[{}] approvals must be greater than 0", index));
+        });
+
+        if self.quotas.max_characters == Some(0) {
+            errors.push("quotas.max_characters must be greater than 0, remove it for no limit".to_string());
+        }
+        // A misspelled job would silently never match any fiche
+        self.quotas.department_slots.iter().enumerate().filter(|(_, slots)| !Job::FAMILY_KEYS.contains(&slots.job.as_str())).for_each(|(index, slots)| {
+            errors.push(format!("quotas.department_slots[{}] job {} is not a job family ({})", index, slots.job, Job::FAMILY_KEYS.join(", ")));
+        });
+        self.quotas.job_quotas.iter().enumerate().filter(|(_, quota)| !Job::FAMILY_KEYS.contains(&quota.job.as_str())).for_each(|(index, quota)| {
+            errors.push(format!("quotas.job_quotas[{}] job {} is not a job family ({})", index, quota.job, Job::FAMILY_KEYS.join(", ")));
+        });
+
+        [("markdown.max_description_length", self.markdown.max_description_length), ("markdown.max_lore_length", self.markdown.max_lore_length), ("markdown.max_comment_length", self.markdown.max_comment_length)]
+            .iter()
+            .filter(|(_, max)| *max == 0)
+            .for_each(|(key, _)| errors.push(format!("{} must be greater than 0", key)));
+        self.markdown.allowed_image_hosts.iter().filter(|host| host.is_empty() || host.contains(['/', ':', '@'])).for_each(|host| {
+            errors.push(format!("markdown.allowed_image_hosts must only contain host names ({})", host));
+        });
+
+        [("images.max_upload_bytes", self.images.max_upload_bytes as u64), ("images.max_dimension", self.images.max_dimension as u64), ("images.stored_dimension", self.images.stored_dimension as u64), ("images.thumbnail_dimension", self.images.thumbnail_dimension as u64)]
+            .iter()
+            .filter(|(_, value)| *value == 0)
+            .for_each(|(key, _)| errors.push(format!("{} must be greater than 0", key)));
+        if self.images.thumbnail_dimension > self.images.stored_dimension || self.images.stored_dimension > self.images.max_dimension {
+            errors.push("images dimensions must be ordered: thumbnail_dimension <= stored_dimension <= max_dimension".to_string());
+        }
+
+        if self.token_keys.is_empty() {
+            errors.push("token_keys is missing, generate a key with `backend generate-token-key`".to_string());
+        }
+        self.token_keys.iter().enumerate().for_each(|(index, token_key)| {
+            if token_key.id.is_empty() || token_key.id.contains(':') {
+                errors.push(format!("token_keys[{}] id must be non empty and without \":\"", index));
+            } else if self.token_keys[..index].iter().any(|other| other.id == token_key.id) {
+                errors.push(format!("token_keys[{}] id {} is used twice", index, token_key.id));
+            }
+            if token_key.key.trim().is_empty() || token_key.key == EXAMPLE_TOKEN_KEY {
+                errors.push(format!("token_keys[{}] key is missing, generate one with `backend generate-token-key`", index));
+            } else if decode_key(&token_key.key).is_none() {
+                errors.push(format!("token_keys[{}] key must be 32 bytes encoded in base64", index));
+            }
+        });
+
+        [("legal.cgu", self.legal.cgu), ("legal.privacy", self.legal.privacy)]
+            .iter()
+            .filter(|(_, version)| *version == 0)
+            .for_each(|(key, _)| errors.push(format!("{} must be greater than 0", key)));
+
+        if !self.metrics_token.is_empty() && self.metrics_token.len() < 32 {
+            errors.push("metrics_token must be at least 32 characters long, leave it empty to disable /metrics".to_string());
+        }
+
+        if !self.discord_public_key.is_empty() && (self.discord_public_key.len() != 64 || hex::decode(&self.discord_public_key).is_err()) {
+            errors.push("discord_public_key must be the 64 hex characters public key of the discord application".to_string());
+        }
+
+        errors
+    }
+
+    /// Source of `config_exemple.json`, regenerate the file with `backend config-example` after changing the struct
+    pub fn example() -> Configuration {
+        Configuration {
+            domain: "intranet.projectvisualis.fr".to_string(),
+            scena_webhook: "https://discord.com/api/webhooks/<id>/<token>".to_string(),
+            bot_token: "<bot token>".to_string(),
+            mongo_db_uri: "mongodb://localhost:27017".to_string(),
+            oauth2client: Oauth2Client {
+                client_id: "<application id>".to_string(),
+                client_secret: "<application secret>".to_string(),
+                redirect_url: "https://intranet.projectvisualis.fr/api/oauth2/callback".to_string(),
+                redirect_url_egui: "https://intranet.projectvisualis.fr".to_string(),
+                ..Oauth2Client::default()
+            },
+            role_mappings: vec![RoleMapping {
+                job: "Security".to_string(),
+                role: Some("Gunsmith".to_string()),
+                rank: None,
+                role_ids: vec!["1031296063056924718".to_string()],
+            }],
+            approval_quorum: ApprovalQuorum {
+                default_approvals: 1,
+                job_families: vec![JobQuorum {
+                    job: "Science".to_string(),
+                    approvals: 2,
+                }],
+            },
+            quotas: QuotaRules {
+                max_characters: Some(3),
+                department_slots: vec![DepartmentSlots {
+                    job: "Security".to_string(),
+                    max: 1,
+                }],
+                job_quotas: vec![JobQuota {
+                    job: "SiteDirector".to_string(),
+                    role: None,
+                    rank: None,
+                    max: 1,
+                }, JobQuota {
+                    job: "Mtf".to_string(),
+                    role: Some("Omega10".to_string()),
+                    rank: None,
+                    max: 8,
+                }],
+            },
+            markdown: MarkdownRules {
+                allowed_image_hosts: vec!["intranet.projectvisualis.fr".to_string(), "i.imgur.com".to_string()],
+                ..MarkdownRules::default()
+            },
+            token_keys: vec![TokenKey {
+                id: "example".to_string(),
+                key: EXAMPLE_TOKEN_KEY.to_string(),
+            }],
+            ..Configuration::default()
+        }
+    }
+}
+
+fn is_snowflake(id: &str) -> bool {
+    !id.is_empty() && id.parse::<u64>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::crypto_utils::generate_token_key;
+
+    use super::*;
+
+    #[test]
+    fn example_file_is_up_to_date() {
+        let example: String = serde_json::to_string_pretty(&Configuration::example()).unwrap();
+        assert_eq!(include_str!("../../../config_exemple.json").trim_end(), example, "run `backend config-example config_exemple.json`");
+    }
+
+    #[test]
+    fn validation_report() {
+        assert_eq!(Configuration::example().validate(), vec!["token_keys[0] key is missing, generate one with `backend generate-token-key`".to_string()]);
+        let mut example: Configuration = Configuration::example();
+        example.token_keys[0].key = generate_token_key();
+        assert!(example.validate().is_empty());
+
+        let mut configuration: Configuration = Configuration::default();
+        configuration.oauth2client.token_url = "https://discord.com/api/oauth2/token/".to_string();
+        configuration.guild_id = "guild".to_string();
+        configuration.quotas.job_quotas = vec![JobQuota { job: "Securite".to_string(), role: None, rank: None, max: 2 }];
+        let errors: Vec<String> = configuration.validate();
+
+        assert!(errors.contains(&"bot_token is missing".to_string()));
+        assert!(errors.contains(&"oauth2client.redirect_url is missing".to_string()));
+        assert!(errors.contains(&"oauth2client.token_url must not end with \"/\"".to_string()));
+        assert!(errors.contains(&"guild_id is not a discord id (guild)".to_string()));
+        assert!(errors.iter().any(|error| error.starts_with("quotas.job_quotas[0] job Securite is not a job family")));
+    }
+}
@@ -0,0 +1,135 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lazy_static::lazy_static;
+use oauth2::basic::BasicTokenResponse;
+use oauth2::{AccessToken, RefreshToken, TokenResponse};
+
+use crate::utils::config_utils::TokenKey;
+use crate::CONFIG;
+
+/// Prefix of the encrypted values, followed by the key id and the base64 of the nonce and ciphertext
+const ENCRYPTED_PREFIX: &str = "enc:";
+const NONCE_LENGTH: usize = 12;
+
+lazy_static! {
+    pub static ref TOKEN_CIPHER: TokenCipher = TokenCipher::new(&CONFIG.token_keys).expect("[ERROR] token_keys checked by the configuration validation");
+}
+
+/// AES-256-GCM over the OAuth tokens stored in the database. The first key encrypts, every key decrypts,
+/// so a new key can be put first and the old one kept until `backend rotate-token-keys` re-encrypted everything
+pub struct TokenCipher {
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl TokenCipher {
+    pub fn new(token_keys: &[TokenKey]) -> Result<TokenCipher> {
+        if token_keys.is_empty() {
+            bail!("no token key");
+        }
+        let keys: Vec<(String, Aes256Gcm)> = token_keys.iter().map(|token_key| {
+            let key: Vec<u8> = decode_key(&token_key.key).ok_or_else(|| anyhow!("token key {} is not 32 bytes of base64", token_key.id))?;
+            Ok((token_key.id.clone(), Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+        }).collect::<Result<_>>()?;
+        Ok(TokenCipher { keys })
+    }
+
+    pub fn encrypt(&self, plain: &str) -> String {
+        let (key_id, cipher) = &self.keys[0];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        // The key id is authenticated, a value can't be moved to another key
+        let ciphertext: Vec<u8> = cipher.encrypt(&nonce, Payload { msg: plain.as_bytes(), aad: key_id.as_bytes() }).expect("AES-GCM encryption can't fail on a token");
+
+        let mut sealed: Vec<u8> = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}:{}", ENCRYPTED_PREFIX, key_id, STANDARD.encode(sealed))
+    }
+
+    /// Values stored before the encryption are returned as is
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encrypted) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let (key_id, sealed) = encrypted.split_once(':').ok_or_else(|| anyhow!("malformed encrypted token"))?;
+        let (_, cipher) = self.keys.iter().find(|(id, _)| id == key_id).ok_or_else(|| anyhow!("unknown token key {}", key_id))?;
+
+        let sealed: Vec<u8> = STANDARD.decode(sealed)?;
+        if sealed.len() < NONCE_LENGTH {
+            bail!("malformed encrypted token");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let plain: Vec<u8> = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: key_id.as_bytes() })
+            .map_err(|_| anyhow!("token can't be decrypted with key {}", key_id))?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    /// Whether the value is encrypted with the current key, otherwise a rotation has to rewrite it
+    pub fn is_current(&self, value: &str) -> bool {
+        value.strip_prefix(ENCRYPTED_PREFIX).and_then(|encrypted| encrypted.split_once(':')).is_some_and(|(key_id, _)| key_id == self.keys[0].0)
+    }
+
+    /// Token as stored: access and refresh tokens encrypted, expiry and scopes left readable for the renewal job
+    pub fn encrypt_token(&self, token: &BasicTokenResponse) -> BasicTokenResponse {
+        let mut encrypted: BasicTokenResponse = token.clone();
+        encrypted.set_access_token(AccessToken::new(self.encrypt(token.access_token().secret())));
+        encrypted.set_refresh_token(token.refresh_token().map(|refresh_token| RefreshToken::new(self.encrypt(refresh_token.secret()))));
+        encrypted
+    }
+
+    pub fn decrypt_token(&self, token: &BasicTokenResponse) -> Result<BasicTokenResponse> {
+        let mut decrypted: BasicTokenResponse = token.clone();
+        decrypted.set_access_token(AccessToken::new(self.decrypt(token.access_token().secret())?));
+        decrypted.set_refresh_token(token.refresh_token().map(|refresh_token| self.decrypt(refresh_token.secret()).map(RefreshToken::new)).transpose()?);
+        Ok(decrypted)
+    }
+
+    pub fn is_token_current(&self, token: &BasicTokenResponse) -> bool {
+        self.is_current(token.access_token().secret()) && token.refresh_token().is_none_or(|refresh_token| self.is_current(refresh_token.secret()))
+    }
+}
+
+/// New random key, printed by `backend generate-token-key`
+pub fn generate_token_key() -> String {
+    STANDARD.encode(Aes256Gcm::generate_key(&mut OsRng))
+}
+
+pub fn decode_key(key: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(key.trim()).ok().filter(|key| key.len() == 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use oauth2::basic::BasicTokenType;
+    use oauth2::EmptyExtraTokenFields;
+
+    use super::*;
+
+    #[test]
+    fn token_encryption() {
+        let old_key: TokenKey = TokenKey { id: "old".to_string(), key: generate_token_key() };
+        let new_key: TokenKey = TokenKey { id: "new".to_string(), key: generate_token_key() };
+        let old_cipher: TokenCipher = TokenCipher::new(&[old_key.clone()]).unwrap();
+        let rotated_cipher: TokenCipher = TokenCipher::new(&[new_key, old_key]).unwrap();
+
+        let mut token: BasicTokenResponse = BasicTokenResponse::new(AccessToken::new("access".to_string()), BasicTokenType::Bearer, EmptyExtraTokenFields {});
+        token.set_refresh_token(Some(RefreshToken::new("refresh".to_string())));
+
+        let encrypted: BasicTokenResponse = old_cipher.encrypt_token(&token);
+        assert!(!encrypted.access_token().secret().contains("access"));
+        assert_ne!(old_cipher.encrypt("access"), old_cipher.encrypt("access"));
+        assert!(old_cipher.is_token_current(&encrypted));
+        assert!(!rotated_cipher.is_token_current(&encrypted));
+        assert!(!old_cipher.is_token_current(&token));
+
+        let decrypted: BasicTokenResponse = rotated_cipher.decrypt_token(&encrypted).unwrap();
+        assert_eq!(decrypted.access_token().secret(), "access");
+        assert_eq!(decrypted.refresh_token().unwrap().secret(), "refresh");
+        assert_eq!(old_cipher.decrypt("plain").unwrap(), "plain");
+
+        let tampered: String = encrypted.access_token().secret().replacen("enc:old:", "enc:new:", 1);
+        assert!(rotated_cipher.decrypt(&tampered).is_err());
+        assert!(TokenCipher::new(&[TokenKey { id: "short".to_string(), key: "AAAA".to_string() }]).is_err());
+    }
+}
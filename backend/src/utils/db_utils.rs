@@ -0,0 +1,658 @@
+use log::{info, warn};
+use mongodb::bson::{doc, to_bson, Bson, Document, Regex};
+use mongodb::{Collection, IndexModel};
+use oauth2::TokenResponse;
+use serenity::futures::TryStreamExt;
+use uuid::Uuid;
+
+use shared::audit::AuditEntry;
+use shared::fiche_rp::{FicheImage, FicheRP, ImageKind, FicheState, LifecycleChange, MessageDeletion, MessageEdit, ReviewClaim, ReviewMessage, ReviewVote, CLAIMABLE_STATES};
+use shared::legal::TermsAcceptance;
+use shared::mentions::MentionNotification;
+use shared::search::{index_terms, shorter_matches};
+use shared::user::{Account, AuthSession, FrontAccount};
+use shared::website_meta::WebsiteMeta;
+
+use crate::utils::auth_utils::SESSION_LIFETIME_SECS;
+use crate::utils::crypto_utils::TOKEN_CIPHER;
+
+pub const SCHEMA_VERSION: u32 = 6;
+/// Sessions kept per account, logging in on one more device drops the oldest one
+pub const MAX_SESSIONS: i32 = 10;
+
+pub fn account_collection<T: Send + Sync>(dbclient: &mongodb::Client) -> Collection<T> {
+    dbclient.database("visualis-website").collection("account")
+}
+
+pub fn meta_collection(dbclient: &mongodb::Client) -> Collection<WebsiteMeta> {
+    dbclient.database("visualis-website").collection("website-meta")
+}
+
+pub fn notification_collection(dbclient: &mongodb::Client) -> Collection<MentionNotification> {
+    dbclient.database("visualis-website").collection("notification")
+}
+
+pub fn audit_collection(dbclient: &mongodb::Client) -> Collection<AuditEntry> {
+    dbclient.database("visualis-website").collection("audit")
+}
+
+pub async fn get_website_meta(dbclient: &mongodb::Client) -> WebsiteMeta {
+    meta_collection(dbclient).find_one(Document::new()).await.expect("Can't retrieve website meta").unwrap_or_default()
+}
+
+pub async fn find_fiche_owner(dbclient: &mongodb::Client, fiche_id: &str) -> mongodb::error::Result<Option<FrontAccount>> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    account_collection::<FrontAccount>(dbclient).find_one(query).await
+}
+
+pub async fn whitelist_add(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<()> {
+    let update = doc! {
+        "$addToSet": { "whitelist": discord_id }
+    };
+    meta_collection(dbclient).update_one(Document::new(), update).upsert(true).await?;
+    Ok(())
+}
+
+pub async fn whitelist_remove(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<()> {
+    let update = doc! {
+        "$pull": { "whitelist": discord_id }
+    };
+    meta_collection(dbclient).update_one(Document::new(), update).await?;
+    Ok(())
+}
+
+/// Pushes a review message on a fiche, the fiche state follows the message unless it is a plain comment.
+/// Returns false when no fiche matched.
+pub async fn push_review_message(dbclient: &mongodb::Client, fiche_id: &str, review_message: &ReviewMessage) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, review_message_update(review_message)).await?.matched_count > 0)
+}
+
+/// Update of `push_review_message`. Any change of state starts a new review round, the votes of the previous one are dropped
+fn review_message_update(review_message: &ReviewMessage) -> Document {
+    match review_message.set_state {
+        FicheState::Comment => doc! {
+            "$push": {"fiches.$.messages": to_bson(review_message).unwrap()}
+        },
+        // A final decision ends the review, the claim has nothing left to protect
+        FicheState::Accepted | FicheState::Refused => doc! {
+            "$set": {"fiches.$.state": to_bson(&review_message.set_state).unwrap(), "fiches.$.claim": null, "fiches.$.votes": []},
+            "$push": {"fiches.$.messages": to_bson(review_message).unwrap()}
+        },
+        _ => doc! {
+            "$set": {"fiches.$.state": to_bson(&review_message.set_state).unwrap(), "fiches.$.votes": []},
+            "$push": {"fiches.$.messages": to_bson(review_message).unwrap()}
+        },
+    }
+}
+
+/// Replaces the content of a message of its author, the previous content goes to the edit history.
+/// Returns false when no message matched (unknown, deleted or of another author).
+pub async fn edit_review_message(dbclient: &mongodb::Client, fiche_id: &str, message_id: &str, author_id: &str, content: &str, edit: &MessageEdit) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$set": {"fiches.$[fiche].messages.$[message].content": content, "fiches.$[fiche].messages.$[message].search_terms": index_terms(&[content])},
+        "$push": {"fiches.$[fiche].messages.$[message].edits": to_bson(edit).unwrap()}
+    };
+    let array_filters = vec![
+        doc! {"fiche.id": fiche_id},
+        doc! {"message.id": message_id, "message.discord_id": author_id, "message.deletion": null},
+    ];
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).array_filters(array_filters).await?.modified_count > 0)
+}
+
+/// Soft deletion, the message stays in its thread. Returns false when no message matched (unknown or already deleted).
+pub async fn delete_review_message(dbclient: &mongodb::Client, fiche_id: &str, message_id: &str, deletion: &MessageDeletion) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$set": {"fiches.$[fiche].messages.$[message].deletion": to_bson(deletion).unwrap(), "fiches.$[fiche].messages.$[message].search_terms": []}
+    };
+    let array_filters = vec![
+        doc! {"fiche.id": fiche_id},
+        doc! {"message.id": message_id, "message.deletion": null},
+    ];
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).array_filters(array_filters).await?.modified_count > 0)
+}
+
+/// Accounts with a fiche matching every term, through the indexes of the search terms. The fiches of a match still have to be
+/// checked one by one: the terms may be spread over several fiches, drafts and hidden archives are not filtered here
+pub async fn find_search_candidates(dbclient: &mongodb::Client, terms: &[String], include_messages: bool) -> mongodb::error::Result<Vec<FrontAccount>> {
+    account_collection::<FrontAccount>(dbclient).find(search_filter(terms, include_messages)).await?.try_collect().await
+}
+
+fn search_filter(terms: &[String], include_messages: bool) -> Document {
+    let term_filters: Vec<Document> = terms.iter().map(|term| {
+        // Words starting with the term, the anchored regex is answered by the index, and the shorter words it still matches
+        let mut words: Vec<Bson> = vec![Bson::RegularExpression(Regex { pattern: format!("^{}", term), options: String::new() })];
+        words.extend(shorter_matches(term).into_iter().map(Bson::from));
+        if include_messages {
+            doc! {"$or": [{"fiches.search_terms": {"$in": words.clone()}}, {"fiches.messages.search_terms": {"$in": words}}]}
+        } else {
+            doc! {"fiches.search_terms": {"$in": words}}
+        }
+    }).collect();
+    doc! {
+        "$and": term_filters
+    }
+}
+
+/// A gallery image is only added while the gallery has fewer than `max_gallery_images` images,
+/// the count is checked by the update itself so concurrent uploads can't go over it
+pub async fn push_fiche_image(dbclient: &mongodb::Client, fiche_id: &str, image: &FicheImage, max_gallery_images: usize) -> mongodb::error::Result<bool> {
+    let mut query = doc! {
+        "fiches.id": fiche_id
+    };
+    if image.kind == ImageKind::Gallery {
+        query.insert("$expr", doc! {
+            "$anyElementTrue": [{"$map": {
+                "input": "$fiches",
+                "as": "fiche",
+                "in": {"$and": [
+                    {"$eq": ["$$fiche.id", fiche_id]},
+                    {"$lt": [{"$size": {"$filter": {"input": "$$fiche.images", "as": "image", "cond": {"$eq": ["$$image.kind", ImageKind::Gallery.as_ref()]}}}}, max_gallery_images as i64]}
+                ]}
+            }}]
+        });
+    }
+    let update = doc! {
+        "$push": {"fiches.$.images": to_bson(image).unwrap()}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+pub async fn remove_fiche_image(dbclient: &mongodb::Client, fiche_id: &str, image_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$pull": {"fiches.$.images": {"id": image_id}}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+pub async fn set_fiche_state(dbclient: &mongodb::Client, fiche_id: &str, state: &FicheState) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$set": {"fiches.$.state": to_bson(state).unwrap()}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+pub async fn push_lifecycle_change(dbclient: &mongodb::Client, fiche_id: &str, change: &LifecycleChange) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$push": {"fiches.$.lifecycle": to_bson(change).unwrap()}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+/// Updates the content of a draft of the account, submitted fiches never match. Returns false when no draft matched.
+pub async fn update_draft(dbclient: &mongodb::Client, auth_id: &str, draft: &FicheRP) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "sessions.auth_id": auth_id,
+        "fiches": {
+            "$elemMatch": {"id": &draft.id, "state": to_bson(&FicheState::Draft).unwrap()}
+        }
+    };
+    let update = doc! {
+        "$set": {
+            "fiches.$.name": &draft.name,
+            "fiches.$.job": to_bson(&draft.job).unwrap(),
+            "fiches.$.description": &draft.description,
+            "fiches.$.lore": &draft.lore,
+            "fiches.$.submission_date": to_bson(&draft.submission_date).unwrap(),
+        }
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+pub async fn delete_draft(dbclient: &mongodb::Client, auth_id: &str, fiche_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "sessions.auth_id": auth_id
+    };
+    let update = doc! {
+        "$pull": {"fiches": {"id": fiche_id, "state": to_bson(&FicheState::Draft).unwrap()}}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+/// Replaces the votes of a fiche along with the state they lead to.
+/// `expected_state` guards against a decision taken between the read of the fiche and this update.
+pub async fn set_fiche_votes(dbclient: &mongodb::Client, fiche_id: &str, votes: &Vec<ReviewVote>, expected_state: &FicheState, state: &FicheState) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches": {
+            "$elemMatch": {"id": fiche_id, "state": to_bson(expected_state).unwrap()}
+        }
+    };
+    let update = doc! {
+        "$set": {"fiches.$.votes": to_bson(votes).unwrap(), "fiches.$.state": to_bson(state).unwrap()}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+/// Sets the claim of a fiche in a claimable state. Unless `force` is set (lead assignment), a still active claim of
+/// another reviewer is kept and false is returned.
+pub async fn claim_fiche(dbclient: &mongodb::Client, fiche_id: &str, claim: &ReviewClaim, force: bool) -> mongodb::error::Result<bool> {
+    let query = if force {
+        doc! {
+            "fiches": {
+                "$elemMatch": {
+                    "id": fiche_id,
+                    "state": {"$in": to_bson(&CLAIMABLE_STATES).unwrap()}
+                }
+            }
+        }
+    } else {
+        doc! {
+            "fiches": {
+                "$elemMatch": {
+                    "id": fiche_id,
+                    "state": {"$in": to_bson(&CLAIMABLE_STATES).unwrap()},
+                    "$or": [
+                        {"claim": null},
+                        {"claim.expires_at": {"$lte": to_bson(&claim.claimed_at).unwrap()}},
+                        {"claim.discord_id": &claim.discord_id}
+                    ]
+                }
+            }
+        }
+    };
+    let update = doc! {
+        "$set": {"fiches.$.claim": to_bson(claim).unwrap()}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+pub async fn release_claim(dbclient: &mongodb::Client, fiche_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "fiches.id": fiche_id
+    };
+    let update = doc! {
+        "$set": {"fiches.$.claim": null}
+    };
+    Ok(account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+pub async fn insert_notifications(dbclient: &mongodb::Client, notifications: &Vec<MentionNotification>) -> mongodb::error::Result<()> {
+    if notifications.is_empty() {
+        return Ok(());
+    }
+    notification_collection(dbclient).insert_many(notifications).await?;
+    Ok(())
+}
+
+/// Latest notifications of a user, newest first
+pub async fn find_notifications(dbclient: &mongodb::Client, discord_id: &str, limit: i64) -> mongodb::error::Result<Vec<MentionNotification>> {
+    let query = doc! {
+        "discord_id": discord_id
+    };
+    notification_collection(dbclient).find(query).sort(doc! {"date": -1}).limit(limit).await?.try_collect().await
+}
+
+pub async fn mark_notifications_read(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<u64> {
+    let query = doc! {
+        "discord_id": discord_id,
+        "read": false
+    };
+    let update = doc! {
+        "$set": {"read": true}
+    };
+    Ok(notification_collection(dbclient).update_many(query, update).await?.modified_count)
+}
+
+/// Banned accounts can't authenticate anymore, their sessions are revoked so the cookies die immediately.
+pub async fn set_account_banned(dbclient: &mongodb::Client, discord_id: &String, banned: bool) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let update = doc! {
+        "$set": {"banned": banned}
+    };
+    let matched: bool = account_collection::<FrontAccount>(dbclient).update_one(query, update).await?.matched_count > 0;
+
+    if matched && banned {
+        revoke_sessions(dbclient, discord_id).await?;
+    }
+    Ok(matched)
+}
+
+pub async fn record_sync_success(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let update = doc! {
+        "$set": {"sync_failures": 0, "next_sync": 0}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+/// The account is skipped by the background sync until `next_sync`
+pub async fn record_sync_failure(dbclient: &mongodb::Client, discord_id: &str, failures: u32, next_sync: u64, needs_relogin: bool) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let update = doc! {
+        "$set": {"sync_failures": failures, "next_sync": next_sync as i64, "needs_relogin": needs_relogin}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+pub async fn set_needs_relogin(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let update = doc! {
+        "$set": {"needs_relogin": true}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+/// Account owning the session, expired sessions included
+pub async fn find_session_account(dbclient: &mongodb::Client, auth_id: &str) -> mongodb::error::Result<Option<Account>> {
+    let query = doc! {
+        "sessions.auth_id": auth_id
+    };
+    account_collection::<Account>(dbclient).find_one(query).await
+}
+
+/// Adds a session to the account, dropping its expired sessions and the oldest ones above `MAX_SESSIONS`
+pub async fn add_session(dbclient: &mongodb::Client, discord_id: &str, session: &AuthSession, time_now: u64) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let cleanup = doc! {
+        "$pull": {"sessions": {"expires_at": {"$lte": time_now as i64}}}
+    };
+    account_collection::<Account>(dbclient).update_one(query.clone(), cleanup).await?;
+
+    let update = doc! {
+        "$push": {"sessions": {"$each": [to_bson(session).unwrap()], "$slice": -MAX_SESSIONS}}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+/// Logout, removes the session behind the cookie
+pub async fn remove_session(dbclient: &mongodb::Client, auth_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "sessions.auth_id": auth_id
+    };
+    let update = doc! {
+        "$pull": {"sessions": {"auth_id": auth_id}}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+/// Revokes one session of the account owning `auth_id`, a session of another account never matches
+pub async fn revoke_session(dbclient: &mongodb::Client, auth_id: &str, session_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "sessions.auth_id": auth_id
+    };
+    let update = doc! {
+        "$pull": {"sessions": {"id": session_id}}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0)
+}
+
+/// Logs the account out of every device. Returns false when no account matched.
+pub async fn revoke_sessions(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    let update = doc! {
+        "$set": {"sessions": []}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+/// Records the acceptance of the legal texts by the account owning `auth_id`, earlier acceptances are kept
+pub async fn record_terms_acceptance(dbclient: &mongodb::Client, auth_id: &str, acceptance: &TermsAcceptance) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "sessions.auth_id": auth_id
+    };
+    let update = doc! {
+        "$push": {"terms_acceptances": to_bson(acceptance).unwrap()}
+    };
+    Ok(account_collection::<Account>(dbclient).update_one(query, update).await?.matched_count > 0)
+}
+
+/// Terms acceptances of an account, oldest first. They are not part of `FrontAccount`
+pub async fn find_terms_acceptances(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<Vec<TermsAcceptance>> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    Ok(account_collection::<Account>(dbclient).find_one(query).await?.map(|account| account.terms_acceptances).unwrap_or_default())
+}
+
+/// Replaces `discord_id` by `replacement_id` wherever it appears on the fiches of other accounts: message authors and
+/// deletions, votes, lifecycle changes and assignments. Its claims are released. Every update can run again after a failure
+pub async fn anonymise_account_references(dbclient: &mongodb::Client, discord_id: &str, replacement_id: &str) -> mongodb::error::Result<()> {
+    let updates = [
+        ("fiches.messages.discord_id", doc! {"$set": {"fiches.$[].messages.$[message].discord_id": replacement_id}}, doc! {"message.discord_id": discord_id}),
+        ("fiches.messages.deletion.discord_id", doc! {"$set": {"fiches.$[].messages.$[message].deletion.discord_id": replacement_id}}, doc! {"message.deletion.discord_id": discord_id}),
+        ("fiches.votes.discord_id", doc! {"$set": {"fiches.$[].votes.$[vote].discord_id": replacement_id}}, doc! {"vote.discord_id": discord_id}),
+        ("fiches.lifecycle.set_by", doc! {"$set": {"fiches.$[].lifecycle.$[change].set_by": replacement_id}}, doc! {"change.set_by": discord_id}),
+        ("fiches.claim.assigned_by", doc! {"$set": {"fiches.$[fiche].claim.assigned_by": replacement_id}}, doc! {"fiche.claim.assigned_by": discord_id}),
+        // A deleted reviewer won't review the fiche, it goes back to the queue
+        ("fiches.claim.discord_id", doc! {"$set": {"fiches.$[fiche].claim": null}}, doc! {"fiche.claim.discord_id": discord_id}),
+    ];
+
+    for (field, update, array_filter) in updates {
+        account_collection::<FrontAccount>(dbclient).update_many(doc! {field: discord_id}, update).array_filters(vec![array_filter]).await?;
+    }
+    Ok(())
+}
+
+/// Deletes the notifications received by `discord_id` and anonymises the ones it caused, returns how many were deleted
+pub async fn forget_notifications(dbclient: &mongodb::Client, discord_id: &str, replacement_id: &str) -> mongodb::error::Result<u64> {
+    let deleted: u64 = notification_collection(dbclient).delete_many(doc! {"discord_id": discord_id}).await?.deleted_count;
+
+    let query = doc! {
+        "author_id": discord_id
+    };
+    let update = doc! {
+        "$set": {"author_id": replacement_id}
+    };
+    notification_collection(dbclient).update_many(query, update).await?;
+    Ok(deleted)
+}
+
+/// Removes the account document, its sessions and OAuth token go with it
+pub async fn delete_account_document(dbclient: &mongodb::Client, discord_id: &str) -> mongodb::error::Result<bool> {
+    let query = doc! {
+        "discord_user.id": discord_id
+    };
+    Ok(account_collection::<Account>(dbclient).delete_one(query).await?.deleted_count > 0)
+}
+
+pub async fn insert_audit_entry(dbclient: &mongodb::Client, entry: &AuditEntry) -> mongodb::error::Result<()> {
+    audit_collection(dbclient).insert_one(entry).await?;
+    Ok(())
+}
+
+/// Rewrites the OAuth tokens not encrypted with the current key, returns how many were rewritten.
+/// Tokens that can't be decrypted anymore are left as is, their owner logs in again once they expire
+pub async fn reencrypt_tokens(dbclient: &mongodb::Client) -> mongodb::error::Result<usize> {
+    let accounts: Vec<Account> = account_collection::<Account>(dbclient).find(Document::new()).await?.try_collect().await?;
+    let mut rewritten: usize = 0;
+
+    for account in accounts.iter().filter(|account| !TOKEN_CIPHER.is_token_current(&account.token)) {
+        let token = match TOKEN_CIPHER.decrypt_token(&account.token) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("Can't decrypt the token of {}: {}", account.discord_user.id, err);
+                continue;
+            }
+        };
+
+        // A renewal may have written a new token meanwhile, it must not be replaced by the old one
+        let query = doc! {
+            "discord_user.id": &account.discord_user.id,
+            "token.access_token": account.token.access_token().secret()
+        };
+        let update = doc! {
+            "$set": {"token": to_bson(&TOKEN_CIPHER.encrypt_token(&token)).unwrap()}
+        };
+        if account_collection::<Account>(dbclient).update_one(query, update).await?.modified_count > 0 {
+            rewritten += 1;
+        }
+    }
+    Ok(rewritten)
+}
+
+/// Applies every migration above the schema version stored in the website meta, returns the new version.
+pub async fn migrate(dbclient: &mongodb::Client) -> mongodb::error::Result<u32> {
+    let meta: Option<WebsiteMeta> = meta_collection(dbclient).find_one(Document::new()).await?;
+    let mut version: u32 = meta.map(|meta| meta.schema_version).unwrap_or(0);
+
+    while version < SCHEMA_VERSION {
+        match version + 1 {
+            // website-meta is expected to exist by every handler
+            1 => {
+                let update = doc! {
+                    "$setOnInsert": {"whitelist": []}
+                };
+                meta_collection(dbclient).update_one(Document::new(), update).upsert(true).await?;
+            }
+            // Replies, edits and deletions address the review messages by id
+            2 => {
+                let accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+                for mut account in accounts {
+                    let mut is_changed: bool = false;
+                    account.fiches.iter_mut().flat_map(|fiche| fiche.messages.iter_mut()).filter(|message| message.id.is_empty()).for_each(|message| {
+                        message.id = Uuid::now_v7().to_string();
+                        is_changed = true;
+                    });
+                    if !is_changed {
+                        continue;
+                    }
+
+                    let query = doc! {
+                        "discord_user.id": &account.discord_user.id
+                    };
+                    let update = doc! {
+                        "$set": {"fiches": to_bson(&account.fiches).unwrap()}
+                    };
+                    account_collection::<FrontAccount>(dbclient).update_one(query, update).await?;
+                }
+            }
+            // The notification bell lists the latest notifications of a user
+            3 => {
+                let index = IndexModel::builder()
+                    .keys(doc! {"discord_id": 1, "date": -1})
+                    .build();
+                notification_collection(dbclient).create_index(index).await?;
+            }
+            // OAuth tokens are encrypted at rest
+            4 => {
+                let rewritten: usize = reencrypt_tokens(dbclient).await?;
+                info!("{} OAuth token(s) encrypted", rewritten);
+            }
+            // The single auth_id of each account becomes its first session, accounts are looked up by session
+            5 => {
+                let legacy_query = doc! {
+                    "auth_id": {"$exists": true}
+                };
+                let legacy_accounts: Vec<Document> = account_collection::<Document>(dbclient).find(legacy_query).await?.try_collect().await?;
+                for account in legacy_accounts {
+                    // The cookie was set at the last login, which also renewed the token
+                    let last_renewal: u64 = account.get_i64("last_renewal").unwrap_or_default() as u64;
+                    let sessions: Vec<AuthSession> = account.get_str("auth_id").ok().filter(|auth_id| !auth_id.is_empty()).map(|auth_id| AuthSession {
+                        id: Uuid::now_v7().to_string(),
+                        auth_id: auth_id.to_string(),
+                        created_at: last_renewal,
+                        expires_at: last_renewal + SESSION_LIFETIME_SECS,
+                        user_agent: String::new(),
+                    }).into_iter().collect();
+
+                    let query = doc! {
+                        "_id": account.get_object_id("_id").unwrap()
+                    };
+                    let update = doc! {
+                        "$set": {"sessions": to_bson(&sessions).unwrap()},
+                        "$unset": {"auth_id": ""}
+                    };
+                    account_collection::<Document>(dbclient).update_one(query, update).await?;
+                }
+
+                let index = IndexModel::builder()
+                    .keys(doc! {"sessions.auth_id": 1})
+                    .build();
+                account_collection::<Account>(dbclient).create_index(index).await?;
+            }
+            // The search looks the folded words of the fiches up in these indexes, the staff searches the review messages too
+            6 => {
+                let accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+                for mut account in accounts {
+                    account.fiches.iter_mut().for_each(FicheRP::refresh_search_terms);
+
+                    let query = doc! {
+                        "discord_user.id": &account.discord_user.id
+                    };
+                    let update = doc! {
+                        "$set": {"fiches": to_bson(&account.fiches).unwrap()}
+                    };
+                    account_collection::<FrontAccount>(dbclient).update_one(query, update).await?;
+                }
+
+                let indexes = vec![
+                    IndexModel::builder().keys(doc! {"fiches.search_terms": 1}).build(),
+                    IndexModel::builder().keys(doc! {"fiches.messages.search_terms": 1}).build(),
+                ];
+                account_collection::<FrontAccount>(dbclient).create_indexes(indexes).await?;
+            }
+            _ => unreachable!(),
+        }
+        version += 1;
+
+        let update = doc! {
+            "$set": {"schema_version": version}
+        };
+        meta_collection(dbclient).update_one(Document::new(), update).upsert(true).await?;
+        info!("Database migrated to schema version {}", version);
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn decisions_clear_votes() {
+        assert!(review_message_update(&fixtures::review_message("1", FicheState::Comment)).get("$set").is_none());
+        for state in [FicheState::RequestModification, FicheState::Waiting, FicheState::Accepted, FicheState::Refused] {
+            let update: Document = review_message_update(&fixtures::review_message("1", state));
+            assert_eq!(update.get_document("$set").unwrap().get_array("fiches.$.votes").unwrap(), &vec![]);
+        }
+    }
+    #[test]
+    fn search_lookup() {
+        let terms: Vec<String> = vec!["eloise".to_string(), "personnages".to_string()];
+        let filter: Document = search_filter(&terms, false);
+        let term_filters = filter.get_array("$and").unwrap();
+        assert_eq!(term_filters.len(), 2);
+
+        let words = term_filters[1].as_document().unwrap().get_document("fiches.search_terms").unwrap().get_array("$in").unwrap();
+        assert_eq!(words[0], Bson::RegularExpression(Regex { pattern: "^personnages".to_string(), options: String::new() }));
+        assert_eq!(words[1..], [Bson::from("personnage"), Bson::from("personnag")]);
+
+        // The staff also finds the fiches through their review messages
+        let staff_filter: Document = search_filter(&terms, true);
+        let alternatives = staff_filter.get_array("$and").unwrap()[0].as_document().unwrap().get_array("$or").unwrap();
+        assert!(alternatives[1].as_document().unwrap().contains_key("fiches.messages.search_terms"));
+    }
+}
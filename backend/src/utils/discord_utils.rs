@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+
+use log::{error, info, warn};
+use mongodb::bson::Document;
+use mongodb::Collection;
+use serde::Serialize;
+use serenity::all::{GuildId, Http, RoleId, UserId};
+use serenity::futures::TryStreamExt;
+
+use shared::fiche_rp::{FicheRP, Job};
+use shared::user::FrontAccount;
+
+use crate::CONFIG;
+
+#[derive(Serialize)]
+pub struct RoleDrift {
+    pub discord_id: String,
+    pub global_name: String,
+    pub missing: Vec<String>,
+    pub unexpected: Vec<String>,
+}
+
+pub fn mapped_role_ids(job: &Job) -> HashSet<String> {
+    CONFIG.role_mappings.iter()
+          .filter(|mapping| mapping.matches(job))
+          .flat_map(|mapping| mapping.role_ids.clone())
+          .collect()
+}
+
+pub fn expected_role_ids(fiches: &Vec<FicheRP>) -> HashSet<String> {
+    fiches.iter()
+          .filter(|fiche| fiche.is_active_character())
+          .flat_map(|fiche| mapped_role_ids(&fiche.job))
+          .collect()
+}
+
+/// Role update owed to the owner when a fiche changes, only characters becoming or ceasing to be active matter
+#[derive(Debug, PartialEq)]
+pub enum RoleChange {
+    Grant,
+    Revoke,
+}
+
+pub fn role_change(before: &FicheRP, after: &FicheRP) -> Option<RoleChange> {
+    match (before.is_active_character(), after.is_active_character()) {
+        (false, true) => Some(RoleChange::Grant),
+        (true, false) => Some(RoleChange::Revoke),
+        _ => None,
+    }
+}
+
+/// Grants or revokes the roles of a fiche after a change of its state or of its character status.
+/// `owner_fiches` are the fiches of the owner before the change.
+pub async fn sync_fiche_roles(discord_id: String, before: &FicheRP, after: FicheRP, owner_fiches: Vec<FicheRP>) {
+    match role_change(before, &after) {
+        Some(RoleChange::Grant) => grant_fiche_roles(discord_id, after).await,
+        Some(RoleChange::Revoke) => revoke_fiche_roles(discord_id, after, owner_fiches).await,
+        None => {}
+    }
+}
+
+pub async fn grant_fiche_roles(discord_id: String, fiche: FicheRP) {
+    actix_rt::spawn(async move {
+        add_fiche_roles(&discord_id, &fiche).await;
+    });
+}
+
+/// Removes the roles given by `fiche`, except the ones still justified by another accepted fiche of the owner.
+pub async fn revoke_fiche_roles(discord_id: String, fiche: FicheRP, owner_fiches: Vec<FicheRP>) {
+    actix_rt::spawn(async move {
+        remove_fiche_roles(&discord_id, &fiche, &owner_fiches).await;
+    });
+}
+
+pub async fn add_fiche_roles(discord_id: &String, fiche: &FicheRP) {
+    let (Some(guild_id), Some(user_id)) = (parse_snowflake(&CONFIG.guild_id), parse_snowflake(discord_id)) else {
+        error!("Can't grant roles, invalid guild or user id ({})", discord_id);
+        return;
+    };
+    let http: Http = Http::new(&CONFIG.bot_token);
+
+    for role_id in mapped_role_ids(&fiche.job) {
+        if let Some(role) = parse_snowflake(&role_id) {
+            match http.add_member_role(GuildId::new(guild_id), UserId::new(user_id), RoleId::new(role), Some("FicheRP acceptée")).await {
+                Ok(_) => info!("Granted role {} to {} for fiche {}", role_id, discord_id, fiche.id),
+                Err(err) => error!("Can't grant role {} to {}: {}", role_id, discord_id, err),
+            }
+        }
+    }
+}
+
+pub async fn remove_fiche_roles(discord_id: &String, fiche: &FicheRP, owner_fiches: &Vec<FicheRP>) {
+    let (Some(guild_id), Some(user_id)) = (parse_snowflake(&CONFIG.guild_id), parse_snowflake(discord_id)) else {
+        error!("Can't revoke roles, invalid guild or user id ({})", discord_id);
+        return;
+    };
+    let http: Http = Http::new(&CONFIG.bot_token);
+
+    let other_fiches: Vec<FicheRP> = owner_fiches.iter().filter(|owner_fiche| owner_fiche.id != fiche.id).cloned().collect();
+    let still_expected: HashSet<String> = expected_role_ids(&other_fiches);
+
+    for role_id in mapped_role_ids(&fiche.job).difference(&still_expected) {
+        if let Some(role) = parse_snowflake(role_id) {
+            match http.remove_member_role(GuildId::new(guild_id), UserId::new(user_id), RoleId::new(role), Some("FicheRP retirée")).await {
+                Ok(_) => info!("Revoked role {} from {} for fiche {}", role_id, discord_id, fiche.id),
+                Err(err) => error!("Can't revoke role {} from {}: {}", role_id, discord_id, err),
+            }
+        }
+    }
+}
+
+/// Compares the mapped roles expected from accepted fiches with the ones members actually hold in the guild.
+/// Only roles present in the configured mappings are considered.
+pub async fn compute_role_drift(dbclient: mongodb::Client) -> anyhow::Result<Vec<RoleDrift>> {
+    let guild_id: u64 = parse_snowflake(&CONFIG.guild_id).ok_or_else(|| anyhow::anyhow!("invalid guild id {}", CONFIG.guild_id))?;
+    let managed: HashSet<String> = CONFIG.role_mappings.iter().flat_map(|mapping| mapping.role_ids.clone()).collect();
+
+    let accounts: Collection<FrontAccount> = dbclient.database("visualis-website").collection("account");
+    let all_accounts: Vec<FrontAccount> = accounts.find(Document::new()).await?.try_collect().await?;
+
+    let http: Http = Http::new(&CONFIG.bot_token);
+    let mut drifts: Vec<RoleDrift> = vec![];
+
+    for account in all_accounts {
+        let Some(user_id) = parse_snowflake(&account.discord_user.id) else {
+            continue;
+        };
+
+        let actual: HashSet<String> = match http.get_member(GuildId::new(guild_id), UserId::new(user_id)).await {
+            Ok(member) => member.roles.iter().map(|role| role.to_string()).filter(|role| managed.contains(role)).collect(),
+            Err(err) => {
+                warn!("Can't retrieve guild member {}({}): {}", account.discord_user.global_name, account.discord_user.id, err);
+                continue;
+            }
+        };
+        let expected: HashSet<String> = expected_role_ids(&account.fiches);
+
+        if expected != actual {
+            drifts.push(RoleDrift {
+                discord_id: account.discord_user.id.clone(),
+                global_name: account.discord_user.global_name.clone(),
+                missing: expected.difference(&actual).cloned().collect(),
+                unexpected: actual.difference(&expected).cloned().collect(),
+            });
+        }
+    }
+    Ok(drifts)
+}
+
+fn parse_snowflake(id: &str) -> Option<u64> {
+    id.parse::<u64>().ok().filter(|id| *id != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::fiche_rp::{CharacterStatus, FicheState, LifecycleChange};
+    use shared::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn role_changes() {
+        let accepted: FicheRP = fixtures::fiche(FicheState::Accepted);
+        let mut archived: FicheRP = accepted.clone();
+        archived.lifecycle.push(LifecycleChange { status: CharacterStatus::Archived, reason: "Inactif".to_string(), date: 10, set_by: "2".to_string() });
+        let mut retired: FicheRP = accepted.clone();
+        retired.lifecycle.push(LifecycleChange { status: CharacterStatus::Retired, reason: "Départ".to_string(), date: 10, set_by: "1".to_string() });
+
+        assert_eq!(role_change(&fixtures::fiche(FicheState::Waiting), &accepted), Some(RoleChange::Grant));
+        assert_eq!(role_change(&accepted, &FicheRP { state: FicheState::RequestModification, ..accepted.clone() }), Some(RoleChange::Revoke));
+        assert_eq!(role_change(&accepted, &archived), Some(RoleChange::Revoke));
+        assert_eq!(role_change(&retired, &archived), None);
+        // A retired character accepted again after modifications stays without roles
+        assert_eq!(role_change(&FicheRP { state: FicheState::StaffValidated, ..retired.clone() }, &retired), None);
+        assert_eq!(role_change(&fixtures::fiche(FicheState::Waiting), &fixtures::fiche(FicheState::Refused)), None);
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use mongodb::bson::{doc, Document};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use shared::events::{EventScope, LiveEvent, LiveEventKind};
+use shared::user::FrontAccount;
+
+use crate::utils::auth_utils::is_staff;
+use crate::utils::db_utils::{account_collection, meta_collection};
+
+/// Events kept for the slowest client before it lags and gets a `Resync`
+const EVENTS_CAPACITY: usize = 256;
+/// Proxies close idle connections, a comment line keeps the stream alive
+const HEARTBEAT_SECS: u64 = 30;
+
+lazy_static! {
+    static ref LIVE_EVENTS: broadcast::Sender<LiveEvent> = broadcast::channel(EVENTS_CAPACITY).0;
+}
+
+pub fn publish_event(kind: LiveEventKind, owner_id: &str, fiche_id: &str, scope: EventScope) {
+    // Fails only when nobody is connected
+    let _ = LIVE_EVENTS.send(LiveEvent::new(kind, owner_id, fiche_id, scope));
+}
+
+/// Server-Sent Events stream of the events `discord_id` is allowed to see, it ends with the client connection.
+/// The session is checked again on every heartbeat: the stream ends on logout, revocation or ban,
+/// and a promotion or a demotion applies without reconnecting
+pub fn event_stream(dbclient: mongodb::Client, auth_id: String, discord_id: String, is_staff: bool) -> impl Stream<Item=Result<Bytes, actix_web::Error>> {
+    let is_staff: Arc<AtomicBool> = Arc::new(AtomicBool::new(is_staff));
+
+    let events_is_staff: Arc<AtomicBool> = is_staff.clone();
+    let events = futures::stream::unfold(LIVE_EVENTS.subscribe(), move |mut receiver| {
+        let discord_id: String = discord_id.clone();
+        let is_staff: Arc<AtomicBool> = events_is_staff.clone();
+        async move {
+            loop {
+                let event: LiveEvent = match receiver.recv().await {
+                    Ok(event) if event.is_visible_to(&discord_id, is_staff.load(Ordering::Relaxed)) => event,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => LiveEvent::new(LiveEventKind::Resync, &discord_id, "", EventScope::Owner),
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Some(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&event).unwrap()))), receiver));
+            }
+        }
+    });
+
+    // `None` marks the end of the session
+    let heartbeat = futures::stream::unfold(actix_rt::time::interval(Duration::from_secs(HEARTBEAT_SECS)), move |mut interval| {
+        let (dbclient, auth_id, is_staff) = (dbclient.clone(), auth_id.clone(), is_staff.clone());
+        async move {
+            interval.tick().await;
+            match session_status(&dbclient, &auth_id).await {
+                Ok(Some(still_staff)) => is_staff.store(still_staff, Ordering::Relaxed),
+                Ok(None) => return Some((None, interval)),
+                // The previous status is kept when the database can't be read
+                Err(_) => {}
+            }
+            Some((Some(Bytes::from_static(b": heartbeat\n\n")), interval))
+        }
+    });
+
+    futures::stream::select(events, heartbeat)
+        .take_while(|bytes| futures::future::ready(bytes.is_some()))
+        .filter_map(|bytes| futures::future::ready(bytes.map(Ok)))
+}
+
+/// Staff status of the account behind `auth_id`, `None` once the session is gone or the account banned
+async fn session_status(dbclient: &mongodb::Client, auth_id: &str) -> mongodb::error::Result<Option<bool>> {
+    let time_now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).expect("invalid time").as_secs();
+    let query = doc! {
+        "sessions" : { "$elemMatch": { "auth_id": auth_id, "expires_at": { "$gt": time_now as i64 } } },
+        "banned" : { "$ne": true }
+    };
+    let Some(account) = account_collection::<FrontAccount>(dbclient).find_one(query).await? else {
+        return Ok(None);
+    };
+    let whitelist: Vec<String> = meta_collection(dbclient).find_one(Document::new()).await?.unwrap_or_default().whitelist;
+    Ok(Some(is_staff(&account, &whitelist)))
+}
@@ -0,0 +1,300 @@
+use chrono::DateTime;
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Deserialize;
+
+use shared::discord::User;
+use shared::fiche_rp::{FicheRP, ReviewMessage};
+use shared::markdown::MarkdownRules;
+
+const PDF_LINE_WIDTH: usize = 95;
+const PDF_LINE_HEIGHT: f32 = 5.0;
+const PDF_TOP: f32 = 280.0;
+const PDF_BOTTOM: f32 = 15.0;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Html,
+    Markdown,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &str {
+        match self {
+            ExportFormat::Html => "text/html; charset=utf-8",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// A review message already resolved to its author name
+pub struct ExportedReview<'a> {
+    pub author: String,
+    pub message: &'a ReviewMessage,
+}
+
+pub fn fiche_to_markdown(fiche: &FicheRP, owner: &User, reviews: &Vec<ExportedReview>) -> String {
+    let mut markdown: String = format!("# Fiche RP — {}\n\n", fiche.name);
+
+    header_fields(fiche, owner).iter().for_each(|(label, value)| {
+        markdown.push_str(&format!("- **{} :** {}\n", label, value));
+    });
+
+    markdown.push_str(&format!("\n## Description physique\n\n{}\n\n## Lore\n\n{}\n", fiche.description, fiche.lore));
+
+    if !reviews.is_empty() {
+        markdown.push_str("\n## Avis\n");
+        reviews.iter().for_each(|review| {
+            markdown.push_str(&format!("\n### {} — {} — {}\n\n{}\n", review.author, review.message.set_state.get_text(), format_date(review.message.date), review.message.content));
+        });
+    }
+    markdown
+}
+
+pub fn fiche_to_html(fiche: &FicheRP, owner: &User, reviews: &Vec<ExportedReview>, rules: &MarkdownRules) -> String {
+    let mut body: String = format!("<h1>Fiche RP — {}</h1>\n<table>\n", escape_html(&fiche.name));
+
+    header_fields(fiche, owner).iter().for_each(|(label, value)| {
+        body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(value)));
+    });
+    body.push_str("</table>\n");
+
+    body.push_str(&format!("<h2>Description physique</h2>\n{}<h2>Lore</h2>\n{}", markdown_to_html(&fiche.description, rules), markdown_to_html(&fiche.lore, rules)));
+
+    if !reviews.is_empty() {
+        body.push_str("<h2>Avis</h2>\n");
+        reviews.iter().for_each(|review| {
+            body.push_str(&format!("<section class=\"review\"><h3>{} — {} — {}</h3>\n{}</section>\n", escape_html(&review.author), review.message.set_state.get_text(), format_date(review.message.date), markdown_to_html(&review.message.content, rules)));
+        });
+    }
+
+    format!("<!DOCTYPE html>
+<html lang=\"fr\">
+<head>
+<meta charset=\"utf-8\">
+<title>Fiche RP — {}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; line-height: 1.5; color: #161616; }}
+th {{ text-align: left; padding-right: 1em; }}
+.review {{ border-top: 1px solid #B8B8B8; }}
+@media print {{ body {{ margin: 0; }} }}
+</style>
+</head>
+<body>
+{}</body>
+</html>
+", escape_html(&fiche.name), body)
+}
+
+pub fn fiche_to_pdf(fiche: &FicheRP, owner: &User, reviews: &Vec<ExportedReview>) -> anyhow::Result<Vec<u8>> {
+    let (document, page, layer) = PdfDocument::new(format!("Fiche RP — {}", fiche.name), Mm(210.0), Mm(297.0), "Fiche");
+    let regular: IndirectFontRef = document.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold: IndirectFontRef = document.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    let mut writer: PdfWriter = PdfWriter {
+        layer: document.get_page(page).get_layer(layer),
+        document: &document,
+        y: PDF_TOP,
+    };
+
+    writer.line(&format!("Fiche RP — {}", fiche.name), &bold, 16.0);
+    writer.skip();
+    header_fields(fiche, owner).iter().for_each(|(label, value)| {
+        writer.paragraph(&format!("{} : {}", label, value), &regular, 10.0);
+    });
+
+    writer.skip();
+    writer.line("Description physique", &bold, 13.0);
+    writer.paragraph(&markdown_to_text(&fiche.description), &regular, 10.0);
+    writer.skip();
+    writer.line("Lore", &bold, 13.0);
+    writer.paragraph(&markdown_to_text(&fiche.lore), &regular, 10.0);
+
+    if !reviews.is_empty() {
+        writer.skip();
+        writer.line("Avis", &bold, 13.0);
+        reviews.iter().for_each(|review| {
+            writer.skip();
+            writer.line(&format!("{} — {} — {}", review.author, review.message.set_state.get_text(), format_date(review.message.date)), &bold, 10.0);
+            writer.paragraph(&markdown_to_text(&review.message.content), &regular, 10.0);
+        });
+    }
+
+    Ok(document.save_to_bytes()?)
+}
+
+struct PdfWriter<'a> {
+    document: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    y: f32,
+}
+
+impl PdfWriter<'_> {
+    fn line(&mut self, text: &str, font: &IndirectFontRef, size: f32) {
+        if self.y < PDF_BOTTOM {
+            let (page, layer) = self.document.add_page(Mm(210.0), Mm(297.0), "Fiche");
+            self.layer = self.document.get_page(page).get_layer(layer);
+            self.y = PDF_TOP;
+        }
+        self.layer.use_text(text, size, Mm(15.0), Mm(self.y), font);
+        self.y -= PDF_LINE_HEIGHT * size / 10.0;
+    }
+
+    fn paragraph(&mut self, text: &str, font: &IndirectFontRef, size: f32) {
+        text.lines().for_each(|line| {
+            wrap_line(line, PDF_LINE_WIDTH).iter().for_each(|wrapped| self.line(wrapped, font, size));
+        });
+    }
+
+    fn skip(&mut self) {
+        self.y -= PDF_LINE_HEIGHT;
+    }
+}
+
+fn header_fields(fiche: &FicheRP, owner: &User) -> Vec<(&'static str, String)> {
+    let first_submission: u64 = fiche.version.first().map(|version| version.submission_date).unwrap_or(fiche.submission_date);
+
+    vec![
+        ("Joueur", owner.global_name.clone()),
+        ("Nom", fiche.name.clone()),
+        ("Job", fiche.job.to_string()),
+        ("Statut", fiche.state.get_text().to_string()),
+        ("Soumise le", format_date(first_submission)),
+        ("Dernière version", format_date(fiche.submission_date)),
+    ]
+}
+
+/// Renders user markdown, raw HTML is kept as text, non web links and images from hosts not allowed are neutralised
+pub fn markdown_to_html(markdown: &str, rules: &MarkdownRules) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+            Event::Start(Tag::Link { link_type, dest_url: CowStr::Borrowed("#"), title, id })
+        }
+        // The export is opened in a browser too, it follows the image allowlist of the viewer
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) if !rules.is_image_allowed(&dest_url) => {
+            Event::Start(Tag::Image { link_type, dest_url: CowStr::Borrowed(""), title, id })
+        }
+        event => event,
+    });
+
+    let mut output: String = String::new();
+    html::push_html(&mut output, parser);
+    output
+}
+
+pub fn markdown_to_text(markdown: &str) -> String {
+    let mut text: String = String::new();
+
+    Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).for_each(|event| match event {
+        Event::Text(content) | Event::Code(content) | Event::Html(content) | Event::InlineHtml(content) => text.push_str(&content),
+        Event::SoftBreak => text.push(' '),
+        Event::HardBreak | Event::Rule => text.push('\n'),
+        Event::Start(Tag::Item) => text.push_str("• "),
+        Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Heading(_)) | Event::End(TagEnd::Item) | Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::TableRow) => text.push('\n'),
+        Event::End(TagEnd::TableCell) => text.push_str(" | "),
+        _ => {}
+    });
+    text.trim_end().to_string()
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn is_safe_url(url: &str) -> bool {
+    let lowercase: String = url.trim().to_lowercase();
+    lowercase.starts_with("https://") || lowercase.starts_with("http://") || lowercase.starts_with("mailto:") || lowercase.starts_with('#')
+}
+
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut current: String = String::new();
+
+    line.split_whitespace().for_each(|word| {
+        if !current.is_empty() && current.chars().count() + word.chars().count() + 1 > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    });
+    lines.push(current);
+    lines
+}
+
+fn format_date(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0).map(|datetime| datetime.format("%d-%m-%Y").to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::fiche_rp::{FicheState, Job};
+    use shared::fixtures;
+
+    use super::*;
+
+    fn fiche() -> FicheRP {
+        FicheRP {
+            id: "fiche".to_string(),
+            name: "Roger <b>Dupont</b>".to_string(),
+            job: Job::ClassD,
+            description: "Grand et **brun**".to_string(),
+            lore: "Né à Paris.\n\n<script>alert(1)</script>\n\n[lien](javascript:alert(1))".to_string(),
+            submission_date: 1720000000,
+            ..fixtures::fiche(FicheState::Accepted)
+        }
+    }
+
+    #[test]
+    fn html_export_is_escaped() {
+        let mut fiche: FicheRP = fiche();
+        fiche.description.push_str("\n\n![ok](https://intranet.projectvisualis.fr/a.png) ![traceur](https://evil.com/t.png)");
+        let html: String = fiche_to_html(&fiche, &User::default(), &vec![], &MarkdownRules::default());
+
+        assert!(html.contains("Roger &lt;b&gt;Dupont&lt;/b&gt;"));
+        assert!(html.contains("<strong>brun</strong>"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("javascript:"));
+        assert!(html.contains(FicheState::Accepted.get_text()));
+        assert!(html.contains("src=\"https://intranet.projectvisualis.fr/a.png\""));
+        assert!(!html.contains("evil.com"));
+    }
+
+    #[test]
+    fn markdown_export_with_reviews() {
+        let message: ReviewMessage = ReviewMessage {
+            content: "Très bien".to_string(),
+            date: 1720000000,
+            ..fixtures::review_message("1", FicheState::Accepted)
+        };
+        let markdown: String = fiche_to_markdown(&fiche(), &User::default(), &vec![ExportedReview { author: "Lead".to_string(), message: &message }]);
+
+        assert!(markdown.starts_with("# Fiche RP — Roger"));
+        assert!(markdown.contains("- **Job :** Classe-D"));
+        assert!(markdown.contains("### Lead — ACCEPTÉE — 03-07-2024"));
+    }
+
+    #[test]
+    fn pdf_text_is_wrapped() {
+        assert_eq!(markdown_to_text("# Titre\n\n- un\n- deux"), "Titre\n• un\n• deux");
+        assert_eq!(wrap_line("aaa bbb ccc", 7), vec!["aaa bbb", "ccc"]);
+        assert!(fiche_to_pdf(&fiche(), &User::default(), &vec![]).unwrap().starts_with(b"%PDF"));
+    }
+}
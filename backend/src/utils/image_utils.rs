@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{Cursor, ErrorKind};
+use std::path::PathBuf;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+
+use crate::utils::config_utils::ImageRules;
+
+pub const IMAGE_DIR: &str = "data/images";
+
+pub struct ProcessedImage {
+    pub full: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug)]
+pub enum ImageRejection {
+    TooLarge(usize),
+    UnsupportedFormat,
+    Invalid(String),
+}
+
+impl Display for ImageRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageRejection::TooLarge(max) => write!(f, "Image too large: {} bytes at most", max),
+            ImageRejection::UnsupportedFormat => write!(f, "Unsupported image format, use PNG, JPEG, WebP or GIF"),
+            ImageRejection::Invalid(err) => write!(f, "Invalid image: {}", err),
+        }
+    }
+}
+
+/// Sniffs the type from the content (the client name and mime type are not trusted), decodes it within the limits
+/// and re-encodes it to WebP. Only the pixels are kept, EXIF and other metadata never reach the disk
+pub fn process_upload(bytes: &[u8], rules: &ImageRules) -> Result<ProcessedImage, ImageRejection> {
+    if bytes.len() > rules.max_upload_bytes {
+        return Err(ImageRejection::TooLarge(rules.max_upload_bytes));
+    }
+    let format: ImageFormat = image::guess_format(bytes).map_err(|_| ImageRejection::UnsupportedFormat)?;
+    if !matches!(format, ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Gif) {
+        return Err(ImageRejection::UnsupportedFormat);
+    }
+
+    let mut limits: Limits = Limits::default();
+    limits.max_image_width = Some(rules.max_dimension);
+    limits.max_image_height = Some(rules.max_dimension);
+    limits.max_alloc = Some(rules.max_decoded_bytes());
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    // Phones store the rotation in EXIF, it has to be applied before the metadata is dropped
+    let orientation = decoder.orientation().map_err(invalid)?;
+    let mut image: DynamicImage = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+
+    let full: DynamicImage = fit(image, rules.stored_dimension);
+    let thumbnail: DynamicImage = fit(full.clone(), rules.thumbnail_dimension);
+
+    Ok(ProcessedImage {
+        width: full.width(),
+        height: full.height(),
+        full: encode(&full)?,
+        thumbnail: encode(&thumbnail)?,
+    })
+}
+
+pub fn store_image(fiche_id: &str, image_id: &str, image: &ProcessedImage) -> std::io::Result<()> {
+    let dir: PathBuf = fiche_image_dir(fiche_id)?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{}.webp", image_id)), &image.full)?;
+    fs::write(dir.join(format!("{}_thumb.webp", image_id)), &image.thumbnail)
+}
+
+/// Full image and thumbnail as stored on the disk, used by the archives
+pub fn read_image(fiche_id: &str, image_id: &str) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let dir: PathBuf = fiche_image_dir(fiche_id)?;
+    Ok((fs::read(dir.join(format!("{}.webp", image_id)))?, fs::read(dir.join(format!("{}_thumb.webp", image_id)))?))
+}
+
+/// File of a stored image, the thumbnail or the full image
+pub fn image_path(fiche_id: &str, image_id: &str, thumbnail: bool) -> std::io::Result<PathBuf> {
+    if image_id.is_empty() || !image_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("invalid image id {}", image_id)));
+    }
+    let file_name: String = if thumbnail { format!("{}_thumb.webp", image_id) } else { format!("{}.webp", image_id) };
+    Ok(fiche_image_dir(fiche_id)?.join(file_name))
+}
+
+pub fn remove_image(fiche_id: &str, image_id: &str) -> std::io::Result<()> {
+    let dir: PathBuf = fiche_image_dir(fiche_id)?;
+    [format!("{}.webp", image_id), format!("{}_thumb.webp", image_id)].iter().try_for_each(|file| ignore_not_found(fs::remove_file(dir.join(file))))
+}
+
+/// Removes every image of a fiche, used when a draft is deleted
+pub fn remove_fiche_images(fiche_id: &str) -> std::io::Result<()> {
+    ignore_not_found(fs::remove_dir_all(fiche_image_dir(fiche_id)?))
+}
+
+/// Fiche ids end up in paths, anything but a uuid is refused
+fn fiche_image_dir(fiche_id: &str) -> std::io::Result<PathBuf> {
+    if fiche_id.is_empty() || !fiche_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, format!("invalid fiche id {}", fiche_id)));
+    }
+    Ok(PathBuf::from(IMAGE_DIR).join(fiche_id))
+}
+
+fn fit(image: DynamicImage, max: u32) -> DynamicImage {
+    if image.width() <= max && image.height() <= max {
+        image
+    } else {
+        image.resize(max, max, FilterType::Lanczos3)
+    }
+}
+
+fn encode(image: &DynamicImage) -> Result<Vec<u8>, ImageRejection> {
+    let mut bytes: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut bytes, ImageFormat::WebP).map_err(invalid)?;
+    Ok(bytes.into_inner())
+}
+
+fn invalid(err: image::ImageError) -> ImageRejection {
+    ImageRejection::Invalid(err.to_string())
+}
+
+pub(crate) fn ignore_not_found(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_processing() {
+        let rules: ImageRules = ImageRules::default();
+        assert!(matches!(process_upload(b"#!/bin/sh\nrm -rf /", &rules), Err(ImageRejection::UnsupportedFormat)));
+
+        let mut png: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        DynamicImage::new_rgb8(2000, 1000).write_to(&mut png, ImageFormat::Png).unwrap();
+        let processed: ProcessedImage = process_upload(png.get_ref(), &rules).unwrap();
+        assert_eq!((processed.width, processed.height), (1600, 800));
+        assert_eq!(image::guess_format(&processed.full).unwrap(), ImageFormat::WebP);
+
+        let thumbnail: DynamicImage = image::load_from_memory(&processed.thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+        let small_rules: ImageRules = ImageRules { max_dimension: 1000, ..ImageRules::default() };
+        assert!(matches!(process_upload(png.get_ref(), &small_rules), Err(ImageRejection::Invalid(_))));
+
+        assert!(image_path("fiche", "../config", false).is_err());
+        assert_eq!(image_path("fiche", "image", true).unwrap(), PathBuf::from(IMAGE_DIR).join("fiche").join("image_thumb.webp"));
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::Document;
+use prometheus::{register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder};
+use strum::IntoEnumIterator;
+
+use shared::fiche_rp::FicheState;
+use shared::user::FrontAccount;
+
+use crate::utils::db_utils::account_collection;
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!("http_requests_total", "HTTP requests by route and status", &["method", "route", "status"]).unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!("http_request_duration_seconds", "HTTP request latency by route", &["method", "route"]).unwrap();
+    pub static ref RATE_LIMIT_REJECTIONS: IntCounter = register_int_counter!("rate_limit_rejections_total", "Requests rejected by the rate limiter").unwrap();
+    pub static ref WEBHOOK_FAILURES: IntCounterVec = register_int_counter_vec!("webhook_failures_total", "Discord webhooks that could not be sent", &["kind"]).unwrap();
+    pub static ref TOKEN_RENEWALS: IntCounterVec = register_int_counter_vec!("token_renewals_total", "OAuth2 token renewal outcomes", &["outcome"]).unwrap();
+    pub static ref FICHES: IntGaugeVec = register_int_gauge_vec!("fiches", "Fiches by state", &["state"]).unwrap();
+}
+
+/// `route` is the matched pattern (e.g. "/api/avatars/{id}"), never the raw path, to keep the label set bounded
+pub fn record_request(method: &str, route: &str, status: u16, duration: Duration) {
+    HTTP_REQUESTS.with_label_values(&[method, route, &status.to_string()]).inc();
+    HTTP_REQUEST_DURATION.with_label_values(&[method, route]).observe(duration.as_secs_f64());
+}
+
+/// Fiche gauges are recomputed from the accounts by a background job instead of being tracked on every update,
+/// a scrape never reads the database
+pub fn update_fiche_gauges(accounts: &[FrontAccount]) {
+    FicheState::iter().filter(|state| *state != FicheState::Comment).for_each(|state| {
+        let count: usize = accounts.iter().flat_map(|account| account.fiches.iter()).filter(|fiche| fiche.state == state).count();
+        FICHES.with_label_values(&[state.as_ref()]).set(count as i64);
+    });
+}
+
+pub async fn refresh_fiche_gauges(dbclient: mongodb::Client) -> anyhow::Result<String> {
+    let accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(&dbclient).find(Document::new()).await?.try_collect().await?;
+    update_fiche_gauges(&accounts);
+    Ok(format!("{} account(s) counted", accounts.len()))
+}
+
+/// `authorization` is the raw header, scrapers send `Bearer <metrics_token>`. An empty token disables the endpoint
+pub fn is_scrape_authorized(authorization: Option<&str>, token: &str) -> bool {
+    match authorization.and_then(|header| header.strip_prefix("Bearer ")) {
+        // Compared in constant time, the length of the token is not a secret
+        Some(given) if !token.is_empty() && given.len() == token.len() => given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0,
+        _ => false,
+    }
+}
+
+pub fn render_metrics() -> String {
+    let mut buffer: Vec<u8> = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn metrics_rendering() {
+        let account: FrontAccount = FrontAccount {
+            fiches: vec![
+                fixtures::fiche(FicheState::Accepted),
+                fixtures::fiche(FicheState::Accepted),
+                fixtures::fiche(FicheState::Waiting),
+            ],
+            ..FrontAccount::default()
+        };
+        update_fiche_gauges(&[account]);
+        record_request("GET", "/api/avatars/{id}", 200, Duration::from_millis(5));
+        let metrics: String = render_metrics();
+
+        assert!(metrics.contains("fiches{state=\"Accepted\"} 2"));
+        assert!(metrics.contains("fiches{state=\"Waiting\"} 1"));
+        assert!(metrics.contains("fiches{state=\"Refused\"} 0"));
+        assert!(!metrics.contains("state=\"Comment\""));
+        assert!(metrics.contains("http_requests_total{method=\"GET\",route=\"/api/avatars/{id}\",status=\"200\"}"));
+    }
+
+    #[test]
+    fn scrape_authorization() {
+        assert!(is_scrape_authorized(Some("Bearer 0123456789abcdef"), "0123456789abcdef"));
+        assert!(!is_scrape_authorized(Some("Bearer 0123456789abcdeg"), "0123456789abcdef"));
+        assert!(!is_scrape_authorized(Some("0123456789abcdef"), "0123456789abcdef"));
+        assert!(!is_scrape_authorized(None, "0123456789abcdef"));
+        assert!(!is_scrape_authorized(Some("Bearer "), ""));
+    }
+}
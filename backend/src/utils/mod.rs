@@ -1,3 +1,14 @@
 pub mod config_utils;
 pub mod auth_utils;
-pub mod webhook_utils;
\ No newline at end of file
+pub mod webhook_utils;
+pub mod discord_utils;
+pub mod export_utils;
+pub mod db_utils;
+pub mod archive_utils;
+pub mod metrics_utils;
+pub mod event_utils;
+pub mod image_utils;
+pub mod avatar_utils;
+pub mod crypto_utils;
+pub mod scheduler_utils;
+pub mod privacy_utils;
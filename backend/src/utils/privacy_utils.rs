@@ -0,0 +1,251 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use log::{error, info};
+use mongodb::bson::{doc, Document};
+use serde::Serialize;
+use serenity::futures::TryStreamExt;
+use uuid::Uuid;
+
+use shared::audit::{AuditAction, AuditEntry};
+use shared::fiche_rp::ReviewMessage;
+use shared::legal::TermsAcceptance;
+use shared::mentions::MentionNotification;
+use shared::user::{Account, FrontAccount, SessionInfo};
+
+use crate::utils::avatar_utils::remove_avatar;
+use crate::utils::db_utils::{account_collection, anonymise_account_references, delete_account_document, forget_notifications, insert_audit_entry, notification_collection, whitelist_remove};
+use crate::utils::image_utils::remove_fiche_images;
+
+/// Author of the review messages of deleted accounts
+pub const DELETED_USER_ID: &str = "deleted";
+
+/// Everything stored about a user, downloaded from "Mes données". OAuth tokens and session secrets are left out
+#[derive(Serialize)]
+pub struct PersonalData {
+    pub exported_at: u64,
+    /// Fiches with their versions and messages, the private staff messages of other users excluded
+    pub account: FrontAccount,
+    pub terms_acceptances: Vec<TermsAcceptance>,
+    pub sessions: Vec<SessionInfo>,
+    pub last_token_renewal: u64,
+    /// Review messages written on the fiches of other accounts
+    pub authored_messages: Vec<AuthoredMessage>,
+    pub notifications: Vec<MentionNotification>,
+}
+
+#[derive(Serialize)]
+pub struct AuthoredMessage {
+    pub fiche_id: String,
+    pub fiche_name: String,
+    pub message: ReviewMessage,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DeletionReport {
+    pub fiches: usize,
+    pub anonymised_messages: usize,
+    pub deleted_notifications: u64,
+}
+
+impl PersonalData {
+    pub fn collect(account: &Account, all_accounts: &[FrontAccount], notifications: Vec<MentionNotification>, exported_at: u64) -> PersonalData {
+        let discord_id: &str = &account.discord_user.id;
+        let mut front_account: FrontAccount = account.front();
+        front_account.fiches.iter_mut().for_each(|fiche| fiche.messages.retain(|message| !message.is_private || message.discord_id == discord_id));
+
+        PersonalData {
+            exported_at,
+            account: front_account,
+            terms_acceptances: account.terms_acceptances.clone(),
+            sessions: account.sessions.iter().map(|session| session.info("")).collect(),
+            last_token_renewal: account.last_renewal,
+            authored_messages: authored_messages(discord_id, all_accounts),
+            notifications,
+        }
+    }
+}
+
+/// Messages of `discord_id` on the fiches of the other accounts
+pub fn authored_messages(discord_id: &str, all_accounts: &[FrontAccount]) -> Vec<AuthoredMessage> {
+    all_accounts.iter()
+        .filter(|account| account.discord_user.id != discord_id)
+        .flat_map(|account| account.fiches.iter())
+        .flat_map(|fiche| fiche.messages.iter().filter(|message| message.discord_id == discord_id).map(|message| AuthoredMessage {
+            fiche_id: fiche.id.clone(),
+            fiche_name: fiche.name.clone(),
+            message: message.clone(),
+        }))
+        .collect()
+}
+
+pub async fn export_personal_data(dbclient: &mongodb::Client, account: &Account, actor_id: &str) -> Result<PersonalData> {
+    let all_accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+    let notifications: Vec<MentionNotification> = notification_collection(dbclient).find(doc! {"discord_id": &account.discord_user.id}).await?.try_collect().await?;
+
+    let personal_data: PersonalData = PersonalData::collect(account, &all_accounts, notifications, now());
+    audit(dbclient, AuditAction::DataExport, actor_id, &account.discord_user.id, format!("{} fiche(s), {} authored message(s)", personal_data.account.fiches.len(), personal_data.authored_messages.len())).await?;
+    Ok(personal_data)
+}
+
+/// Deletes the account with its fiches, images, token, sessions, avatar and notifications. What it wrote or did
+/// on other fiches stays for their owners but is anonymised. None when the account doesn't exist.
+/// The account document goes first so a failure can't leave it half anonymised, the references left behind
+/// by an interrupted deletion are cleaned by running it again
+pub async fn delete_account(dbclient: &mongodb::Client, discord_id: &str, actor_id: &str) -> Result<Option<DeletionReport>> {
+    let all_accounts: Vec<FrontAccount> = account_collection::<FrontAccount>(dbclient).find(Document::new()).await?.try_collect().await?;
+    let account: Option<&FrontAccount> = match all_accounts.iter().find(|account| account.discord_user.id == discord_id) {
+        Some(account) if delete_account_document(dbclient, discord_id).await? => Some(account),
+        _ => None,
+    };
+
+    // The account is gone, files left behind are only logged
+    if let Some(account) = account {
+        account.fiches.iter().for_each(|fiche| {
+            if let Err(err) = remove_fiche_images(&fiche.id) {
+                error!("Can't delete the images of fiche {}: {}", fiche.id, err);
+            }
+        });
+        if let Err(err) = remove_avatar(discord_id) {
+            error!("Can't delete the avatar of {}: {}", discord_id, err);
+        }
+    }
+
+    anonymise_account_references(dbclient, discord_id, DELETED_USER_ID).await?;
+    let deleted_notifications: u64 = forget_notifications(dbclient, discord_id, DELETED_USER_ID).await?;
+    whitelist_remove(dbclient, discord_id).await?;
+    let Some(account) = account else {
+        return Ok(None);
+    };
+
+    let report: DeletionReport = DeletionReport {
+        fiches: account.fiches.len(),
+        anonymised_messages: authored_messages(discord_id, &all_accounts).len(),
+        deleted_notifications,
+    };
+
+    audit(dbclient, AuditAction::AccountDeletion, actor_id, discord_id, format!("{} fiche(s), {} message(s) anonymised, {} notification(s)", report.fiches, report.anonymised_messages, report.deleted_notifications)).await?;
+    info!("Account {} deleted by {}", discord_id, actor_id);
+    Ok(Some(report))
+}
+
+async fn audit(dbclient: &mongodb::Client, action: AuditAction, actor_id: &str, target_id: &str, details: String) -> Result<()> {
+    let entry: AuditEntry = AuditEntry {
+        id: Uuid::now_v7().to_string(),
+        action,
+        actor_id: actor_id.to_string(),
+        target_id: target_id.to_string(),
+        date: now(),
+        details,
+    };
+    insert_audit_entry(dbclient, &entry).await?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use oauth2::basic::{BasicTokenResponse, BasicTokenType};
+    use oauth2::{AccessToken, EmptyExtraTokenFields};
+
+    use shared::discord::User;
+    use shared::fiche_rp::{CharacterStatus, FicheRP, FicheState, LifecycleChange, MessageDeletion, ReviewClaim, ReviewVote};
+    use shared::fixtures;
+    use shared::legal::LegalVersions;
+    use shared::user::AuthSession;
+
+    use crate::utils::db_utils::audit_collection;
+
+    use super::*;
+
+    fn message(discord_id: &str, is_private: bool) -> ReviewMessage {
+        ReviewMessage { is_private, id: Uuid::now_v7().to_string(), ..fixtures::review_message(discord_id, FicheState::Comment) }
+    }
+
+    fn fiche(id: &str, messages: Vec<ReviewMessage>) -> FicheRP {
+        FicheRP { id: id.to_string(), messages, ..fixtures::fiche(FicheState::Waiting) }
+    }
+
+    #[test]
+    fn personal_data() {
+        let account: Account = Account {
+            discord_user: User { id: "1".to_string(), global_name: "Roger".to_string(), avatar: "".to_string() },
+            discord_roles: vec![],
+            sessions: vec![AuthSession { id: "session".to_string(), auth_id: "secret".to_string(), created_at: 1720000000, expires_at: 1722419200, user_agent: "Firefox".to_string() }],
+            token: BasicTokenResponse::new(AccessToken::new("access".to_string()), BasicTokenType::Bearer, EmptyExtraTokenFields {}),
+            last_renewal: 1720000000,
+            fiches: vec![fiche("own", vec![message("2", false), message("2", true), message("1", true)])],
+            creation_date: 1710000000,
+            banned: false,
+            needs_relogin: false,
+            terms_acceptances: vec![TermsAcceptance { versions: LegalVersions::default(), date: 1720000000 }],
+            sync_failures: 0,
+            next_sync: 0,
+        };
+        let staff: FrontAccount = FrontAccount {
+            discord_user: User { id: "2".to_string(), global_name: "Lead".to_string(), avatar: "".to_string() },
+            fiches: vec![fiche("other", vec![message("1", false), message("2", false)])],
+            ..FrontAccount::default()
+        };
+
+        let personal_data: PersonalData = PersonalData::collect(&account, &[staff], vec![], 1730000000);
+        assert_eq!(personal_data.account.fiches[0].messages.len(), 2);
+        assert_eq!(personal_data.authored_messages.len(), 1);
+        assert_eq!(personal_data.authored_messages[0].fiche_id, "other");
+        assert_eq!(personal_data.terms_acceptances, account.terms_acceptances);
+
+        let json: String = serde_json::to_string(&personal_data).unwrap();
+        assert!(!json.contains("secret") && !json.contains("access"));
+    }
+
+    /// Runs against the MongoDB of `VISUALIS_TEST_MONGO_URI`, on accounts created for the test:
+    /// `VISUALIS_TEST_MONGO_URI=mongodb://localhost:27017 cargo test -p backend -- --ignored`
+    #[actix_web::test]
+    #[ignore = "needs VISUALIS_TEST_MONGO_URI"]
+    async fn account_deletion_in_database() {
+        let uri: String = std::env::var("VISUALIS_TEST_MONGO_URI").expect("VISUALIS_TEST_MONGO_URI is not set");
+        let dbclient: mongodb::Client = mongodb::Client::with_uri_str(uri).await.unwrap();
+        let nanos: u128 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let (deleted_id, other_id): (String, String) = (format!("9{}", nanos), format!("8{}", nanos));
+
+        let mut reviewed: FicheRP = fiche(&Uuid::now_v7().to_string(), vec![ReviewMessage {
+            deletion: Some(MessageDeletion { discord_id: deleted_id.clone(), date: 0 }),
+            ..message(&deleted_id, false)
+        }]);
+        reviewed.votes = vec![ReviewVote { discord_id: deleted_id.clone(), approve: true, date: 0, changes: vec![] }];
+        reviewed.lifecycle = vec![LifecycleChange { status: CharacterStatus::Active, reason: String::new(), date: 0, set_by: deleted_id.clone() }];
+        reviewed.claim = Some(ReviewClaim { discord_id: deleted_id.clone(), claimed_at: 0, expires_at: 0, assigned_by: None });
+        let mut assigned: FicheRP = fiche(&Uuid::now_v7().to_string(), vec![]);
+        assigned.claim = Some(ReviewClaim { discord_id: other_id.clone(), claimed_at: 0, expires_at: 0, assigned_by: Some(deleted_id.clone()) });
+
+        let accounts: mongodb::Collection<FrontAccount> = account_collection(&dbclient);
+        accounts.insert_many(vec![FrontAccount {
+            discord_user: User { id: deleted_id.clone(), ..User::default() },
+            fiches: vec![fiche(&Uuid::now_v7().to_string(), vec![])],
+            ..FrontAccount::default()
+        }, FrontAccount {
+            discord_user: User { id: other_id.clone(), ..User::default() },
+            fiches: vec![reviewed, assigned],
+            ..FrontAccount::default()
+        }]).await.unwrap();
+
+        let report: DeletionReport = delete_account(&dbclient, &deleted_id, "test").await.unwrap().unwrap();
+        assert_eq!((report.fiches, report.anonymised_messages), (1, 1));
+        assert!(accounts.find_one(doc! {"discord_user.id": &deleted_id}).await.unwrap().is_none());
+
+        let other: FrontAccount = accounts.find_one(doc! {"discord_user.id": &other_id}).await.unwrap().unwrap();
+        assert!(!serde_json::to_string(&other).unwrap().contains(&deleted_id));
+        assert_eq!(other.fiches[0].messages[0].deletion.as_ref().unwrap().discord_id, DELETED_USER_ID);
+        assert_eq!(other.fiches[0].claim, None);
+        assert_eq!(other.fiches[1].claim.as_ref().unwrap().assigned_by.as_deref(), Some(DELETED_USER_ID));
+
+        // Nothing left to delete, running it again only cleans the references
+        assert_eq!(delete_account(&dbclient, &deleted_id, "test").await.unwrap(), None);
+
+        accounts.delete_one(doc! {"discord_user.id": &other_id}).await.unwrap();
+        audit_collection(&dbclient).delete_many(doc! {"target_id": &deleted_id}).await.unwrap();
+    }
+}
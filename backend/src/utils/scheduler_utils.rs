@@ -0,0 +1,124 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::rt::time;
+use dashmap::DashMap;
+use log::{error, info};
+use rand::Rng;
+
+use shared::jobs::JobStatus;
+
+/// Delay before the first run of each job, spread so they don't all start with the server
+const FIRST_RUN_JITTER: Duration = Duration::from_secs(60);
+
+/// Runs the background jobs and keeps their status for the admin space
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    statuses: Arc<DashMap<&'static str, JobStatus>>,
+}
+
+impl Scheduler {
+    /// Runs `job` every `interval`, plus up to a tenth of it of jitter. Each run is supervised: an error or a panic
+    /// is recorded in the job status and the next run happens as planned, the job never dies
+    pub fn spawn<F, Fut>(&self, name: &'static str, interval: Duration, job: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output=anyhow::Result<String>> + 'static,
+    {
+        let mut delay: Duration = jitter(FIRST_RUN_JITTER);
+        self.statuses.insert(name, JobStatus {
+            name: name.to_string(),
+            interval_secs: interval.as_secs(),
+            next_run: Some(now() + delay.as_secs()),
+            ..JobStatus::default()
+        });
+        let scheduler: Scheduler = self.clone();
+
+        actix_rt::spawn(async move {
+            loop {
+                time::sleep(delay).await;
+                scheduler.run(name, job()).await;
+
+                delay = interval + jitter(interval / 10);
+                scheduler.update_status(name, |status| status.next_run = Some(now() + delay.as_secs()));
+            }
+        });
+    }
+
+    async fn run(&self, name: &'static str, run: impl Future<Output=anyhow::Result<String>> + 'static) {
+        self.update_status(name, |status| {
+            status.running = true;
+            status.last_start = Some(now());
+            status.next_run = None;
+        });
+        // A panic only kills the spawned run, the runtime reports it in the join result
+        let result: anyhow::Result<String> = match actix_rt::spawn(run).await {
+            Ok(result) => result,
+            Err(err) => Err(anyhow::anyhow!("run panicked: {}", err)),
+        };
+
+        self.update_status(name, |status| {
+            status.running = false;
+            status.runs += 1;
+            status.last_end = Some(now());
+            match &result {
+                Ok(report) => {
+                    info!("Job {} done: {}", name, report);
+                    status.last_success = status.last_end;
+                    status.last_report = report.clone();
+                    status.last_error = None;
+                    status.consecutive_failures = 0;
+                }
+                Err(err) => {
+                    error!("Job {} failed: {}", name, err);
+                    status.last_error = Some(err.to_string());
+                    status.consecutive_failures += 1;
+                }
+            }
+        });
+    }
+
+    fn update_status(&self, name: &'static str, update: impl FnOnce(&mut JobStatus)) {
+        if let Some(mut status) = self.statuses.get_mut(name) {
+            update(&mut status);
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        let mut statuses: Vec<JobStatus> = self.statuses.iter().map(|status| status.value().clone()).collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max.as_millis() as u64))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn supervised_runs() {
+        let scheduler: Scheduler = Scheduler::default();
+        scheduler.spawn("sync", Duration::from_secs(3600), || async { Ok("nothing to do".to_string()) });
+        assert_eq!(scheduler.statuses()[0].interval_secs, 3600);
+        assert!(scheduler.statuses()[0].next_run.is_some());
+
+        scheduler.run("sync", async { Err(anyhow::anyhow!("database unavailable")) }).await;
+        scheduler.run("sync", async { panic!("unexpected") }).await;
+        let status: JobStatus = scheduler.statuses().remove(0);
+        assert_eq!((status.runs, status.consecutive_failures, status.running), (2, 2, false));
+        assert!(status.last_error.is_some_and(|err| err.starts_with("run panicked")));
+
+        scheduler.run("sync", async { Ok("3 renewed".to_string()) }).await;
+        let status: JobStatus = scheduler.statuses().remove(0);
+        assert_eq!((status.consecutive_failures, status.last_report.as_str(), status.last_error), (0, "3 renewed", None));
+    }
+}
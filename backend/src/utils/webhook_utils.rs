@@ -1,10 +1,13 @@
+use crate::utils::metrics_utils::WEBHOOK_FAILURES;
 use crate::CONFIG;
-use serenity::all::{CreateEmbed, CreateEmbedFooter, ExecuteWebhook, Http, Webhook};
+use log::error;
+use serenity::all::{CreateAllowedMentions, CreateEmbed, CreateEmbedFooter, ExecuteWebhook, Http, UserId, Webhook};
+use std::future::Future;
 use shared::discord::User;
 use shared::fiche_rp::{FicheRP, FicheState, ReviewMessage};
 
 pub async fn send_scena_fiche_notif(fiche: FicheRP, user: User) {
-    actix_rt::spawn(async move {
+    spawn_webhook("fiche", async move {
         let http: Http = Http::new("");
 
         let webhook: Webhook = Webhook::from_url(&http, &CONFIG.scena_webhook).await?;
@@ -23,6 +26,7 @@ pub async fn send_scena_fiche_notif(fiche: FicheRP, user: User) {
             FicheState::Accepted => embed = embed.image("https://intranet.projectvisualis.fr/app_img/accepted.svg"),
             FicheState::Refused => embed = embed.image("https://intranet.projectvisualis.fr/app_img/refused.svg"),
             FicheState::Comment => embed = embed.image("https://intranet.projectvisualis.fr/app_img/comment.svg"),
+            FicheState::Draft => {}
         }
 
         let builder = ExecuteWebhook::new()
@@ -34,7 +38,7 @@ pub async fn send_scena_fiche_notif(fiche: FicheRP, user: User) {
 }
 
 pub async fn send_scena_comment_notif(fiche: FicheRP, review_message: ReviewMessage, user: User) {
-    actix_rt::spawn(async move {
+    spawn_webhook("comment", async move {
         let http: Http = Http::new("");
 
         let webhook: Webhook = Webhook::from_url(&http, &CONFIG.scena_webhook).await?;
@@ -54,6 +58,7 @@ pub async fn send_scena_comment_notif(fiche: FicheRP, review_message: ReviewMess
             FicheState::Accepted => embed = embed.image("https://intranet.projectvisualis.fr/app_img/accepted.svg"),
             FicheState::Refused => embed = embed.image("https://intranet.projectvisualis.fr/app_img/refused.svg"),
             FicheState::Comment => embed = embed.image("https://intranet.projectvisualis.fr/app_img/comment.svg"),
+            FicheState::Draft => {}
         }
 
         let builder = ExecuteWebhook::new()
@@ -62,4 +67,40 @@ pub async fn send_scena_comment_notif(fiche: FicheRP, review_message: ReviewMess
 
         webhook.execute(&http, false, builder).await
     });
-}
\ No newline at end of file
+}
+
+/// Pings the mentioned users in the scenarist channel, the message itself stays on the intranet
+pub async fn send_mention_notif(fiche: FicheRP, mentioned_ids: Vec<String>, author: User) {
+    spawn_webhook("mention", async move {
+        let http: Http = Http::new("");
+
+        let webhook: Webhook = Webhook::from_url(&http, &CONFIG.scena_webhook).await?;
+
+        let user_ids: Vec<UserId> = mentioned_ids.iter().filter_map(|id| id.parse::<u64>().ok()).filter(|id| *id != 0).map(UserId::new).collect();
+        let pings: String = user_ids.iter().map(|id| format!("<@{}>", id)).collect::<Vec<String>>().join(" ");
+
+        let embed: CreateEmbed = CreateEmbed::new()
+            .title(format!("{} vous a mentionné", author.global_name))
+            .description(format!("**Sur la fiche :**\nName : **{}** \tJob : **{}**", fiche.name, fiche.job))
+            .url("https://intranet.projectvisualis.fr/")
+            .colour(0x1F8B4C)
+            .footer(CreateEmbedFooter::new("Gestionaire de FicheRP"));
+
+        let builder = ExecuteWebhook::new()
+            .content(pings)
+            .allowed_mentions(CreateAllowedMentions::new().users(user_ids))
+            .embed(embed)
+            .username("FicheRP");
+
+        webhook.execute(&http, false, builder).await
+    });
+}
+
+fn spawn_webhook<T: 'static>(kind: &'static str, webhook: impl Future<Output=Result<T, serenity::Error>> + 'static) {
+    actix_rt::spawn(async move {
+        if let Err(err) = webhook.await {
+            WEBHOOK_FAILURES.with_label_values(&[kind]).inc();
+            error!("Can't send {} webhook: {}", kind, err);
+        }
+    });
+}
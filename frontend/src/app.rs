@@ -1,7 +1,9 @@
 use std::future::IntoFuture;
 use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, TryLockResult};
 
-use crate::backend_handler::{authenticate, get_api_path, get_oath2_url};
+use crate::backend_handler::{authenticate, get_api_path, get_oath2_url, listen_live_events};
+use crate::ui::components::mention_components::notification_menu;
+use crate::ui::components::account_components::{account_menu, personal_data, session_list, terms_acceptance};
 use crate::ui::select_space::SpacePanel;
 use crate::ui::spaces::admin_space::AdminSpace;
 use crate::ui::spaces::fiche_space::{FicheSpace, FilterEnum};
@@ -15,13 +17,25 @@ use egui_commonmark::CommonMarkCache;
 use json_gettext::{get_text, static_json_gettext_build, JSONGetText};
 use lazy_static::lazy_static;
 use log::{error, warn};
+use shared::fiche_rp::ApprovalQuorum;
+use shared::jobs::JobStatus;
+use shared::legal::LegalVersions;
+use shared::markdown::MarkdownRules;
+use shared::mentions::MentionNotification;
 use shared::permissions::DiscordRole;
-use shared::user::FrontAccount;
+use shared::quota::QuotaOverview;
+use shared::search::SearchHit;
+use shared::stats::StaffStats;
+use shared::user::{FrontAccount, SessionInfo};
 use shared::website_meta::WebsiteMeta;
 
 pub struct App {
     pub location_url: String,
     pub is_ui_debug: bool,
+    pub is_viewing_sessions: bool,
+    pub is_viewing_personal_data: bool,
+    pub deletion_confirmation: String,
+    pub is_terms_checked: bool,
     // PANELS
     pub fiche_space: FicheSpace,
     pub space_panel: SpacePanel,
@@ -34,6 +48,12 @@ pub struct AuthInfo {
     pub authenticated: bool,
     pub account: Option<FrontAccount>,
     pub website_meta: WebsiteMeta,
+    pub approval_quorum: ApprovalQuorum,
+    pub markdown_rules: MarkdownRules,
+    /// Current versions of the legal texts, None until fetched
+    pub legal_versions: Option<LegalVersions>,
+    /// The user accepted the current versions, checked by the backend
+    pub terms_accepted: bool,
 }
 impl Default for AuthInfo {
     fn default() -> Self {
@@ -41,6 +61,10 @@ impl Default for AuthInfo {
             authenticated: false,
             account: None,
             website_meta: Default::default(),
+            approval_quorum: Default::default(),
+            markdown_rules: Default::default(),
+            legal_versions: None,
+            terms_accepted: false,
         }
     }
 }
@@ -72,6 +96,16 @@ lazy_static! {
     pub static ref GET_TEXT_CTX:Arc<JSONGetText<'static>>=Arc::new(static_json_gettext_build!("fr_FR";"fr_FR" => "assets/langs/fr_FR.json").unwrap());
     pub static ref AUTH_INFO:Arc<RwLock<AuthInfo>> = Arc::new(RwLock::new(AuthInfo::default()));
     pub static ref ALL_ACCOUNTS:Arc<RwLock<Vec<FrontAccount>>> = Arc::new(RwLock::new(vec![]));
+    /// `Err` holds the reason the backend refused the stats, shown instead of waiting forever
+    pub static ref STAFF_STATS:Arc<RwLock<Option<Result<StaffStats, String>>>> = Arc::new(RwLock::new(None));
+    pub static ref REVIEW_QUEUE:Arc<RwLock<Vec<String>>> = Arc::new(RwLock::new(vec![]));
+    pub static ref SAVED_DRAFT_ID:Arc<RwLock<Option<String>>> = Arc::new(RwLock::new(None));
+    pub static ref SUBMIT_ERROR:Arc<RwLock<Option<String>>> = Arc::new(RwLock::new(None));
+    pub static ref QUOTA_OVERVIEW:Arc<RwLock<Option<QuotaOverview>>> = Arc::new(RwLock::new(None));
+    pub static ref SEARCH_RESULTS:Arc<RwLock<Option<Vec<SearchHit>>>> = Arc::new(RwLock::new(None));
+    pub static ref NOTIFICATIONS:Arc<RwLock<Vec<MentionNotification>>> = Arc::new(RwLock::new(vec![]));
+    pub static ref JOB_STATUSES:Arc<RwLock<Option<Vec<JobStatus>>>> = Arc::new(RwLock::new(None));
+    pub static ref SESSIONS:Arc<RwLock<Option<Vec<SessionInfo>>>> = Arc::new(RwLock::new(None));
 }
 
 impl App {
@@ -96,10 +130,15 @@ impl App {
         egui_extras::install_image_loaders(&cc.egui_ctx);
 
         authenticate();
+        listen_live_events(cc.egui_ctx.clone());
 
         Self {
             location_url: cc.integration_info.web_info.location.url.clone(),
             is_ui_debug: false,
+            is_viewing_sessions: false,
+            is_viewing_personal_data: false,
+            deletion_confirmation: "".to_string(),
+            is_terms_checked: false,
 
             fiche_space: FicheSpace {
                 common_mark_cache: Arc::new(RwLock::new(CommonMarkCache::default())),
@@ -114,6 +153,7 @@ impl App {
                 is_editing_existing_fiche: false,
                 background_image: None,
                 fiche_filter: FilterEnum::OWN,
+                search_query: "".to_string(),
             },
             admin_space: AdminSpace {
                 common_mark_cache: Arc::new(RwLock::new(CommonMarkCache::default())),
@@ -127,6 +167,10 @@ impl App {
                 is_writing_message: false,
                 is_viewing_fiche_history: false,
                 is_editing_existing_fiche: false,
+                is_viewing_stats: false,
+                is_viewing_quotas: false,
+                is_viewing_jobs: false,
+                search_query: "".to_string(),
                 background_image: None,
             },
 
@@ -187,6 +231,13 @@ impl eframe::App for App {
                                     SELECTED_SPACE.write().unwrap().selected_space = Space::EficheSpace;
                                 };
                                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                    account_menu(ui, &mut self.is_viewing_sessions, &mut self.is_viewing_personal_data);
+
+                                    if let Some(selected_fiche_account) = notification_menu(ui) {
+                                        self.fiche_space.selected_fiche_account = Some(selected_fiche_account);
+                                        SELECTED_SPACE.write().unwrap().selected_space = Space::EficheSpace;
+                                    }
+
                                     #[cfg(debug_assertions)]
                                     ui.label(format!("Connecté en tant que : {} ({})", account.discord_user.global_name, account.discord_user.id));
 
@@ -221,6 +272,41 @@ impl eframe::App for App {
                 });
             });
 
+            if auth_info.account.as_ref().is_some_and(|account| account.needs_relogin) {
+                egui::TopBottomPanel::top("relogin_panel").show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("Le lien avec votre compte Discord ne peut plus être renouvelé, reconnectez-vous pour garder vos rôles à jour.").color(hex_color!("#E67E22")));
+                        if ui.button("Se reconnecter").clicked() {
+                            web_sys::window().expect("no global `window` exists").location().set_href(&*get_oath2_url()).expect("Can't redirect");
+                        }
+                    });
+                });
+            }
+
+            // New versions of the texts have to be accepted before going on, fiche submissions are refused until then
+            if let Some(legal_versions) = auth_info.legal_versions.filter(|_| auth_info.account.is_some() && !auth_info.terms_accepted) {
+                egui::Window::new("Conditions d'utilisation").collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0]).show(ctx, |ui| {
+                    terms_acceptance(ui, legal_versions, &mut self.is_terms_checked);
+                });
+            }
+
+            if self.is_viewing_sessions {
+                egui::Window::new("Mes sessions").open(&mut self.is_viewing_sessions).default_size([640.0, 240.0]).show(ctx, |ui| {
+                    match SESSIONS.read().unwrap().as_ref() {
+                        Some(sessions) => session_list(ui, sessions),
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                });
+            }
+
+            if self.is_viewing_personal_data {
+                egui::Window::new("Mes données").open(&mut self.is_viewing_personal_data).default_size([480.0, 320.0]).show(ctx, |ui| {
+                    personal_data(ui, &mut self.deletion_confirmation);
+                });
+            }
+
             let selected_space: Space = SELECTED_SPACE.read().unwrap().selected_space;
             match selected_space {
                 Space::Eselection => self.space_panel.update(ctx, frame),
@@ -248,11 +334,26 @@ pub fn get_string(query: &str) -> String {
     }
 }
 
-pub fn avatar_resolver(user_id: &str) -> String {
+/// Avatar served by the backend, its default avatar when the Discord one isn't cached. Sizes are 32, 64 or 128 pixels
+pub fn avatar_resolver(user_id: &str, size: u32) -> String {
     let mut path: String = get_api_path();
-    path.push_str("api/cache/avatars/");
+    path.push_str("api/avatars/");
     path.push_str(user_id);
-    path.push_str("/image.webp");
+    path.push('/');
+    path.push_str(&size.to_string());
+    path
+}
+
+pub fn fiche_image_resolver(fiche_id: &str, image_id: &str, thumbnail: bool) -> String {
+    let mut path: String = get_api_path();
+    path.push_str("api/images/");
+    path.push_str(fiche_id);
+    path.push('/');
+    path.push_str(image_id);
+    if thumbnail {
+        path.push_str("_thumb");
+    }
+    path.push_str(".webp");
     path
 }
 
@@ -1,19 +1,38 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, LockResult, RwLock};
 
 use ehttp::{Headers, Mode, Request};
 use lazy_static::lazy_static;
-use log::{debug, info};
+use log::{debug, info, warn};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{EventSource, File, HtmlInputElement, MessageEvent};
 
-use crate::app::{AuthInfo, ALL_ACCOUNTS, AUTH_INFO};
+use crate::app::{AuthInfo, ALL_ACCOUNTS, AUTH_INFO, JOB_STATUSES, NOTIFICATIONS, QUOTA_OVERVIEW, REVIEW_QUEUE, SAVED_DRAFT_ID, SEARCH_RESULTS, SESSIONS, STAFF_STATS, SUBMIT_ERROR};
 use crate::ui::spaces::fiche_space::FicheSpace;
 use crate::App;
-use shared::fiche_rp::{FicheRP, ReviewMessage};
-use shared::user::FrontAccount;
+use shared::events::{LiveEvent, LiveEventKind};
+use shared::fiche_rp::{ApprovalQuorum, FicheRP, FicheState, ImageKind, LifecycleChange, ReviewMessage, ReviewQueue};
+use shared::jobs::JobStatus;
+use shared::legal::LegalVersions;
+use shared::markdown::MarkdownRules;
+use shared::mentions::MentionNotification;
+use shared::quota::QuotaOverview;
+use shared::search::SearchHit;
+use shared::stats::StaffStats;
+use shared::user::{AuthAccount, FrontAccount, SessionInfo};
 use shared::website_meta::WebsiteMeta;
 
 pub const IS_DEBUG: bool = cfg!(debug_assertions);
 
+static IS_LISTENING_EVENTS: AtomicBool = AtomicBool::new(false);
+/// Set while an accounts refresh asked by the live events runs
+static IS_REFRESHING_ACCOUNTS: AtomicBool = AtomicBool::new(false);
+/// Events arrived during that refresh, one more is needed once it ends
+static ARE_ACCOUNTS_STALE: AtomicBool = AtomicBool::new(false);
+
 pub fn get_oath2_url() -> String {
     let api_url: String = format!("{}api/oauth2/auth", get_api_path());
     api_url
@@ -27,38 +46,109 @@ pub fn authenticate() {
         ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
             let mut result = result.unwrap();
             if result.status == 200 {
-                let account: FrontAccount = result.clone().json().unwrap();
+                let auth_account: AuthAccount = result.clone().json().unwrap();
                 match AUTH_INFO.clone().write() {
                     Ok(mut lock) => {
-                        lock.account = Option::from(account);
+                        lock.account = Option::from(auth_account.account);
+                        lock.terms_accepted = auth_account.terms_accepted;
                         lock.authenticated = true;
                     }
                     Err(_) => {}
                 };
                 retrieve_accounts();
                 retrieve_whitelist();
+                retrieve_approval_quorum();
+                retrieve_markdown_rules();
+                retrieve_legal_versions();
+                retrieve_notifications();
             }
         });
     }
 }
 
 pub fn retrieve_accounts() {
+    fetch_accounts(|| {});
+}
+
+/// `on_done` runs once the request ends, whether the accounts were updated or not
+fn fetch_accounts(on_done: impl FnOnce() + Send + 'static) {
     let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
     let api_url: String = format!("{}api/front/retrieve_accounts?auth_id={}", get_api_path(), auth_id);
     let mut request: Request = Request::get(api_url);
 
     ehttp::fetch(request, |result: ehttp::Result<ehttp::Response>| {
-        let mut result = result.unwrap();
-        if result.status == 200 {
-            let accounts: Vec<FrontAccount> = result.clone().json().unwrap();
-            match ALL_ACCOUNTS.clone().write() {
-                Ok(mut lock) => {
-                    *lock = accounts;
+        match result {
+            Ok(result) if result.status == 200 => {
+                let accounts: Vec<FrontAccount> = result.json().unwrap();
+                match ALL_ACCOUNTS.clone().write() {
+                    Ok(mut lock) => {
+                        *lock = accounts;
+                    }
+                    Err(_) => {}
+                };
+            }
+            Ok(result) => warn!("Can't retrieve the accounts: {}", result.status),
+            Err(err) => warn!("Can't retrieve the accounts: {}", err),
+        }
+        on_done();
+    });
+}
+
+/// Refresh asked by a live event. A burst of events (a review touching several fiches, a resync) ends up in at most
+/// two requests instead of one per event
+fn refresh_accounts(ctx: egui::Context) {
+    if IS_REFRESHING_ACCOUNTS.swap(true, Ordering::SeqCst) {
+        ARE_ACCOUNTS_STALE.store(true, Ordering::SeqCst);
+        return;
+    }
+    fetch_accounts(move || {
+        IS_REFRESHING_ACCOUNTS.store(false, Ordering::SeqCst);
+        ctx.request_repaint();
+        if ARE_ACCOUNTS_STALE.swap(false, Ordering::SeqCst) {
+            refresh_accounts(ctx);
+        }
+    });
+}
+
+/// Opens the Server-Sent Events stream of the backend, every event refreshes the accounts and repaints the UI.
+/// The browser reconnects by itself when the connection drops, and gives up once the session ended (401 on reconnection).
+pub fn listen_live_events(ctx: egui::Context) {
+    let Some(Ok(auth_id)) = wasm_cookies::get("auth_id") else {
+        return;
+    };
+    if IS_LISTENING_EVENTS.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let api_url: String = format!("{}api/front/events?auth_id={}", get_api_path(), auth_id);
+    let event_source: EventSource = match EventSource::new(&api_url) {
+        Ok(event_source) => event_source,
+        Err(err) => {
+            warn!("Can't open the live events stream: {:?}", err);
+            IS_LISTENING_EVENTS.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |message: MessageEvent| {
+        let Some(data) = message.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<LiveEvent>(&data) {
+            Ok(event) => {
+                debug!("Live event {:?}", event);
+                let ctx: egui::Context = ctx.clone();
+                match event.kind {
+                    LiveEventKind::Mention => fetch_notifications(move || ctx.request_repaint()),
+                    _ => refresh_accounts(ctx),
                 }
-                Err(_) => {}
-            };
+            }
+            Err(err) => warn!("Unknown live event {}: {}", data, err),
         }
     });
+    event_source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    // The stream lives as long as the page
+    on_message.forget();
 }
 
 pub fn retrieve_whitelist() {
@@ -80,9 +170,242 @@ pub fn retrieve_whitelist() {
     });
 }
 
+pub fn retrieve_quota_overview() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_quota_overview?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let overview: QuotaOverview = result.json().unwrap();
+            match QUOTA_OVERVIEW.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(overview);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn retrieve_search(query: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/search?auth_id={}&query={}", get_api_path(), auth_id, encode_query_value(query));
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let hits: Vec<SearchHit> = result.json().unwrap();
+            match SEARCH_RESULTS.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(hits);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn retrieve_notifications() {
+    fetch_notifications(|| {});
+}
+
+fn fetch_notifications(on_update: impl FnOnce() + Send + 'static) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_notifications?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let notifications: Vec<MentionNotification> = result.json().unwrap();
+            match NOTIFICATIONS.clone().write() {
+                Ok(mut lock) => {
+                    *lock = notifications;
+                }
+                Err(_) => {}
+            };
+            on_update();
+        }
+    });
+}
+
+pub fn retrieve_markdown_rules() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_markdown_rules?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let markdown_rules: MarkdownRules = result.json().unwrap();
+            match AUTH_INFO.clone().write() {
+                Ok(mut lock) => {
+                    lock.markdown_rules = markdown_rules;
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn retrieve_legal_versions() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_legal_versions?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let legal_versions: LegalVersions = result.json().unwrap();
+            match AUTH_INFO.clone().write() {
+                Ok(mut lock) => {
+                    lock.legal_versions = Some(legal_versions);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+/// Accepts the texts shown to the user. The account is reloaded so the acceptance modal closes, a version published
+/// in the meantime is fetched instead and asked again
+pub fn post_terms_acceptance(versions: LegalVersions) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/accept_terms?auth_id={}&cgu={}&privacy={}", get_api_path(), auth_id, versions.cgu, versions.privacy), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        match result.status {
+            200 => authenticate(),
+            409 => retrieve_legal_versions(),
+            _ => info!("{}", result.text().unwrap_or_default()),
+        }
+    });
+}
+
+pub fn retrieve_approval_quorum() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_approval_quorum?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let approval_quorum: ApprovalQuorum = result.json().unwrap();
+            match AUTH_INFO.clone().write() {
+                Ok(mut lock) => {
+                    lock.approval_quorum = approval_quorum;
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn retrieve_staff_stats() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_staff_stats?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        let stats: Result<StaffStats, String> = match result.status {
+            200 => Ok(result.json().unwrap()),
+            401 => Err("Les statistiques sont réservées aux responsables.".to_string()),
+            status => Err(format!("Impossible de charger les statistiques (erreur {}).", status)),
+        };
+        match STAFF_STATS.clone().write() {
+            Ok(mut lock) => {
+                *lock = Some(stats);
+            }
+            Err(_) => {}
+        };
+    });
+}
+
+pub fn retrieve_job_statuses() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_job_statuses?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let statuses: Vec<JobStatus> = result.json().unwrap();
+            match JOB_STATUSES.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(statuses);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn retrieve_review_queue(queue: ReviewQueue) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let queue_name: String = serde_json::to_value(queue).unwrap().as_str().unwrap().to_string();
+    let api_url: String = format!("{}api/front/retrieve_review_queue?auth_id={}&queue={}", get_api_path(), auth_id, queue_name);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let fiche_ids: Vec<String> = result.json().unwrap();
+            match REVIEW_QUEUE.clone().write() {
+                Ok(mut lock) => {
+                    *lock = fiche_ids;
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn post_fiche_claim(ficherp_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/claim_fiche?auth_id={}&fiche_id={}", get_api_path(), auth_id, ficherp_id));
+}
+
+pub fn post_fiche_unclaim(ficherp_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/unclaim_fiche?auth_id={}&fiche_id={}", get_api_path(), auth_id, ficherp_id));
+}
+
+pub fn post_fiche_assign(ficherp_id: &str, reviewer_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/assign_fiche?auth_id={}&fiche_id={}&user_id={}", get_api_path(), auth_id, ficherp_id, reviewer_id));
+}
+
+pub fn post_fiche_vote(ficherp_id: &str, approve: bool) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/vote_fiche?auth_id={}&fiche_id={}&approve={}", get_api_path(), auth_id, ficherp_id, approve));
+}
+
+fn post_and_refresh_accounts(api_url: String) {
+    let request: Request = post_json(api_url, vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+
+        if result.status == 200 {
+            info!("{}", result.text().unwrap_or_default());
+            retrieve_accounts();
+        } else {
+            report_submit_error(&result);
+        }
+    });
+}
+
 pub fn post_ficherp(ficherp: &FicheRP) {
     let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
-    let api_url: String = format!("{}api/front/submit_ficherp?auth_id={}", get_api_path(), auth_id);
+    let mut api_url: String = format!("{}api/front/submit_ficherp?auth_id={}", get_api_path(), auth_id);
+    if ficherp.state == FicheState::Draft && !ficherp.id.is_empty() {
+        api_url.push_str(&format!("&fiche_id={}", ficherp.id));
+    }
     let request: Request = post_json(api_url, serde_json::to_string(ficherp).unwrap().into_bytes());
 
     ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
@@ -90,9 +413,42 @@ pub fn post_ficherp(ficherp: &FicheRP) {
 
         if result.status == 200 {
             authenticate();
+        } else {
+            report_submit_error(&result);
+        }
+    });
+}
+/// Autosave of the fiche being written, the id of a newly created draft lands in `SAVED_DRAFT_ID`
+pub fn post_draft(ficherp: &FicheRP) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let mut api_url: String = format!("{}api/front/save_draft?auth_id={}", get_api_path(), auth_id);
+    let is_new_draft: bool = ficherp.id.is_empty();
+    if !is_new_draft {
+        api_url.push_str(&format!("&fiche_id={}", ficherp.id));
+    }
+    let request: Request = post_json(api_url, serde_json::to_string(ficherp).unwrap().into_bytes());
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+
+        if result.status == 200 && is_new_draft {
+            let draft_id: String = result.json().unwrap();
+            match SAVED_DRAFT_ID.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(draft_id);
+                }
+                Err(_) => {}
+            };
+            retrieve_accounts();
         }
     });
 }
+
+pub fn post_draft_deletion(ficherp_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/delete_draft?auth_id={}&fiche_id={}", get_api_path(), auth_id, ficherp_id));
+}
+
 pub fn post_ficherp_admin(ficherp: &FicheRP, target_account: &FrontAccount) {
     let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
     let api_url: String = format!("{}api/front/submit_ficherp_admin?auth_id={}&user_id={}", get_api_path(), auth_id, target_account.discord_user.id);
@@ -103,6 +459,8 @@ pub fn post_ficherp_admin(ficherp: &FicheRP, target_account: &FrontAccount) {
 
         if result.status == 200 {
             authenticate();
+        } else {
+            report_submit_error(&result);
         }
     });
 }
@@ -117,6 +475,23 @@ pub fn post_ficherp_modif(ficherp: &FicheRP) {
 
         if result.status == 200 {
             authenticate();
+        } else {
+            report_submit_error(&result);
+        }
+    });
+}
+
+pub fn post_character_status(ficherp_id: &str, change: &LifecycleChange) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/set_character_status?auth_id={}&fiche_id={}", get_api_path(), auth_id, ficherp_id);
+    let request: Request = post_json(api_url, serde_json::to_string(change).unwrap().into_bytes());
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        info!("{}", result.text().unwrap_or_default());
+
+        if result.status == 200 {
+            retrieve_accounts();
         }
     });
 }
@@ -136,6 +511,178 @@ pub fn post_comment(comment: &ReviewMessage, ficherp_id: String) {
     });
 }
 
+pub fn post_comment_edit(ficherp_id: &str, message_id: &str, content: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/edit_comment?auth_id={}&fiche_id={}&message_id={}", get_api_path(), auth_id, ficherp_id, message_id);
+    let request: Request = post_json(api_url, serde_json::to_string(content).unwrap().into_bytes());
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        info!("{}", result.text().unwrap_or_default());
+
+        if result.status == 200 {
+            retrieve_accounts();
+        }
+    });
+}
+
+pub fn post_comment_deletion(ficherp_id: &str, message_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/delete_comment?auth_id={}&fiche_id={}&message_id={}", get_api_path(), auth_id, ficherp_id, message_id));
+}
+
+pub fn post_notifications_read() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/read_notifications?auth_id={}", get_api_path(), auth_id), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        if result.is_ok_and(|result| result.status == 200) {
+            retrieve_notifications();
+        }
+    });
+}
+
+/// The backend removes the session and its cookie, the page is reloaded back to the login screen
+pub fn post_logout() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/logout?auth_id={}", get_api_path(), auth_id), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        if result.is_ok_and(|result| result.status == 200) {
+            wasm_cookies::delete("auth_id");
+            web_sys::window().expect("no global `window` exists").location().reload().expect("Can't reload");
+        }
+    });
+}
+
+pub fn retrieve_sessions() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/retrieve_sessions?auth_id={}", get_api_path(), auth_id);
+    let request: Request = Request::get(api_url);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            let sessions: Vec<SessionInfo> = result.json().unwrap();
+            match SESSIONS.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(sessions);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn post_session_revoke(session_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/revoke_session?auth_id={}&session_id={}", get_api_path(), auth_id, session_id), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        if result.is_ok_and(|result| result.status == 200) {
+            retrieve_sessions();
+        }
+    });
+}
+
+/// Logs an account out of every device, refused by the backend unless the user is a lead
+pub fn post_account_sessions_revoke(discord_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/revoke_account_sessions?auth_id={}&user_id={}", get_api_path(), auth_id, discord_id), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        info!("{}", result.text().unwrap_or_default());
+        let error: Option<String> = match result.status {
+            200 => None,
+            401 => Some("La déconnexion des utilisateurs est réservée aux responsables.".to_string()),
+            404 => Some("Cet utilisateur n'existe plus.".to_string()),
+            status => Some(format!("Impossible de déconnecter l'utilisateur (erreur {}).", status)),
+        };
+        if let Some(error) = error {
+            match SUBMIT_ERROR.clone().write() {
+                Ok(mut lock) => {
+                    *lock = Some(error);
+                }
+                Err(_) => {}
+            };
+        }
+    });
+}
+
+pub fn get_personal_data_url() -> String {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    format!("{}api/front/retrieve_personal_data?auth_id={}", get_api_path(), auth_id)
+}
+
+/// Deletes the account of the user, the page is reloaded back to the login screen
+pub fn post_account_deletion() {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let request: Request = post_json(format!("{}api/front/delete_account?auth_id={}", get_api_path(), auth_id), vec![]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            wasm_cookies::delete("auth_id");
+            web_sys::window().expect("no global `window` exists").location().reload().expect("Can't reload");
+        } else {
+            report_submit_error(&result);
+        }
+    });
+}
+
+/// Opens the file picker of the browser then uploads the chosen image, the backend checks its real type
+pub fn pick_fiche_image(ficherp_id: &str, kind: ImageKind) {
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let input: HtmlInputElement = document.create_element("input").expect("Can't create input").unchecked_into();
+    input.set_type("file");
+    input.set_accept("image/png,image/jpeg,image/webp,image/gif");
+
+    let ficherp_id: String = ficherp_id.to_string();
+    let picker: HtmlInputElement = input.clone();
+    let on_change = Closure::once_into_js(move || {
+        let Some(file) = picker.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        wasm_bindgen_futures::spawn_local(async move {
+            match JsFuture::from(file.array_buffer()).await {
+                Ok(buffer) => post_fiche_image(&ficherp_id, kind, js_sys::Uint8Array::new(&buffer).to_vec()),
+                Err(err) => warn!("Can't read {}: {:?}", file.name(), err),
+            }
+        });
+    });
+    input.set_onchange(Some(on_change.unchecked_ref()));
+    input.click();
+}
+
+fn post_fiche_image(ficherp_id: &str, kind: ImageKind, bytes: Vec<u8>) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    let api_url: String = format!("{}api/front/upload_image?auth_id={}&fiche_id={}&kind={}", get_api_path(), auth_id, ficherp_id, kind.as_ref());
+    let mut request: Request = post_json(api_url, bytes);
+    request.headers = Headers::new(&[("Accept", "*/*"), ("Content-Type", "application/octet-stream")]);
+
+    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
+        let result = result.unwrap();
+        if result.status == 200 {
+            retrieve_accounts();
+        } else {
+            report_submit_error(&result);
+        }
+    });
+}
+
+pub fn post_fiche_image_deletion(ficherp_id: &str, image_id: &str) {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    post_and_refresh_accounts(format!("{}api/front/delete_image?auth_id={}&fiche_id={}&image_id={}", get_api_path(), auth_id, ficherp_id, image_id));
+}
+
+pub fn get_export_url(ficherp_id: &str, format: &str, include_reviews: bool) -> String {
+    let auth_id: String = wasm_cookies::get("auth_id").unwrap().unwrap();
+    format!("{}api/front/export_fiche?auth_id={}&fiche_id={}&format={}&include_reviews={}", get_api_path(), auth_id, ficherp_id, format, include_reviews)
+}
+
 pub fn get_api_path() -> String {
     let path: String = if IS_DEBUG {
         "http://localhost:2828/".to_string()
@@ -147,6 +694,29 @@ pub fn get_api_path() -> String {
     path
 }
 
+/// Refused submissions (quotas, ...) are explained to the user, the other failures stay in the logs
+fn report_submit_error(result: &ehttp::Response) {
+    let message: String = result.text().unwrap_or_default().to_string();
+    info!("{}", message);
+
+    if result.status == 403 || result.status == 409 || result.status == 413 || result.status == 415 {
+        match SUBMIT_ERROR.clone().write() {
+            Ok(mut lock) => {
+                *lock = Some(message);
+            }
+            Err(_) => {}
+        };
+    }
+}
+
+/// Percent-encoding of the free text sent in a query string
+fn encode_query_value(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
 fn post_json(url: String, body: Vec<u8>) -> Request {
     Request {
         method: "POST".to_owned(),
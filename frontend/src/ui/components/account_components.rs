@@ -0,0 +1,90 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use egui::{hex_color, Button, Hyperlink, OpenUrl, RichText, TextEdit, Ui};
+
+use shared::legal::LegalVersions;
+use shared::user::SessionInfo;
+
+use crate::backend_handler::{get_personal_data_url, post_account_deletion, post_logout, post_session_revoke, post_terms_acceptance, retrieve_sessions};
+
+/// Text to type before the account can be deleted
+const DELETION_CONFIRMATION: &str = "SUPPRIMER";
+
+/// Account menu of the top bar, opens the session list or the personal data window
+pub fn account_menu(ui: &mut Ui, is_viewing_sessions: &mut bool, is_viewing_personal_data: &mut bool) {
+    ui.menu_button("👤", |ui| {
+        if ui.button("Mes sessions").clicked() {
+            retrieve_sessions();
+            *is_viewing_sessions = true;
+            ui.close_menu();
+        }
+        if ui.button("Mes données").clicked() {
+            *is_viewing_personal_data = true;
+            ui.close_menu();
+        }
+        if ui.button("Se déconnecter").clicked() {
+            post_logout();
+            ui.close_menu();
+        }
+    });
+}
+
+/// Devices logged in to the account, any of them but the current one can be revoked
+pub fn session_list(ui: &mut Ui, sessions: &Vec<SessionInfo>) {
+    egui::Grid::new("sessions").num_columns(4).striped(true).show(ui, |ui| {
+        ui.label(RichText::new("Appareil").strong());
+        ui.label(RichText::new("Connexion").strong());
+        ui.label(RichText::new("Expiration").strong());
+        ui.label("");
+        ui.end_row();
+
+        sessions.iter().for_each(|session| {
+            let device: &str = if session.user_agent.is_empty() { "Appareil inconnu" } else { &session.user_agent };
+            ui.add(egui::Label::new(device).truncate()).on_hover_text(device);
+            ui.label(format_date(session.created_at));
+            ui.label(format_date(session.expires_at));
+            if session.current {
+                ui.label(RichText::new("Cet appareil").italics());
+            } else if ui.button("Révoquer").clicked() {
+                post_session_revoke(&session.id);
+            }
+            ui.end_row();
+        });
+    });
+}
+
+/// Download of the personal data and deletion of the account, which has to be confirmed by typing `DELETION_CONFIRMATION`
+pub fn personal_data(ui: &mut Ui, confirmation: &mut String) {
+    ui.label("Téléchargez une copie de tout ce que le site conserve sur vous : compte, sessions, fiches et leurs versions, messages écrits et notifications.");
+    if ui.button("Télécharger mes données").clicked() {
+        ui.ctx().open_url(OpenUrl::new_tab(get_personal_data_url()));
+    }
+
+    ui.separator();
+    ui.label(RichText::new("Supprimer mon compte").strong());
+    ui.label(RichText::new("Vos fiches, images, sessions et notifications sont supprimées définitivement. Les messages que vous avez écrits sur les fiches des autres joueurs restent visibles, sans votre nom.").color(hex_color!("#E67E22")));
+    ui.horizontal(|ui| {
+        ui.label(format!("Tapez {} pour confirmer :", DELETION_CONFIRMATION));
+        ui.add(TextEdit::singleline(confirmation).desired_width(120.0));
+    });
+    if ui.add_enabled(confirmation.as_str() == DELETION_CONFIRMATION, Button::new("Supprimer définitivement mon compte")).clicked() {
+        post_account_deletion();
+    }
+}
+
+/// Content of the acceptance modal shown when the account hasn't accepted the current versions of the texts
+pub fn terms_acceptance(ui: &mut Ui, versions: LegalVersions, is_checked: &mut bool) {
+    ui.label("Les conditions générales d'utilisation ou la politique de confidentialité ont été mises à jour. Lisez-les et acceptez-les pour continuer à soumettre des fiches.");
+    ui.horizontal(|ui| {
+        ui.add(Hyperlink::from_label_and_url(format!("Conditions Générales d'Utilisation (version {})", versions.cgu), "cgu.html").open_in_new_tab(true));
+        ui.add(Hyperlink::from_label_and_url(format!("Politique de Confidentialité (version {})", versions.privacy), "privacy.html").open_in_new_tab(true));
+    });
+    ui.checkbox(is_checked, "J'ai lu et j'accepte ces documents");
+    if ui.add_enabled(*is_checked, Button::new("Accepter")).clicked() {
+        post_terms_acceptance(versions);
+        *is_checked = false;
+    }
+}
+
+fn format_date(timestamp: u64) -> String {
+    Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(timestamp as i64, 0)).format("%d-%m-%Y %H:%M").to_string()
+}
@@ -4,20 +4,23 @@ use chrono::{NaiveDateTime, TimeZone, Utc};
 use eframe::emath::Align;
 use egui::ecolor::color_hex::color_from_hex;
 use egui::text::LayoutJob;
-use egui::{hex_color, Color32, Image, Layout, Response, RichText, TextFormat, TextStyle};
-use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
+use egui::{hex_color, Button, Color32, Frame, Id, Image, Layout, Response, RichText, TextEdit, TextFormat, TextStyle};
+use egui_commonmark::CommonMarkCache;
 use log::warn;
 use strum::IntoEnumIterator;
 use web_time::{SystemTime, UNIX_EPOCH};
 
 use shared::discord::User;
-use shared::fiche_rp::{FicheRP, FicheState, FicheStateIter, ReviewMessage};
+use shared::fiche_rp::{FicheRP, FicheState, ReviewMessage};
 use shared::permissions::DiscordRole;
 use shared::user::FrontAccount;
 
 use crate::app::{avatar_resolver, AuthInfo, ALL_ACCOUNTS, AUTH_INFO, SELECTED_ROLE};
-use crate::backend_handler::post_comment;
-use crate::ui::components::fiche_components::state_badge;
+use crate::backend_handler::{post_comment, post_comment_deletion, post_comment_edit};
+use crate::ui::components::fiche_components::{markdown_view, state_badge};
+use crate::ui::components::mention_components::{display_mentions, mention_suggestions};
+
+const REPLY_INDENT: f32 = 24.0;
 
 pub fn edit_comment_window(ui: &mut egui::Ui, ficherp_id: String, review_message: &mut ReviewMessage, cache: Arc<RwLock<CommonMarkCache>>, selected_fiche_account: &mut Option<(FrontAccount, FicheRP)>) -> bool {
     let mut close: bool = false;
@@ -25,7 +28,15 @@ pub fn edit_comment_window(ui: &mut egui::Ui, ficherp_id: String, review_message
         match SELECTED_ROLE.try_read() {
             Ok(role_lock) => {
                 ui.horizontal(|ui| {
-                    if *role_lock != DiscordRole::User {
+                    if review_message.parent_id.is_some() {
+                        // A reply only comments, the state of the fiche changes through a new message
+                        ui.label(RichText::new("Réponse").strong());
+                        if *role_lock != DiscordRole::User {
+                            ui.checkbox(&mut review_message.is_private, "Commentaire privé ?");
+                        }
+                        review_message.is_comment = true;
+                        review_message.set_state = FicheState::Comment;
+                    } else if *role_lock != DiscordRole::User {
                         ui.checkbox(&mut review_message.is_comment, "Commentaire ?");
                         if review_message.is_comment {
                             ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
@@ -34,18 +45,19 @@ pub fn edit_comment_window(ui: &mut egui::Ui, ficherp_id: String, review_message
                             });
                         } else {
                             ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                // CONFORME comes from the reviewer votes, ACCEPTÉE only once it is reached
+                                let is_staff_validated: bool = selected_fiche_account.as_ref().is_some_and(|(_, ficherp)| ficherp.state == FicheState::StaffValidated);
                                 egui::ComboBox::from_label("Statut de la fiche").selected_text(review_message.set_state.get_text()).show_ui(ui, |ui| {
-                                    let state_iter: FicheStateIter = FicheState::iter();
+                                    let state_iter = FicheState::iter().filter(|state| state != &FicheState::Comment && state != &FicheState::Draft && state != &FicheState::StaffValidated && (state != &FicheState::Accepted || is_staff_validated));
 
                                     if *role_lock == DiscordRole::Admin || *role_lock == DiscordRole::PlatformAdmin {
-                                        state_iter.filter(|state| state != &FicheState::Comment).for_each(|state| {
+                                        state_iter.for_each(|state| {
                                             ui.selectable_value(&mut review_message.set_state, state.clone(), state.get_text());
                                         });
                                     } else if *role_lock == DiscordRole::Scenarist {
-                                        ui.selectable_value(&mut review_message.set_state, FicheState::StaffValidated, FicheState::StaffValidated.get_text());
                                         ui.selectable_value(&mut review_message.set_state, FicheState::Refused, FicheState::Refused.get_text());
                                     } else if *role_lock == DiscordRole::LeadScenarist || *role_lock == DiscordRole::LeadMed {
-                                        state_iter.filter(|state| state != &FicheState::Comment).for_each(|state| {
+                                        state_iter.for_each(|state| {
                                             ui.selectable_value(&mut review_message.set_state, state.clone(), state.get_text());
                                         });
                                     }
@@ -67,14 +79,21 @@ pub fn edit_comment_window(ui: &mut egui::Ui, ficherp_id: String, review_message
                     size.x *= 0.99;
 
                     ui.add_sized(size, egui::TextEdit::multiline(&mut review_message.content));
+                    mention_suggestions(ui, &mut review_message.content, &ficherp_id, review_message.is_private);
 
                     ui.label(RichText::new("Preview : ").text_style(TextStyle::Name("heading3".into())).strong());
 
-                    CommonMarkViewer::new().show(ui, &mut cache, &review_message.content);
+                    markdown_view(ui, &mut cache, &display_mentions(&review_message.content));
                 });
 
+                let max_length: usize = AUTH_INFO.try_read().map(|auth_info| auth_info.markdown_rules.max_comment_length).unwrap_or(usize::MAX);
+                let length: usize = review_message.content.chars().count();
+
                 ui.vertical_centered(|ui| {
-                    if ui.button("Poster le commentaire/réponse").clicked() {
+                    if length > max_length {
+                        ui.colored_label(Color32::RED, format!("Commentaire trop long : {} / {} caractères", length, max_length));
+                    }
+                    if ui.add_enabled(length <= max_length, Button::new("Poster le commentaire/réponse")).clicked() {
                         review_message.date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
                         post_comment(review_message, ficherp_id);
 
@@ -93,11 +112,16 @@ pub fn edit_comment_window(ui: &mut egui::Ui, ficherp_id: String, review_message
 
 pub fn comment_bubble(ui: &mut egui::Ui, review_message: &ReviewMessage, cache: Arc<RwLock<CommonMarkCache>>) -> Response {
     let binding = ALL_ACCOUNTS.read().unwrap();
-    let account: &FrontAccount = binding.iter().find(|front_account| front_account.discord_user.id == review_message.discord_id).unwrap();
+    // Authors of deleted accounts are anonymised, their messages stay
+    let deleted_account: FrontAccount = FrontAccount {
+        discord_user: User { id: review_message.discord_id.clone(), global_name: "Compte supprimé".to_string(), avatar: "".to_string() },
+        ..FrontAccount::default()
+    };
+    let account: &FrontAccount = binding.iter().find(|front_account| front_account.discord_user.id == review_message.discord_id).unwrap_or(&deleted_account);
     let user: &User = &account.discord_user;
-    let avatar_url = avatar_resolver(&user.id);
+    let avatar_url = avatar_resolver(&user.id, 64);
 
-    let avatar_image: Image = Image::new(avatar_url).fit_to_original_size(0.5).maintain_aspect_ratio(true).rounding(100.0);
+    let avatar_image: Image = Image::new(avatar_url).fit_to_original_size(1.0).maintain_aspect_ratio(true).rounding(100.0);
     let datetime = Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(review_message.date as i64, 0));
 
     let formatted_date = datetime.format("%d-%m-%Y %H:%M:%S").to_string();
@@ -167,9 +191,24 @@ pub fn comment_bubble(ui: &mut egui::Ui, review_message: &ReviewMessage, cache:
 
         let mut cache: RwLockWriteGuard<CommonMarkCache> = cache.write().expect("Can't access common_mark_cache");
 
-        egui::ScrollArea::vertical().id_source((&review_message.content, &review_message.date)).show(ui, |ui| {
-            CommonMarkViewer::new().show(ui, &mut cache, &review_message.content);
-        });
+        if let Some(deletion) = &review_message.deletion {
+            ui.label(RichText::new(format!("Message supprimé le {}", format_date(deletion.date))).italics());
+        }
+        // The backend only sends the content of a deleted message to the staff
+        if !review_message.content.is_empty() {
+            egui::ScrollArea::vertical().id_source((&review_message.content, &review_message.date)).show(ui, |ui| {
+                markdown_view(ui, &mut cache, &display_mentions(&review_message.content));
+            });
+        }
+
+        if let Some(last_edit) = review_message.edits.last() {
+            egui::CollapsingHeader::new(format!("Modifié le {}", format_date(last_edit.date))).id_source(("message_edits", &review_message.id)).show(ui, |ui| {
+                review_message.edits.iter().rev().for_each(|edit| {
+                    ui.label(RichText::new(format!("Avant le {} :", format_date(edit.date))).strong());
+                    markdown_view(ui, &mut cache, &display_mentions(&edit.content));
+                });
+            });
+        }
         ui.separator();
 
         ui.vertical_centered(|ui| {
@@ -177,3 +216,90 @@ pub fn comment_bubble(ui: &mut egui::Ui, review_message: &ReviewMessage, cache:
         });
     }).response
 }
+
+/// Review messages as threads, replies indented under the message they answer. Returns the reply started by the user.
+pub fn comment_thread(ui: &mut egui::Ui, ficherp: &FicheRP, parent_id: Option<&str>, user_account: &FrontAccount, is_staff: bool, frame: Frame, cache: Arc<RwLock<CommonMarkCache>>) -> Option<ReviewMessage> {
+    let mut reply: Option<ReviewMessage> = None;
+
+    ficherp.thread(parent_id).into_iter().filter(|review_message| !review_message.is_private || is_staff).for_each(|review_message| {
+        frame.show(ui, |ui| {
+            comment_bubble(ui, review_message, cache.clone());
+            if let Some(new_reply) = message_controls(ui, ficherp, review_message, user_account, is_staff) {
+                reply = Some(new_reply);
+            }
+        });
+
+        if !ficherp.thread(Some(&review_message.id)).is_empty() {
+            ui.horizontal(|ui| {
+                ui.add_space(REPLY_INDENT);
+                ui.vertical(|ui| {
+                    if let Some(new_reply) = comment_thread(ui, ficherp, Some(&review_message.id), user_account, is_staff, frame, cache.clone()) {
+                        reply = Some(new_reply);
+                    }
+                });
+            });
+        }
+    });
+    reply
+}
+
+/// Reply, edit and delete buttons of a message, the edit happens in place
+fn message_controls(ui: &mut egui::Ui, ficherp: &FicheRP, review_message: &ReviewMessage, user_account: &FrontAccount, is_staff: bool) -> Option<ReviewMessage> {
+    // Messages posted before the threads have no id until the migration ran
+    if review_message.id.is_empty() || review_message.is_deleted() {
+        return None;
+    }
+
+    let mut reply: Option<ReviewMessage> = None;
+    let edit_id: Id = Id::new(("editing_message", &review_message.id));
+    let mut edit_buffer: Option<String> = ui.data(|data| data.get_temp::<String>(edit_id));
+    let mut is_edit_closed: bool = false;
+
+    ui.horizontal(|ui| {
+        if ui.button("Répondre").clicked() {
+            reply = Some(ReviewMessage {
+                discord_id: user_account.discord_user.id.clone(),
+                content: "".to_string(),
+                date: 0,
+                is_private: review_message.is_private,
+                is_comment: true,
+                set_state: FicheState::Comment,
+                id: "".to_string(),
+                parent_id: Some(review_message.id.clone()),
+                edits: vec![],
+                deletion: None,
+                search_terms: vec![],
+            });
+        }
+        if review_message.can_be_edited_by(&user_account.discord_user.id) && edit_buffer.is_none() && ui.button("Modifier").clicked() {
+            edit_buffer = Some(review_message.content.clone());
+        }
+        if review_message.can_be_deleted_by(&user_account.discord_user.id, is_staff) && ui.button("Supprimer").clicked() {
+            post_comment_deletion(&ficherp.id, &review_message.id);
+        }
+    });
+
+    if let Some(buffer) = &mut edit_buffer {
+        ui.add(TextEdit::multiline(buffer).desired_width(f32::INFINITY));
+        mention_suggestions(ui, buffer, &ficherp.id, review_message.is_private);
+        ui.horizontal(|ui| {
+            if ui.button("Enregistrer").clicked() {
+                post_comment_edit(&ficherp.id, &review_message.id, buffer);
+                is_edit_closed = true;
+            }
+            if ui.button("Annuler").clicked() {
+                is_edit_closed = true;
+            }
+        });
+    }
+
+    ui.data_mut(|data| match edit_buffer {
+        Some(buffer) if !is_edit_closed => data.insert_temp(edit_id, buffer),
+        _ => data.remove::<String>(edit_id),
+    });
+    reply
+}
+
+fn format_date(timestamp: u64) -> String {
+    Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(timestamp as i64, 0)).format("%d-%m-%Y %H:%M").to_string()
+}
@@ -1,28 +1,32 @@
 use std::ops::Add;
 use std::str::SplitWhitespace;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use eframe::emath::Align;
 use egui::scroll_area::ScrollBarVisibility;
 use egui::text::LayoutJob;
-use egui::{Button, Color32, FontSelection, Image, Layout, OpenUrl, Response, RichText, TextBuffer, TextEdit, TextFormat, TextStyle};
+use egui::util::cache::{ComputerMut, FrameCache};
+use egui::{Button, Color32, FontSelection, Id, Image, Layout, OpenUrl, Response, RichText, TextBuffer, TextEdit, TextFormat, TextStyle};
 use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
 use strum::IntoEnumIterator;
 use web_time::{SystemTime, UNIX_EPOCH};
 
 use shared::discord::User;
-use shared::fiche_rp::{FicheRP, FicheState, FicheVersion, Job, MedicRank, MedicRole, MtfRole, ScienceRank, ScienceRole, SecurityRank, SecurityRole};
+use shared::fiche_rp::{CharacterStatus, FicheImage, FicheRP, FicheState, FicheVersion, ImageKind, Job, LifecycleChange, MedicRank, MedicRole, MtfRole, ScienceRank, ScienceRole, SecurityRank, SecurityRole, VoteTally};
+use shared::markdown::MarkdownRules;
+use shared::permissions::DiscordRole;
 use shared::user::FrontAccount;
 
-use crate::app::{avatar_resolver, AUTH_INFO};
+use crate::app::{avatar_resolver, fiche_image_resolver, ALL_ACCOUNTS, AUTH_INFO, SAVED_DRAFT_ID, SUBMIT_ERROR};
 use crate::app::{get_string, image_resolver, AuthInfo};
-use crate::backend_handler::{post_ficherp, post_ficherp_admin, post_ficherp_modif};
+use crate::backend_handler::{get_export_url, pick_fiche_image, post_character_status, post_draft, post_draft_deletion, post_fiche_assign, post_fiche_claim, post_fiche_image_deletion, post_fiche_unclaim, post_fiche_vote, post_ficherp, post_ficherp_admin, post_ficherp_modif};
 
-pub fn ficherp_bubble(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User) -> Response {
-    let avatar_url = avatar_resolver(&user.id);
+const AUTOSAVE_SECS: u64 = 30;
 
-    let avatar_image: Image = Image::new(avatar_url).fit_to_original_size(0.5).maintain_aspect_ratio(true).rounding(100.0);
+pub fn ficherp_bubble(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User) -> Response {
+    let avatar_image: Image = portrait_image(ficherp, user);
     let datetime = Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(ficherp.submission_date as i64, 0));
 
     let formatted_date = datetime.format("%d-%m-%Y %H:%M:%S").to_string();
@@ -45,12 +49,139 @@ pub fn ficherp_bubble(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User) -> Resp
 
         layout_job.append(&*ficherp.job.to_string(), 0.0, TextFormat { ..Default::default() });
         ui.label(layout_job);
+
+        if let Some(claim) = ficherp.active_claim(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()) {
+            let claim_text: String = if claim.assigned_by.is_some() {
+                format!("Assignée à {}", account_name(&claim.discord_id))
+            } else {
+                format!("Prise en charge par {}", account_name(&claim.discord_id))
+            };
+            ui.label(RichText::new(claim_text).italics());
+        }
+
+        if ficherp.state == FicheState::Accepted && ficherp.character_status() != CharacterStatus::Active {
+            ui.label(RichText::new(format!("Personnage {}", ficherp.character_status().get_text())).strong());
+        }
     }).response
 }
 
+/// Status changes of an accepted character, only the statuses the user may set are offered
+pub fn lifecycle_controls(ui: &mut egui::Ui, ficherp: &FicheRP, is_owner: bool, is_staff: bool, is_lead: bool) {
+    if ficherp.state != FicheState::Accepted {
+        return;
+    }
+    let current_status: CharacterStatus = ficherp.character_status();
+    let statuses: Vec<CharacterStatus> = CharacterStatus::iter().filter(|status| *status != current_status && status.can_be_set_by(is_owner, is_staff, is_lead)).collect();
+    if statuses.is_empty() {
+        return;
+    }
+
+    ui.menu_button("Statut du personnage", |ui| {
+        let reason_id: Id = ui.id().with("lifecycle_reason");
+        let mut reason: String = ui.data_mut(|data| data.get_temp::<String>(reason_id)).unwrap_or_default();
+
+        ui.label("Raison :");
+        ui.text_edit_singleline(&mut reason);
+        ui.data_mut(|data| data.insert_temp(reason_id, reason.clone()));
+
+        ui.separator();
+
+        ui.add_enabled_ui(!reason.trim().is_empty(), |ui| {
+            statuses.iter().for_each(|status| {
+                if ui.button(status.get_text()).clicked() {
+                    post_character_status(&ficherp.id, &LifecycleChange {
+                        status: *status,
+                        reason: reason.clone(),
+                        date: 0,
+                        set_by: "".to_string(),
+                    });
+                    ui.data_mut(|data| data.remove::<String>(reason_id));
+                    ui.close_menu();
+                }
+            });
+        });
+    });
+}
+
+/// Claim, release and (for leads) assignment of the review of a fiche
+pub fn claim_controls(ui: &mut egui::Ui, ficherp: &FicheRP, user_account: &FrontAccount, is_lead: bool) {
+    let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let active_claim = ficherp.active_claim(now);
+
+    ui.horizontal(|ui| {
+        match active_claim {
+            Some(claim) if claim.discord_id == user_account.discord_user.id => {
+                ui.label("Vous relisez cette fiche");
+                if ui.button("Libérer").clicked() {
+                    post_fiche_unclaim(&ficherp.id);
+                }
+            }
+            Some(claim) => {
+                ui.label(format!("Relue par {}", account_name(&claim.discord_id)));
+                if is_lead && ui.button("Libérer").clicked() {
+                    post_fiche_unclaim(&ficherp.id);
+                }
+            }
+            None => {
+                if ficherp.is_claimable() && ui.button("Prendre en charge").clicked() {
+                    post_fiche_claim(&ficherp.id);
+                }
+            }
+        }
+
+        if is_lead && ficherp.is_claimable() {
+            ui.menu_button("Assigner à", |ui| {
+                let whitelist: Vec<String> = AUTH_INFO.read().unwrap().website_meta.whitelist.clone();
+                let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
+                let all_account = binding.read().unwrap();
+
+                all_account.iter().filter(|account| is_reviewer(account, &whitelist)).for_each(|account| {
+                    if ui.button(&account.discord_user.global_name).clicked() {
+                        post_fiche_assign(&ficherp.id, &account.discord_user.id);
+                        ui.close_menu();
+                    }
+                });
+            });
+        }
+    });
+}
+
+/// Approval or rejection of a fiche by a reviewer, counted towards the quorum of its job family
+pub fn vote_controls(ui: &mut egui::Ui, ficherp: &FicheRP, user_account: &FrontAccount) {
+    if !ficherp.is_reviewable() {
+        return;
+    }
+    let current_vote: Option<bool> = ficherp.votes.iter().find(|vote| vote.discord_id == user_account.discord_user.id).map(|vote| vote.approve);
+
+    ui.horizontal(|ui| {
+        ui.label("Votre vote :");
+        if ui.selectable_label(current_vote == Some(true), "Approuver").clicked() && current_vote != Some(true) {
+            post_fiche_vote(&ficherp.id, true);
+        }
+        if ui.selectable_label(current_vote == Some(false), "Rejeter").clicked() && current_vote != Some(false) {
+            post_fiche_vote(&ficherp.id, false);
+        }
+    });
+}
+
+fn is_reviewer(account: &FrontAccount, whitelist: &Vec<String>) -> bool {
+    whitelist.contains(&account.discord_user.id) || DiscordRole::from_role_ids(&account.discord_roles).unwrap_or_default().iter().any(|role| {
+        *role == DiscordRole::PlatformAdmin || *role == DiscordRole::Admin || *role == DiscordRole::LeadScenarist || *role == DiscordRole::LeadMed || *role == DiscordRole::Scenarist
+    })
+}
+
+fn account_name(discord_id: &str) -> String {
+    match ALL_ACCOUNTS.try_read() {
+        Ok(all_account) => all_account.iter()
+                                      .find(|account| account.discord_user.id == discord_id)
+                                      .map(|account| account.discord_user.global_name.clone())
+                                      .unwrap_or_else(|| discord_id.to_string()),
+        Err(_) => discord_id.to_string(),
+    }
+}
+
 pub fn ficherp_viewer(ui: &mut egui::Ui, ficherp: &FicheRP, job_text_buffer: &mut String, user: &User, cache: Arc<RwLock<CommonMarkCache>>, is_viewing: &mut bool, mut is_editing_existing_fiche: &mut bool, new_fiche: &mut Option<FicheRP>, selected_fiche_account: &mut Option<(FrontAccount, FicheRP)>) {
-    let avatar_url = avatar_resolver(&user.id);
-    let avatar_image: Image = Image::new(avatar_url).fit_to_original_size(0.5).maintain_aspect_ratio(true).rounding(100.0);
+    let avatar_image: Image = portrait_image(ficherp, user);
     let datetime = Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(ficherp.submission_date as i64, 0));
 
     let formatted_date = datetime.format("%d-%m-%Y %H:%M:%S").to_string();
@@ -66,6 +197,23 @@ pub fn ficherp_viewer(ui: &mut egui::Ui, ficherp: &FicheRP, job_text_buffer: &mu
             if ui.add(history_btn).clicked() {
                 *is_viewing = true;
             }
+
+            ui.menu_button("Exporter la fiche", |ui| {
+                let include_reviews_id = ui.id().with("export_include_reviews");
+                let mut include_reviews: bool = ui.data_mut(|data| *data.get_temp_mut_or(include_reviews_id, false));
+
+                ui.checkbox(&mut include_reviews, "Inclure les avis publics");
+                ui.data_mut(|data| data.insert_temp(include_reviews_id, include_reviews));
+
+                ui.separator();
+
+                [("HTML", "html"), ("Markdown", "markdown"), ("PDF", "pdf")].iter().for_each(|(label, format)| {
+                    if ui.button(*label).clicked() {
+                        ui.ctx().open_url(OpenUrl::new_tab(get_export_url(&ficherp.id, format, include_reviews)));
+                        ui.close_menu();
+                    }
+                });
+            });
         });
 
         ui.horizontal(|ui| {
@@ -88,6 +236,17 @@ pub fn ficherp_viewer(ui: &mut egui::Ui, ficherp: &FicheRP, job_text_buffer: &mu
         layout_job.append(&*ficherp.job.to_string(), 0.0, TextFormat { ..Default::default() });
         ui.label(layout_job);
 
+        if let Some(change) = ficherp.lifecycle.last().filter(|_| ficherp.state == FicheState::Accepted) {
+            ui.label(RichText::new(format!("Personnage {} depuis le {} : {}", change.status.get_text(), Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(change.date as i64, 0)).format("%d-%m-%Y"), change.reason)).strong());
+        }
+
+        if ficherp.is_reviewable() || !ficherp.votes.is_empty() {
+            let tally: VoteTally = ficherp.vote_tally(&AUTH_INFO.read().unwrap().approval_quorum);
+            ui.label(format!("Votes : {} / {} approbation(s), {} rejet(s)", tally.approvals, tally.required, tally.rejections));
+        }
+
+        fiche_gallery(ui, ficherp, user);
+
         ui.separator();
 
         let mut cache: RwLockWriteGuard<CommonMarkCache> = cache.write().expect("Can't access common_mark_cache");
@@ -97,12 +256,12 @@ pub fn ficherp_viewer(ui: &mut egui::Ui, ficherp: &FicheRP, job_text_buffer: &mu
         egui::ScrollArea::vertical().max_height(height).id_source("scoll_text_viewer").show(ui, |ui| {
             ui.label(RichText::new("Description physique : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &ficherp.description);
+            markdown_view(ui, &mut cache, &ficherp.description);
             ui.separator();
 
             ui.label(RichText::new("Lore : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &ficherp.lore);
+            markdown_view(ui, &mut cache, &ficherp.lore);
         });
 
         ui.add_space(5.0);
@@ -141,14 +300,23 @@ pub fn ficherp_edit(ui: &mut egui::Ui, ficherp: &mut FicheRP, is_previewing: &mu
     };
 
     let user = account.discord_user;
-    let avatar_url = avatar_resolver(&user.id);
-    let avatar_image = Image::new(avatar_url).fit_to_original_size(0.5).maintain_aspect_ratio(true).rounding(100.0);
+    let avatar_url = avatar_resolver(&user.id, 64);
+    let avatar_image = Image::new(avatar_url).fit_to_original_size(1.0).maintain_aspect_ratio(true).rounding(100.0);
 
     ficherp.submission_date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
 
+    // Fiches written for someone else or already submitted are not drafts
+    let is_draft: bool = !*is_editing_existing_fiche && selected_account.is_none() && (ficherp.id.is_empty() || ficherp.state == FicheState::Draft);
+    if is_draft {
+        autosave_draft(ui, ficherp);
+    }
+
     ui.vertical(|ui| {
         ui.vertical_centered(|ui| {
             ui.label(format!("{} | Composition de votre Fiche RP", &user.global_name));
+            if is_draft && ficherp.state == FicheState::Draft {
+                ui.label(RichText::new("Brouillon enregistré automatiquement").italics());
+            }
         });
         ui.horizontal(|ui| {
             ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
@@ -469,12 +637,57 @@ pub fn ficherp_edit(ui: &mut egui::Ui, ficherp: &mut FicheRP, is_previewing: &mu
                         can_be_closed = true;
                     }
                 });
+
+                if is_draft && ficherp.state == FicheState::Draft && ui.button("Supprimer le brouillon").clicked() {
+                    post_draft_deletion(&ficherp.id);
+                    *background_image = Option::from(image_resolver("checkmark_expo.svg"));
+                    can_be_closed = true;
+                }
             }
         });
     });
     can_be_closed
 }
 
+/// Explains why the backend refused the last submission (slot limits, job quotas)
+pub fn submit_error_window(ctx: &egui::Context) {
+    let Some(message) = SUBMIT_ERROR.read().unwrap().clone() else {
+        return;
+    };
+
+    egui::Window::new("Soumission refusée").collapsible(false).resizable(false).show(ctx, |ui| {
+        ui.label(message);
+        ui.vertical_centered(|ui| {
+            if ui.button("OK").clicked() {
+                *SUBMIT_ERROR.write().unwrap() = None;
+            }
+        });
+    });
+}
+
+/// Saves the fiche being written every `AUTOSAVE_SECS` when its content changed since the last save
+fn autosave_draft(ui: &mut egui::Ui, ficherp: &mut FicheRP) {
+    if ficherp.id.is_empty() {
+        if let Some(draft_id) = SAVED_DRAFT_ID.write().unwrap().take() {
+            ficherp.id = draft_id;
+            ficherp.state = FicheState::Draft;
+        }
+    }
+
+    let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let autosave_id: Id = Id::new("draft_autosave");
+    let (last_save, last_saved_fiche): (u64, Option<FicheRP>) = ui.data_mut(|data| data.get_temp(autosave_id)).unwrap_or_default();
+
+    let has_changed: bool = last_saved_fiche.is_none_or(|saved| saved.id != ficherp.id || saved.name != ficherp.name || saved.job != ficherp.job || saved.description != ficherp.description || saved.lore != ficherp.lore);
+    let has_content: bool = !ficherp.name.is_empty() || !ficherp.description.is_empty() || !ficherp.lore.is_empty();
+
+    if has_changed && has_content && now >= last_save + AUTOSAVE_SECS {
+        post_draft(ficherp);
+        ui.data_mut(|data| data.insert_temp(autosave_id, (now, Some(ficherp.clone()))));
+    }
+    ui.ctx().request_repaint_after(Duration::from_secs(AUTOSAVE_SECS));
+}
+
 pub fn ficherp_viewer_window(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User, cache: Arc<RwLock<CommonMarkCache>>) {
     let datetime = Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(ficherp.submission_date as i64, 0));
 
@@ -502,12 +715,12 @@ pub fn ficherp_viewer_window(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User,
         egui::ScrollArea::vertical().id_source("scoll_text_viewer").show(ui, |ui| {
             ui.label(RichText::new("Description physique : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &ficherp.description);
+            markdown_view(ui, &mut cache, &ficherp.description);
             ui.separator();
 
             ui.label(RichText::new("Lore : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &ficherp.lore);
+            markdown_view(ui, &mut cache, &ficherp.lore);
             ui.separator();
         });
     });
@@ -555,12 +768,12 @@ pub fn ficherp_history_viewer_window(ui: &mut egui::Ui, ficherp: &FicheRP, selec
         egui::ScrollArea::vertical().id_source("scoll_text_viewer").show(ui, |ui| {
             ui.label(RichText::new("Description physique : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &selected_fiche_account_version.description);
+            markdown_view(ui, &mut cache, &selected_fiche_account_version.description);
             ui.separator();
 
             ui.label(RichText::new("Lore : ").strong().text_style(TextStyle::Name("heading3".into())));
 
-            CommonMarkViewer::new().show(ui, &mut cache, &selected_fiche_account_version.lore);
+            markdown_view(ui, &mut cache, &selected_fiche_account_version.lore);
             ui.separator();
         });
     });
@@ -573,7 +786,8 @@ pub fn state_badge(ui: &mut egui::Ui, state: &FicheState) {
         FicheState::StaffValidated => "conform.svg",
         FicheState::Accepted => "accepted.svg",
         FicheState::Refused => "refused.svg",
-        FicheState::Comment => "comment.svg"
+        FicheState::Comment => "comment.svg",
+        FicheState::Draft => "draft.svg",
     };
 
     let badge: Image = Image::new(image_resolver(format!("badges/{}", img_to_load).as_str())).fit_to_original_size(1.0).maintain_aspect_ratio(true);
@@ -585,3 +799,97 @@ fn truncate_at_char_boundary(s: String, index: usize) -> String {
     let truncate_index = s.char_indices().nth(index).map(|(i, _)| i).unwrap_or(s.len());
     s[..truncate_index].to_string()
 }
+
+/// Keeps the open fiche in sync with `ALL_ACCOUNTS`, which the live events refresh
+pub fn refresh_selected_fiche(selected_fiche_account: &mut Option<(FrontAccount, FicheRP)>) {
+    let Some((_, selected_ficherp)) = selected_fiche_account else {
+        return;
+    };
+    let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
+    let Ok(all_accounts) = binding.read() else {
+        return;
+    };
+
+    let latest: Option<(&FrontAccount, &FicheRP)> = all_accounts.iter().find_map(|account| account.fiches.iter().find(|fiche| fiche.id == selected_ficherp.id).map(|fiche| (account, fiche)));
+    if let Some((account, ficherp)) = latest.filter(|(_, ficherp)| *ficherp != selected_ficherp) {
+        *selected_fiche_account = Some((account.clone(), ficherp.clone()));
+    }
+}
+
+#[derive(Default)]
+struct MarkdownSanitizer;
+
+impl ComputerMut<(&str, &MarkdownRules), String> for MarkdownSanitizer {
+    fn compute(&mut self, (markdown, rules): (&str, &MarkdownRules)) -> String {
+        rules.sanitize(markdown)
+    }
+}
+
+/// Sanitised markdown kept as long as it is shown, instead of parsing it again every frame
+type SanitizedMarkdownCache = FrameCache<String, MarkdownSanitizer>;
+
+/// Viewer of user markdown, images from hosts that are not allowed are shown as their alt text instead of being loaded
+pub fn markdown_view(ui: &mut egui::Ui, cache: &mut CommonMarkCache, markdown: &str) {
+    let sanitized: String = match AUTH_INFO.try_read() {
+        Ok(auth_info) => ui.memory_mut(|memory| memory.caches.cache::<SanitizedMarkdownCache>().get((markdown, &auth_info.markdown_rules))),
+        Err(_) => ui.memory_mut(|memory| memory.caches.cache::<SanitizedMarkdownCache>().get((markdown, &MarkdownRules::default()))),
+    };
+    CommonMarkViewer::new().show(ui, cache, &sanitized);
+}
+
+/// Character portrait when the owner uploaded one, their Discord avatar otherwise
+fn portrait_image(ficherp: &FicheRP, user: &User) -> Image<'static> {
+    match ficherp.portrait() {
+        Some(portrait) => Image::new(fiche_image_resolver(&ficherp.id, &portrait.id, true)).max_height(128.0).maintain_aspect_ratio(true).rounding(8.0),
+        None => Image::new(avatar_resolver(&user.id, 64)).fit_to_original_size(1.0).maintain_aspect_ratio(true).rounding(100.0),
+    }
+}
+
+/// Gallery thumbnails opening the full image, with the upload and removal buttons for the owner and the staff
+fn fiche_gallery(ui: &mut egui::Ui, ficherp: &FicheRP, user: &User) {
+    let (is_owner, is_staff): (bool, bool) = match AUTH_INFO.try_read() {
+        Ok(auth_info) => match &auth_info.account {
+            Some(account) => (account.discord_user == *user, is_reviewer(account, &auth_info.website_meta.whitelist)),
+            None => (false, false),
+        },
+        Err(_) => (false, false),
+    };
+    let gallery: Vec<&FicheImage> = ficherp.gallery();
+    if gallery.is_empty() && !is_owner {
+        return;
+    }
+
+    ui.separator();
+    ui.label(RichText::new("Galerie : ").strong().text_style(TextStyle::Name("heading3".into())));
+    egui::ScrollArea::horizontal().id_source(("fiche_gallery", &ficherp.id)).show(ui, |ui| {
+        ui.horizontal(|ui| {
+            gallery.iter().for_each(|image| {
+                ui.vertical(|ui| {
+                    let thumbnail: Image = Image::new(fiche_image_resolver(&ficherp.id, &image.id, true)).max_height(96.0).maintain_aspect_ratio(true);
+                    if ui.add(Button::image(thumbnail)).on_hover_text(format!("{} x {}", image.width, image.height)).clicked() {
+                        ui.ctx().open_url(OpenUrl::new_tab(fiche_image_resolver(&ficherp.id, &image.id, false)));
+                    }
+                    if (is_owner || (is_staff && ficherp.state != FicheState::Draft)) && ui.small_button("Retirer").clicked() {
+                        post_fiche_image_deletion(&ficherp.id, &image.id);
+                    }
+                });
+            });
+        });
+    });
+
+    if is_owner {
+        ui.horizontal(|ui| {
+            if ui.button("Changer le portrait").clicked() {
+                pick_fiche_image(&ficherp.id, ImageKind::Portrait);
+            }
+            if let Some(portrait) = ficherp.portrait() {
+                if ui.button("Retirer le portrait").clicked() {
+                    post_fiche_image_deletion(&ficherp.id, &portrait.id);
+                }
+            }
+            if ui.button("Ajouter une image").clicked() {
+                pick_fiche_image(&ficherp.id, ImageKind::Gallery);
+            }
+        });
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::{Arc, RwLock};
+
+use egui::{Button, RichText};
+
+use shared::fiche_rp::FicheRP;
+use shared::mentions::{complete_mention, pending_mention, render_mentions, MentionNotification};
+use shared::permissions::DiscordRole;
+use shared::search::fold;
+use shared::user::FrontAccount;
+
+use crate::app::{ALL_ACCOUNTS, AUTH_INFO, NOTIFICATIONS};
+use crate::backend_handler::post_notifications_read;
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Suggests the accounts matching the `@name` being typed, the chosen one replaces it by a mention.
+/// Only the accounts able to read the message are suggested, the others wouldn't be notified
+pub fn mention_suggestions(ui: &mut egui::Ui, content: &mut String, ficherp_id: &str, is_private: bool) {
+    let Some(partial) = pending_mention(content).map(fold) else {
+        return;
+    };
+    let whitelist: Vec<String> = AUTH_INFO.read().map(|auth_info| auth_info.website_meta.whitelist.clone()).unwrap_or_default();
+    let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
+    let Ok(all_accounts) = binding.read() else {
+        return;
+    };
+    let Some(owner_id) = all_accounts.iter().find(|account| account.fiches.iter().any(|fiche| fiche.id == ficherp_id)).map(|account| account.discord_user.id.clone()) else {
+        return;
+    };
+
+    let mut chosen_id: Option<String> = None;
+    ui.horizontal_wrapped(|ui| {
+        all_accounts.iter()
+            .filter(|account| fold(&account.discord_user.global_name).contains(&partial))
+            .filter(|account| is_staff_account(account, &whitelist) || (account.discord_user.id == owner_id && !is_private))
+            .take(MAX_SUGGESTIONS)
+            .for_each(|account| {
+                if ui.button(format!("@{}", account.discord_user.global_name)).clicked() {
+                    chosen_id = Some(account.discord_user.id.clone());
+                }
+            });
+    });
+
+    if let Some(discord_id) = chosen_id {
+        *content = complete_mention(content, &discord_id);
+    }
+}
+
+/// Content of a message with the mentions replaced by the names of the accounts
+pub fn display_mentions(content: &str) -> String {
+    match ALL_ACCOUNTS.read() {
+        Ok(all_accounts) => render_mentions(content, |id| all_accounts.iter().find(|account| account.discord_user.id == id).map(|account| account.discord_user.global_name.clone())),
+        Err(_) => content.to_string(),
+    }
+}
+
+/// Bell of the top bar listing the mentions of the user, returns the fiche of the notification clicked
+pub fn notification_menu(ui: &mut egui::Ui) -> Option<(FrontAccount, FicheRP)> {
+    let notifications: Vec<MentionNotification> = NOTIFICATIONS.read().map(|notifications| notifications.clone()).unwrap_or_default();
+    let unread: usize = notifications.iter().filter(|notification| !notification.read).count();
+
+    let mut opened_fiche: Option<(FrontAccount, FicheRP)> = None;
+    let title: RichText = if unread > 0 { RichText::new(format!("🔔 {}", unread)).strong() } else { RichText::new("🔔") };
+    ui.menu_button(title, |ui| {
+        if notifications.is_empty() {
+            ui.label("Aucune notification");
+            return;
+        }
+        if unread > 0 && ui.button("Tout marquer comme lu").clicked() {
+            post_notifications_read();
+        }
+        ui.separator();
+
+        let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
+        let Ok(all_accounts) = binding.read() else {
+            return;
+        };
+        notifications.iter().for_each(|notification| {
+            let author: String = all_accounts.iter().find(|account| account.discord_user.id == notification.author_id).map(|account| account.discord_user.global_name.clone()).unwrap_or_default();
+            let fiche: Option<(&FrontAccount, &FicheRP)> = all_accounts.iter()
+                .find(|account| account.discord_user.id == notification.owner_id)
+                .and_then(|account| account.fiches.iter().find(|fiche| fiche.id == notification.fiche_id).map(|fiche| (account, fiche)));
+            let fiche_name: String = fiche.map(|(_, fiche)| fiche.name.clone()).unwrap_or_default();
+
+            let mut text: RichText = RichText::new(format!("{} vous a mentionné sur la fiche {}", author, fiche_name));
+            if !notification.read {
+                text = text.strong();
+            }
+            if ui.add_enabled(fiche.is_some(), Button::new(text)).clicked() {
+                opened_fiche = fiche.map(|(account, fiche)| (account.clone(), fiche.clone()));
+                post_notifications_read();
+                ui.close_menu();
+            }
+        });
+    });
+    opened_fiche
+}
+
+fn is_staff_account(account: &FrontAccount, whitelist: &Vec<String>) -> bool {
+    whitelist.contains(&account.discord_user.id) || DiscordRole::from_role_ids(&account.discord_roles).unwrap_or_default().iter().any(|role| *role == DiscordRole::PlatformAdmin || *role == DiscordRole::Admin || *role == DiscordRole::LeadScenarist || *role == DiscordRole::LeadMed || *role == DiscordRole::Scenarist)
+}
@@ -1,3 +1,7 @@
 pub mod fiche_components;
 pub mod utils_components;
-pub mod comment_components;
\ No newline at end of file
+pub mod comment_components;
+pub mod stats_components;
+pub mod search_components;
+pub mod mention_components;
+pub mod account_components;
\ No newline at end of file
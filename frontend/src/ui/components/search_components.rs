@@ -0,0 +1,72 @@
+use std::sync::{Arc, RwLock};
+
+use egui::text::LayoutJob;
+use egui::{hex_color, Color32, CursorIcon, Id, Key, RichText, Sense, TextEdit, TextFormat};
+
+use shared::fiche_rp::FicheRP;
+use shared::search::{SearchHit, Snippet};
+use shared::user::FrontAccount;
+
+use crate::app::{ALL_ACCOUNTS, SEARCH_RESULTS};
+use crate::backend_handler::retrieve_search;
+
+/// Search bar above the fiche lists, returns true when the results should replace the list
+pub fn search_bar(ui: &mut egui::Ui, search_query: &mut String) -> bool {
+    ui.horizontal(|ui| {
+        let response = ui.add(TextEdit::singleline(search_query).hint_text("Rechercher une fiche…"));
+        let is_submitted: bool = response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+
+        if (is_submitted || ui.button("🔍").clicked()) && !search_query.trim().is_empty() {
+            retrieve_search(search_query);
+        }
+        if !search_query.is_empty() && ui.button("✖").clicked() {
+            search_query.clear();
+            *SEARCH_RESULTS.write().unwrap() = None;
+        }
+    });
+
+    !search_query.trim().is_empty() && SEARCH_RESULTS.read().is_ok_and(|results| results.is_some())
+}
+
+/// Hits of the last search with their highlighted snippets, returns the fiche clicked
+pub fn search_results(ui: &mut egui::Ui) -> Option<(FrontAccount, FicheRP)> {
+    let results: Vec<SearchHit> = SEARCH_RESULTS.read().ok()?.clone()?;
+    if results.is_empty() {
+        ui.label("Aucune fiche ne correspond à la recherche");
+        return None;
+    }
+
+    let mut clicked_hit: Option<&SearchHit> = None;
+    results.iter().for_each(|hit| {
+        let response = ui.group(|ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&hit.name).strong());
+                ui.label(RichText::new(hit.state.get_text()).small());
+            });
+            hit.snippets.iter().for_each(|snippet| {
+                ui.label(snippet_layout(snippet));
+            });
+        }).response;
+
+        if ui.interact(response.rect, Id::new(("search_hit", &hit.fiche_id)), Sense::click()).on_hover_cursor(CursorIcon::PointingHand).clicked() {
+            clicked_hit = Some(hit);
+        }
+    });
+
+    let hit: &SearchHit = clicked_hit?;
+    let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
+    let all_accounts = binding.read().ok()?;
+    let account: &FrontAccount = all_accounts.iter().find(|account| account.discord_user.id == hit.owner_id)?;
+    let ficherp: &FicheRP = account.fiches.iter().find(|fiche| fiche.id == hit.fiche_id)?;
+    Some((account.clone(), ficherp.clone()))
+}
+
+fn snippet_layout(snippet: &Snippet) -> LayoutJob {
+    let mut layout_job = LayoutJob::default();
+    layout_job.append(&format!("{} : ", snippet.field.get_text()), 0.0, TextFormat { italics: true, ..Default::default() });
+    layout_job.append(&snippet.before, 0.0, TextFormat { ..Default::default() });
+    layout_job.append(&snippet.matched, 0.0, TextFormat { color: Color32::WHITE, background: hex_color!("#5B4A14"), ..Default::default() });
+    layout_job.append(&snippet.after, 0.0, TextFormat { ..Default::default() });
+    layout_job
+}
@@ -0,0 +1,173 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use egui::{hex_color, vec2, Align2, FontId, Rect, RichText, Sense, Stroke, TextStyle};
+
+use shared::jobs::JobStatus;
+use shared::quota::QuotaOverview;
+use shared::stats::StaffStats;
+
+const CHART_HEIGHT: f32 = 120.0;
+
+pub fn staff_stats_dashboard(ui: &mut egui::Ui, stats: &StaffStats) {
+    ui.vertical(|ui| {
+        ui.label(RichText::new("Soumissions par semaine").text_style(TextStyle::Name("heading2".into())));
+        weekly_chart(ui, stats);
+
+        ui.separator();
+        egui::Grid::new("stats_overview").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Délai médian avant décision");
+            ui.label(stats.median_decision_secs.map(format_duration).unwrap_or_else(|| "—".to_string()));
+            ui.end_row();
+
+            ui.label("Fiches acceptées / refusées");
+            ui.label(format!("{} / {}", stats.accepted, stats.refused));
+            ui.end_row();
+
+            ui.label("Taux d'acceptation");
+            ui.label(stats.acceptance_rate().map(|rate| format!("{:.0} %", rate * 100.0)).unwrap_or_else(|| "—".to_string()));
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label(RichText::new("Fiches en attente par job").text_style(TextStyle::Name("heading2".into())));
+        if stats.backlog.is_empty() {
+            ui.label("Aucune fiche en attente");
+        } else {
+            egui::Grid::new("stats_backlog").num_columns(2).striped(true).show(ui, |ui| {
+                stats.backlog.iter().for_each(|backlog| {
+                    ui.label(&backlog.job_family);
+                    ui.label(backlog.count.to_string());
+                    ui.end_row();
+                });
+            });
+        }
+
+        ui.separator();
+        ui.label(RichText::new("Activité des relecteurs").text_style(TextStyle::Name("heading2".into())));
+        egui::ScrollArea::vertical().id_source("stats_reviewers").max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("stats_reviewers_grid").num_columns(7).striped(true).show(ui, |ui| {
+                ["Relecteur", "Commentaires", "Modifications", "Conformes", "Acceptées", "Refusées", "Dernière activité"].iter().for_each(|header| {
+                    ui.label(RichText::new(*header).strong());
+                });
+                ui.end_row();
+
+                stats.reviewers.iter().for_each(|reviewer| {
+                    ui.label(if reviewer.global_name.is_empty() { &reviewer.discord_id } else { &reviewer.global_name });
+                    ui.label(reviewer.comments.to_string());
+                    ui.label(reviewer.modification_requests.to_string());
+                    ui.label(reviewer.validations.to_string());
+                    ui.label(reviewer.acceptances.to_string());
+                    ui.label(reviewer.refusals.to_string());
+                    ui.label(format_date(reviewer.last_activity));
+                    ui.end_row();
+                });
+            });
+        });
+    });
+}
+
+pub fn quota_overview(ui: &mut egui::Ui, overview: &QuotaOverview) {
+    ui.vertical(|ui| {
+        ui.label(RichText::new("Limites par joueur").text_style(TextStyle::Name("heading2".into())));
+        egui::Grid::new("quota_slots").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Personnages");
+            ui.label(overview.rules.max_characters.map(|max| max.to_string()).unwrap_or_else(|| "—".to_string()));
+            ui.end_row();
+
+            overview.rules.department_slots.iter().for_each(|slots| {
+                ui.label(&slots.job);
+                ui.label(slots.max.to_string());
+                ui.end_row();
+            });
+        });
+
+        ui.separator();
+        ui.label(RichText::new("Quotas par job").text_style(TextStyle::Name("heading2".into())));
+        if overview.usages.is_empty() {
+            ui.label("Aucun quota");
+        } else {
+            egui::Grid::new("quota_jobs").num_columns(2).striped(true).show(ui, |ui| {
+                overview.usages.iter().for_each(|usage| {
+                    ui.label(usage.quota.label());
+                    let text: RichText = RichText::new(format!("{} / {}", usage.used, usage.quota.max));
+                    ui.label(if usage.used >= usage.quota.max { text.color(hex_color!("#E74C3C")) } else { text });
+                    ui.end_row();
+                });
+            });
+        }
+    });
+}
+
+pub fn job_statuses(ui: &mut egui::Ui, statuses: &Vec<JobStatus>) {
+    if statuses.is_empty() {
+        ui.label("Aucune tâche");
+        return;
+    }
+    egui::Grid::new("job_statuses").num_columns(5).striped(true).show(ui, |ui| {
+        ui.label(RichText::new("Tâche").strong());
+        ui.label(RichText::new("Dernier succès").strong());
+        ui.label(RichText::new("Résultat").strong());
+        ui.label(RichText::new("Prochaine exécution").strong());
+        ui.label(RichText::new("Exécutions").strong());
+        ui.end_row();
+
+        statuses.iter().for_each(|status| {
+            ui.label(&status.name).on_hover_text(format!("Toutes les {}", format_duration(status.interval_secs)));
+            ui.label(status.last_success.map(format_date_time).unwrap_or_else(|| "—".to_string()));
+            match &status.last_error {
+                Some(err) => {
+                    ui.label(RichText::new(format!("{} échec(s) : {}", status.consecutive_failures, err)).color(hex_color!("#E74C3C")));
+                }
+                None => {
+                    ui.label(&status.last_report);
+                }
+            }
+            if status.running {
+                ui.label("En cours");
+            } else {
+                ui.label(status.next_run.map(format_date_time).unwrap_or_else(|| "—".to_string()));
+            }
+            ui.label(status.runs.to_string());
+            ui.end_row();
+        });
+    });
+}
+
+fn weekly_chart(ui: &mut egui::Ui, stats: &StaffStats) {
+    let max_count: usize = stats.weekly_submissions.iter().map(|week| week.count).max().unwrap_or(0).max(1);
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), CHART_HEIGHT + 20.0), Sense::hover());
+
+    let bar_width: f32 = response.rect.width() / stats.weekly_submissions.len().max(1) as f32;
+
+    stats.weekly_submissions.iter().enumerate().for_each(|(index, week)| {
+        let height: f32 = CHART_HEIGHT * week.count as f32 / max_count as f32;
+        let left: f32 = response.rect.left() + index as f32 * bar_width;
+        let bottom: f32 = response.rect.top() + CHART_HEIGHT;
+        let bar: Rect = Rect::from_min_max([left + 2.0, bottom - height].into(), [left + bar_width - 2.0, bottom].into());
+
+        painter.rect(bar, 2.0, hex_color!("#1F8B4C"), Stroke::NONE);
+        painter.text([left + bar_width / 2.0, bar.top() - 2.0].into(), Align2::CENTER_BOTTOM, week.count.to_string(), FontId::proportional(11.0), hex_color!("#B8B8B8"));
+        painter.text([left + bar_width / 2.0, bottom + 2.0].into(), Align2::CENTER_TOP, format_short_date(week.week_start), FontId::proportional(10.0), hex_color!("#808080"));
+    });
+}
+
+fn format_duration(secs: u64) -> String {
+    let days: u64 = secs / 86400;
+    let hours: u64 = secs % 86400 / 3600;
+    if days > 0 {
+        format!("{}j {}h", days, hours)
+    } else {
+        format!("{}h {}min", hours, secs % 3600 / 60)
+    }
+}
+
+fn format_date(timestamp: u64) -> String {
+    Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(timestamp as i64, 0)).format("%d-%m-%Y").to_string()
+}
+
+fn format_date_time(timestamp: u64) -> String {
+    Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(timestamp as i64, 0)).format("%d-%m-%Y %H:%M").to_string()
+}
+
+fn format_short_date(timestamp: u64) -> String {
+    Utc.from_utc_datetime(&NaiveDateTime::from_timestamp(timestamp as i64, 0)).format("%d/%m").to_string()
+}
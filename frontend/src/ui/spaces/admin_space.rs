@@ -1,14 +1,18 @@
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
-use egui::{hex_color, Align, CursorIcon, Image, Layout, Margin, Rounding, Sense, Stroke, Widget};
+use egui::{hex_color, Align, Color32, CursorIcon, Image, Layout, Margin, Rounding, Sense, Stroke, Widget};
 use egui_commonmark::CommonMarkCache;
 use shared::discord::User;
 use shared::fiche_rp::{FicheRP, FicheState, FicheVersion, Job, ReviewMessage};
+use shared::permissions::DiscordRole;
 use shared::user::FrontAccount;
 
-use crate::app::{get_string, AuthInfo, ALL_ACCOUNTS, AUTH_INFO};
+use crate::app::{get_string, AuthInfo, ALL_ACCOUNTS, AUTH_INFO, JOB_STATUSES, QUOTA_OVERVIEW, SELECTED_ROLE, STAFF_STATS};
+use crate::backend_handler::{post_account_sessions_revoke, retrieve_job_statuses, retrieve_quota_overview, retrieve_staff_stats};
 use crate::ui::components::comment_components::edit_comment_window;
-use crate::ui::components::fiche_components::{ficherp_bubble, ficherp_edit, ficherp_history_viewer_window, ficherp_viewer, ficherp_viewer_window};
+use crate::ui::components::fiche_components::{ficherp_bubble, ficherp_edit, ficherp_history_viewer_window, ficherp_viewer, ficherp_viewer_window, refresh_selected_fiche, submit_error_window};
+use crate::ui::components::search_components::{search_bar, search_results};
+use crate::ui::components::stats_components::{job_statuses, quota_overview, staff_stats_dashboard};
 
 pub struct AdminSpace {
     pub common_mark_cache: Arc<RwLock<CommonMarkCache>>,
@@ -26,6 +30,11 @@ pub struct AdminSpace {
     pub is_writing_message: bool,
     pub is_viewing_fiche_history: bool,
     pub is_editing_existing_fiche: bool,
+    pub is_viewing_stats: bool,
+    pub is_viewing_quotas: bool,
+    pub is_viewing_jobs: bool,
+
+    pub search_query: String,
 
     pub background_image: Option<String>,
 }
@@ -33,10 +42,17 @@ pub struct AdminSpace {
 impl eframe::App for AdminSpace {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         //Global variables
+        let role_binding = SELECTED_ROLE.clone();
+        let user_role: RwLockReadGuard<DiscordRole> = role_binding.read().unwrap();
+
         let auth_binding: Arc<RwLock<AuthInfo>> = AUTH_INFO.clone();
         let auth_lock: RwLockReadGuard<AuthInfo> = auth_binding.read().unwrap();
         let user_account: FrontAccount = auth_lock.clone().account.unwrap();
 
+        let is_lead: bool = *user_role == DiscordRole::PlatformAdmin || *user_role == DiscordRole::Admin || *user_role == DiscordRole::LeadScenarist || *user_role == DiscordRole::LeadMed;
+
+        refresh_selected_fiche(&mut self.selected_fiche_account);
+
         if self.is_previewing_fiche {
             egui::Window::new("Preview").open(&mut self.is_previewing_fiche).default_size([640.0, 960.0]).show(ctx, |ui| {
                 let user: User = user_account.clone().discord_user;
@@ -59,6 +75,53 @@ impl eframe::App for AdminSpace {
             });
         }
 
+        if self.is_viewing_stats {
+            egui::Window::new("Statistiques").open(&mut self.is_viewing_stats).default_size([720.0, 640.0]).show(ctx, |ui| {
+                if ui.button("Actualiser").clicked() {
+                    retrieve_staff_stats();
+                }
+                match STAFF_STATS.read().unwrap().as_ref() {
+                    Some(Ok(stats)) => staff_stats_dashboard(ui, stats),
+                    Some(Err(message)) => {
+                        ui.colored_label(Color32::RED, message);
+                    }
+                    None => {
+                        ui.spinner();
+                    }
+                }
+            });
+        }
+
+        if self.is_viewing_quotas {
+            egui::Window::new("Quotas").open(&mut self.is_viewing_quotas).default_size([480.0, 480.0]).show(ctx, |ui| {
+                if ui.button("Actualiser").clicked() {
+                    retrieve_quota_overview();
+                }
+                match QUOTA_OVERVIEW.read().unwrap().as_ref() {
+                    Some(overview) => quota_overview(ui, overview),
+                    None => {
+                        ui.spinner();
+                    }
+                }
+            });
+        }
+
+        if self.is_viewing_jobs {
+            egui::Window::new("Tâches de fond").open(&mut self.is_viewing_jobs).default_size([640.0, 240.0]).show(ctx, |ui| {
+                if ui.button("Actualiser").clicked() {
+                    retrieve_job_statuses();
+                }
+                match JOB_STATUSES.read().unwrap().as_ref() {
+                    Some(statuses) => job_statuses(ui, statuses),
+                    None => {
+                        ui.spinner();
+                    }
+                }
+            });
+        }
+
+        submit_error_window(ctx);
+
         // a bit a fuckery happening here :D
         if self.is_writing_message {
             if self.review_message.is_some() {
@@ -107,7 +170,7 @@ impl eframe::App for AdminSpace {
             ui.columns(2, |mut columns| {
                 columns[0].with_layout(Layout::top_down(Align::Center), |ui| {
                     ui.horizontal(|ui| {
-                        if ui.button(get_string("ficherp.create.fiche")).clicked() {
+                        if is_lead && ui.button(get_string("ficherp.create.fiche")).clicked() {
                             self.selected_fiche_account = None;
                             self.new_fiche = Option::from(FicheRP {
                                 id: "".to_string(),
@@ -119,6 +182,11 @@ impl eframe::App for AdminSpace {
                                 messages: vec![],
                                 version: vec![],
                                 state: FicheState::Waiting,
+                                claim: None,
+                                votes: vec![],
+                                lifecycle: vec![],
+                                images: vec![],
+                                search_terms: vec![],
                             });
 
                             self.is_viewing_fiche_history = false;
@@ -141,9 +209,44 @@ impl eframe::App for AdminSpace {
                                 ui.selectable_value(&mut self.selected_account, Option::from(front_account.clone()), &front_account.discord_user.global_name);
                             });
                         });
+
+                        if let Some(selected_account) = self.selected_account.as_ref().filter(|_| is_lead) {
+                            if ui.button("Déconnecter partout").on_hover_text("Révoque toutes les sessions de l'utilisateur").clicked() {
+                                post_account_sessions_revoke(&selected_account.discord_user.id);
+                            }
+                        }
+
+                        if is_lead && ui.button("Statistiques").clicked() {
+                            self.is_viewing_stats = true;
+                            retrieve_staff_stats();
+                        }
+                        if ui.button("Quotas").clicked() {
+                            self.is_viewing_quotas = true;
+                            retrieve_quota_overview();
+                        }
+                        if ui.button("Tâches").clicked() {
+                            self.is_viewing_jobs = true;
+                            retrieve_job_statuses();
+                        }
                     });
 
+                    let is_searching: bool = search_bar(ui, &mut self.search_query);
+
                     egui::ScrollArea::vertical().show(ui, |ui| {
+                        if is_searching {
+                            if let Some(fiche_account) = search_results(ui) {
+                                self.new_fiche = None;
+                                self.selected_fiche_account = Some(fiche_account);
+                                self.selected_fiche_version = None;
+
+                                self.is_viewing_fiche_history = false;
+                                self.is_writing_message = false;
+                                self.is_previewing_fiche = false;
+                                self.background_image = None;
+                            }
+                            return;
+                        }
+
                         let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
                         let all_account = binding.read().unwrap();
 
@@ -4,13 +4,15 @@ use std::sync::{Arc, RwLock, RwLockReadGuard};
 use egui::{hex_color, Align, CursorIcon, Image, Layout, Margin, Rounding, Sense, Stroke, Widget};
 use egui_commonmark::CommonMarkCache;
 use shared::discord::User;
-use shared::fiche_rp::{FicheRP, FicheState, FicheVersion, Job, ReviewMessage};
+use shared::fiche_rp::{CharacterStatus, FicheRP, FicheState, FicheVersion, Job, ReviewMessage, ReviewQueue};
 use shared::permissions::DiscordRole;
 use shared::user::FrontAccount;
 
-use crate::app::{get_string, AuthInfo, ALL_ACCOUNTS, AUTH_INFO, SELECTED_ROLE};
-use crate::ui::components::comment_components::{comment_bubble, edit_comment_window};
-use crate::ui::components::fiche_components::{ficherp_bubble, ficherp_edit, ficherp_history_viewer_window, ficherp_viewer, ficherp_viewer_window};
+use crate::app::{get_string, AuthInfo, ALL_ACCOUNTS, AUTH_INFO, REVIEW_QUEUE, SAVED_DRAFT_ID, SELECTED_ROLE};
+use crate::backend_handler::retrieve_review_queue;
+use crate::ui::components::comment_components::{comment_thread, edit_comment_window};
+use crate::ui::components::fiche_components::{claim_controls, ficherp_bubble, ficherp_edit, ficherp_history_viewer_window, ficherp_viewer, ficherp_viewer_window, lifecycle_controls, refresh_selected_fiche, submit_error_window, vote_controls};
+use crate::ui::components::search_components::{search_bar, search_results};
 
 pub struct FicheSpace {
     pub common_mark_cache: Arc<RwLock<CommonMarkCache>>,
@@ -30,6 +32,7 @@ pub struct FicheSpace {
     pub background_image: Option<String>,
 
     pub fiche_filter: FilterEnum,
+    pub search_query: String,
 }
 #[derive(Eq, PartialEq)]
 pub enum FilterEnum {
@@ -37,6 +40,10 @@ pub enum FilterEnum {
     ALL,
     ACCEPTED_OTHER,
     WAITING,
+    ASSIGNED_TO_ME,
+    UNCLAIMED,
+    DRAFTS,
+    INACTIVE,
 }
 impl fmt::Display for FilterEnum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -45,6 +52,10 @@ impl fmt::Display for FilterEnum {
             FilterEnum::ALL => write!(f, "Toutes"),
             FilterEnum::ACCEPTED_OTHER => write!(f, "Fiches des autres"),
             FilterEnum::WAITING => write!(f, "En attente"),
+            FilterEnum::ASSIGNED_TO_ME => write!(f, "Assignées à moi"),
+            FilterEnum::UNCLAIMED => write!(f, "Non prises en charge"),
+            FilterEnum::DRAFTS => write!(f, "Mes brouillons"),
+            FilterEnum::INACTIVE => write!(f, "Personnages inactifs"),
         }
     }
 }
@@ -60,6 +71,12 @@ impl eframe::App for FicheSpace {
         let user_account: FrontAccount = auth_lock.clone().account.unwrap();
 
         let is_staff: bool = *user_role == DiscordRole::PlatformAdmin || *user_role == DiscordRole::Admin || *user_role == DiscordRole::LeadScenarist || *user_role == DiscordRole::Scenarist || *user_role == DiscordRole::LeadMed;
+        let is_lead: bool = *user_role == DiscordRole::PlatformAdmin || *user_role == DiscordRole::Admin || *user_role == DiscordRole::LeadScenarist || *user_role == DiscordRole::LeadMed;
+
+        let queue_binding: Arc<RwLock<Vec<String>>> = REVIEW_QUEUE.clone();
+        let review_queue: Vec<String> = queue_binding.read().map(|queue| queue.clone()).unwrap_or_default();
+
+        refresh_selected_fiche(&mut self.selected_fiche_account);
 
         if self.is_previewing_fiche {
             egui::Window::new("Preview").open(&mut self.is_previewing_fiche).default_size([640.0, 960.0]).show(ctx, |ui| {
@@ -83,6 +100,8 @@ impl eframe::App for FicheSpace {
             });
         }
 
+        submit_error_window(ctx);
+
         // a bit a fuckery happening here :D
         if self.is_writing_message {
             if self.review_message.is_some() {
@@ -133,6 +152,7 @@ impl eframe::App for FicheSpace {
                     ui.horizontal(|ui| {
                         if ui.button(get_string("ficherp.create.fiche")).clicked() {
                             self.selected_fiche_account = None;
+                            *SAVED_DRAFT_ID.write().unwrap() = None;
                             self.new_fiche = Option::from(FicheRP {
                                 id: "".to_string(),
                                 name: "".to_string(),
@@ -143,6 +163,11 @@ impl eframe::App for FicheSpace {
                                 messages: vec![],
                                 version: vec![],
                                 state: FicheState::Waiting,
+                                claim: None,
+                                votes: vec![],
+                                lifecycle: vec![],
+                                images: vec![],
+                                search_terms: vec![],
                             });
 
                             self.is_viewing_fiche_history = false;
@@ -158,14 +183,37 @@ impl eframe::App for FicheSpace {
                                 ui.selectable_value(&mut self.fiche_filter, FilterEnum::ALL, FilterEnum::ALL.to_string());
                                 ui.selectable_value(&mut self.fiche_filter, FilterEnum::WAITING, FilterEnum::WAITING.to_string());
                                 ui.selectable_value(&mut self.fiche_filter, FilterEnum::ACCEPTED_OTHER, "Acceptée");
+                                if ui.selectable_value(&mut self.fiche_filter, FilterEnum::ASSIGNED_TO_ME, FilterEnum::ASSIGNED_TO_ME.to_string()).clicked() {
+                                    retrieve_review_queue(ReviewQueue::AssignedToMe);
+                                }
+                                if ui.selectable_value(&mut self.fiche_filter, FilterEnum::UNCLAIMED, FilterEnum::UNCLAIMED.to_string()).clicked() {
+                                    retrieve_review_queue(ReviewQueue::Unclaimed);
+                                }
                             } else {
                                 ui.selectable_value(&mut self.fiche_filter, FilterEnum::ACCEPTED_OTHER, FilterEnum::ACCEPTED_OTHER.to_string());
                                 ui.selectable_value(&mut self.fiche_filter, FilterEnum::OWN, FilterEnum::OWN.to_string());
                             }
+                            ui.selectable_value(&mut self.fiche_filter, FilterEnum::INACTIVE, FilterEnum::INACTIVE.to_string());
+                            ui.selectable_value(&mut self.fiche_filter, FilterEnum::DRAFTS, FilterEnum::DRAFTS.to_string());
                         });
                     });
 
+                    let is_searching: bool = search_bar(ui, &mut self.search_query);
+
                     egui::ScrollArea::vertical().show(ui, |ui| {
+                        if is_searching {
+                            if let Some(fiche_account) = search_results(ui) {
+                                self.new_fiche = None;
+                                self.selected_fiche_account = Some(fiche_account);
+                                self.selected_fiche_version = None;
+                                self.is_viewing_fiche_history = false;
+                                self.is_writing_message = false;
+                                self.is_previewing_fiche = false;
+                                self.background_image = None;
+                            }
+                            return;
+                        }
+
                         let binding: Arc<RwLock<Vec<FrontAccount>>> = ALL_ACCOUNTS.clone();
                         if let Ok(all_account) = binding.read() {
                             ui.vertical(|ui| {
@@ -178,9 +226,19 @@ impl eframe::App for FicheSpace {
                                         }
 
                                         account.fiches.iter().filter(|ficherp| match self.fiche_filter {
+                                            FilterEnum::DRAFTS => account.discord_user == user_account.discord_user && ficherp.state == FicheState::Draft,
+                                            _ if ficherp.state == FicheState::Draft => false,
                                             FilterEnum::OWN => account.discord_user == user_account.discord_user,
-                                            FilterEnum::ACCEPTED_OTHER => ficherp.state == FicheState::Accepted,
+                                            // The directory of characters only lists the ones still in play
+                                            FilterEnum::ACCEPTED_OTHER => ficherp.is_active_character(),
+                                            // Archived characters stay out of sight of the other players
+                                            FilterEnum::INACTIVE => ficherp.state == FicheState::Accepted && match ficherp.character_status() {
+                                                CharacterStatus::Active => false,
+                                                CharacterStatus::Archived => is_staff || account.discord_user == user_account.discord_user,
+                                                _ => true,
+                                            },
                                             FilterEnum::WAITING => ficherp.state == FicheState::Waiting,
+                                            FilterEnum::ASSIGNED_TO_ME | FilterEnum::UNCLAIMED => review_queue.contains(&ficherp.id),
                                             FilterEnum::ALL => true,
                                         }).for_each(|ficherp| {
                                             let account_ref: &FrontAccount = account;
@@ -188,9 +246,22 @@ impl eframe::App for FicheSpace {
 
                                             frame.show(ui, |ui| {
                                                 let bubble_rec = ficherp_bubble(ui, ficherp_ref, &account_ref.discord_user);
-                                                let response = ui.allocate_rect(bubble_rec.rect, Sense::click());
+                                                let response = ui.allocate_rect(bubble_rec.rect, Sense::click()).on_hover_cursor(CursorIcon::PointingHand);
 
-                                                if response.on_hover_cursor(CursorIcon::PointingHand).clicked() {
+                                                if response.clicked() && ficherp_ref.state == FicheState::Draft {
+                                                    // Drafts are reopened in the editor
+                                                    *SAVED_DRAFT_ID.write().unwrap() = None;
+                                                    self.new_fiche = Some(ficherp_ref.clone());
+                                                    self.selected_fiche_account = None;
+                                                    if let Job::Other(job_text) = &ficherp_ref.job {
+                                                        self.job_text_buffer = job_text.clone();
+                                                    }
+                                                    self.is_viewing_fiche_history = false;
+                                                    self.is_writing_message = false;
+                                                    self.is_previewing_fiche = false;
+                                                    self.is_editing_existing_fiche = false;
+                                                    self.background_image = None;
+                                                } else if response.clicked() {
                                                     self.new_fiche = None;
                                                     self.selected_fiche_account = Some((account_ref.clone(), ficherp_ref.clone()));
                                                     self.selected_fiche_version = None;
@@ -247,19 +318,28 @@ impl eframe::App for FicheSpace {
                                             is_private: false,
                                             is_comment: false,
                                             set_state: FicheState::Waiting,
+                                            id: "".to_string(),
+                                            parent_id: None,
+                                            edits: vec![],
+                                            deletion: None,
+                                            search_terms: vec![],
                                         });
                                         self.is_writing_message = true;
                                     }
                                 });
+                                lifecycle_controls(ui, &selected_fiche_account.1, selected_fiche_account.0.discord_user == user_account.discord_user, is_staff, is_lead);
+                                if is_staff {
+                                    claim_controls(ui, &selected_fiche_account.1, &user_account, is_lead);
+                                    if selected_fiche_account.0.discord_user != user_account.discord_user {
+                                        vote_controls(ui, &selected_fiche_account.1, &user_account);
+                                    }
+                                }
                                 if selected_fiche_account.0.discord_user == user_account.discord_user || is_staff {
                                     egui::ScrollArea::vertical().show(ui, |ui| {
-                                        selected_fiche_account.1.messages.iter().for_each(|review_message: &ReviewMessage| {
-                                            if !review_message.is_private || (review_message.is_private && is_staff) {
-                                                frame.show(ui, |ui| {
-                                                    comment_bubble(ui, &review_message, self.common_mark_cache.clone())
-                                                });
-                                            }
-                                        });
+                                        if let Some(reply) = comment_thread(ui, &selected_fiche_account.1, None, &user_account, is_staff, frame, self.common_mark_cache.clone()) {
+                                            self.review_message = Some(reply);
+                                            self.is_writing_message = true;
+                                        }
                                         ui.add_space(15.0);
                                     });
                                 }
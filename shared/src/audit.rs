@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Trace of a personal data request, kept after the account is gone to prove the request was handled
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AuditEntry {
+    pub id: String,
+    pub action: AuditAction,
+    /// Discord id of the user who asked for it, `cli` when run from the command line
+    pub actor_id: String,
+    /// Discord id of the account concerned
+    pub target_id: String,
+    pub date: u64,
+    pub details: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    DataExport,
+    AccountDeletion,
+}
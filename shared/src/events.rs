@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fiche_rp::FicheState;
+
+/// Change pushed to the connected clients, it only names the fiche: clients fetch the content again
+/// through the usual routes, which already strip what the user is not allowed to see
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LiveEvent {
+    pub kind: LiveEventKind,
+    pub owner_id: String,
+    pub fiche_id: String,
+    pub scope: EventScope,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum LiveEventKind {
+    Submitted,
+    Updated,
+    Comment,
+    StateChanged(FicheState),
+    /// Sent with the mentioned user as `owner_id` and the `Owner` scope, only them receive it
+    Mention,
+    /// Events were missed by a slow client, everything has to be fetched again
+    Resync,
+}
+
+/// Who receives an event
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum EventScope {
+    Everyone,
+    /// Private messages and claims, the owner of the fiche doesn't receive them unless they are staff
+    Staff,
+    /// Drafts and mentions, hidden from the staff too
+    Owner,
+    /// Archived characters, only their owner and the staff still see them
+    StaffAndOwner,
+}
+
+impl LiveEvent {
+    pub fn new(kind: LiveEventKind, owner_id: &str, fiche_id: &str, scope: EventScope) -> LiveEvent {
+        LiveEvent {
+            kind,
+            owner_id: owner_id.to_string(),
+            fiche_id: fiche_id.to_string(),
+            scope,
+        }
+    }
+
+    pub fn is_visible_to(&self, discord_id: &str, is_staff: bool) -> bool {
+        match self.scope {
+            EventScope::Everyone => true,
+            EventScope::Staff => is_staff,
+            EventScope::Owner => self.owner_id == discord_id,
+            EventScope::StaffAndOwner => is_staff || self.owner_id == discord_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_event_scope() {
+        let private_comment: LiveEvent = LiveEvent::new(LiveEventKind::Comment, "owner", "fiche", EventScope::Staff);
+        assert!(!private_comment.is_visible_to("owner", false));
+        assert!(private_comment.is_visible_to("owner", true));
+        assert!(private_comment.is_visible_to("reviewer", true));
+        assert!(!private_comment.is_visible_to("player", false));
+
+        let draft: LiveEvent = LiveEvent::new(LiveEventKind::Updated, "owner", "fiche", EventScope::Owner);
+        assert!(draft.is_visible_to("owner", false));
+        assert!(!draft.is_visible_to("reviewer", true));
+        assert!(LiveEvent::new(LiveEventKind::Submitted, "owner", "fiche", EventScope::Everyone).is_visible_to("player", false));
+
+        let archived: LiveEvent = LiveEvent::new(LiveEventKind::Updated, "owner", "fiche", EventScope::StaffAndOwner);
+        assert!(archived.is_visible_to("owner", false));
+        assert!(archived.is_visible_to("reviewer", true));
+        assert!(!archived.is_visible_to("player", false));
+    }
+}
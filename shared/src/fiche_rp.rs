@@ -1,7 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use serde::{Deserialize, Serialize};
-use strum::EnumIter;
+use strum::{AsRefStr, EnumIter};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct FicheRP {
@@ -14,9 +14,118 @@ pub struct FicheRP {
     pub messages: Vec<ReviewMessage>,
     pub version: Vec<FicheVersion>,
     pub state: FicheState,
+    #[serde(default)]
+    pub claim: Option<ReviewClaim>,
+    /// Reviewer votes on the current version, reset when the owner modifies the fiche
+    #[serde(default)]
+    pub votes: Vec<ReviewVote>,
+    /// Status changes of the character once accepted, the last one is the current status
+    #[serde(default)]
+    pub lifecycle: Vec<LifecycleChange>,
+    /// Character portrait and gallery images uploaded by the owner
+    #[serde(default)]
+    pub images: Vec<FicheImage>,
+    /// Folded words of the name, description and lore, set by the backend for the indexed search
+    #[serde(default)]
+    pub search_terms: Vec<String>,
     //TODO:VEC RAPPORTS
 }
 
+/// A reviewer taking charge of a fiche, either by claiming it or by being assigned by a lead.
+/// The claim stops blocking other reviewers once `expires_at` is passed.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ReviewClaim {
+    pub discord_id: String,
+    pub claimed_at: u64,
+    pub expires_at: u64,
+    /// Lead who assigned the fiche, `None` when the reviewer claimed it
+    pub assigned_by: Option<String>,
+}
+
+/// Image uploaded for a fiche, the backend stores it re-encoded along with a thumbnail
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FicheImage {
+    pub id: String,
+    pub kind: ImageKind,
+    pub width: u32,
+    pub height: u32,
+    pub uploaded_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ImageKind {
+    /// Shown instead of the Discord avatar, a fiche has at most one
+    Portrait,
+    Gallery,
+}
+
+/// What became of an accepted character, only active characters hold their job roles and quota slot
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, EnumIter, AsRefStr)]
+pub enum CharacterStatus {
+    Active,
+    Retired,
+    Deceased,
+    Archived,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LifecycleChange {
+    pub status: CharacterStatus,
+    pub reason: String,
+    pub date: u64,
+    pub set_by: String,
+}
+
+/// States in which a fiche can be claimed, the backend checks them in the claim update itself
+pub const CLAIMABLE_STATES: [FicheState; 2] = [FicheState::Waiting, FicheState::RequestModification];
+
+/// Review queues computed by the backend
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewQueue {
+    AssignedToMe,
+    Unclaimed,
+}
+
+/// One vote per reviewer, a reviewer changing its mind keeps its previous votes in `changes`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ReviewVote {
+    pub discord_id: String,
+    pub approve: bool,
+    pub date: u64,
+    #[serde(default)]
+    pub changes: Vec<VoteChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct VoteChange {
+    pub approve: bool,
+    pub date: u64,
+}
+
+/// Approvals needed before a fiche becomes `StaffValidated`, `job` is the family key of the job (e.g. "Science")
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct ApprovalQuorum {
+    pub default_approvals: usize,
+    pub job_families: Vec<JobQuorum>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JobQuorum {
+    pub job: String,
+    pub approvals: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VoteTally {
+    pub approvals: usize,
+    pub rejections: usize,
+    pub required: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct FicheVersion {
     pub name: String,
@@ -34,9 +143,36 @@ pub struct ReviewMessage {
     pub is_private: bool,
     pub is_comment: bool,
     pub set_state: FicheState,
+    #[serde(default)]
+    pub id: String,
+    /// Message this one replies to, `None` for the start of a thread
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Contents replaced by the author, oldest first
+    #[serde(default)]
+    pub edits: Vec<MessageEdit>,
+    /// Deleted messages are kept to hold their thread together, only their content is hidden
+    #[serde(default)]
+    pub deletion: Option<MessageDeletion>,
+    /// Folded words of the content, set by the backend for the indexed search of the staff
+    #[serde(default)]
+    pub search_terms: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MessageEdit {
+    /// Content before the edit
+    pub content: String,
+    pub date: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MessageDeletion {
+    pub discord_id: String,
+    pub date: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, EnumIter, AsRefStr)]
 pub enum FicheState {
     Waiting,
     RequestModification,
@@ -44,7 +180,164 @@ pub enum FicheState {
     Accepted,
     Refused,
     Comment,
+    /// Saved by its owner but not submitted yet, invisible to the staff
+    Draft,
+}
+impl FicheRP {
+    pub fn active_claim(&self, now: u64) -> Option<&ReviewClaim> {
+        self.claim.as_ref().filter(|claim| claim.expires_at > now)
+    }
+
+    /// Fiches still waiting on a staff action
+    pub fn is_reviewable(&self) -> bool {
+        self.state == FicheState::Waiting || self.state == FicheState::StaffValidated
+    }
+
+    /// Fiches a reviewer can claim or be assigned, see `CLAIMABLE_STATES`
+    pub fn is_claimable(&self) -> bool {
+        CLAIMABLE_STATES.contains(&self.state)
+    }
+
+    pub fn character_status(&self) -> CharacterStatus {
+        self.lifecycle.last().map(|change| change.status).unwrap_or(CharacterStatus::Active)
+    }
+
+    pub fn portrait(&self) -> Option<&FicheImage> {
+        self.images.iter().find(|image| image.kind == ImageKind::Portrait)
+    }
+
+    pub fn gallery(&self) -> Vec<&FicheImage> {
+        self.images.iter().filter(|image| image.kind == ImageKind::Gallery).collect()
+    }
+
+    /// Accepted characters still in play, the ones counted for roles and job quotas
+    pub fn is_active_character(&self) -> bool {
+        self.state == FicheState::Accepted && self.character_status() == CharacterStatus::Active
+    }
+
+    /// Records the vote of a reviewer, returns false when it is the same as its current vote
+    pub fn cast_vote(&mut self, discord_id: &str, approve: bool, now: u64) -> bool {
+        match self.votes.iter_mut().find(|vote| vote.discord_id == discord_id) {
+            Some(vote) if vote.approve == approve => false,
+            Some(vote) => {
+                vote.changes.push(VoteChange { approve: vote.approve, date: vote.date });
+                vote.approve = approve;
+                vote.date = now;
+                true
+            }
+            None => {
+                self.votes.push(ReviewVote { discord_id: discord_id.to_string(), approve, date: now, changes: vec![] });
+                true
+            }
+        }
+    }
+
+    pub fn vote_tally(&self, quorum: &ApprovalQuorum) -> VoteTally {
+        let approvals: usize = self.votes.iter().filter(|vote| vote.approve).count();
+        VoteTally {
+            approvals,
+            rejections: self.votes.len() - approvals,
+            required: quorum.required_for(&self.job),
+        }
+    }
+
+    /// State once the votes are counted, votes only move a fiche between `Waiting` and `StaffValidated`
+    pub fn state_after_votes(&self, quorum: &ApprovalQuorum) -> FicheState {
+        match self.state {
+            FicheState::Waiting | FicheState::StaffValidated if self.vote_tally(quorum).is_reached() => FicheState::StaffValidated,
+            FicheState::StaffValidated => FicheState::Waiting,
+            _ => self.state.clone(),
+        }
+    }
+
+    /// Replies to `parent_id` by date, the thread starts when `None`. Replies whose parent is missing
+    /// (e.g. filtered out) start their own thread.
+    pub fn thread(&self, parent_id: Option<&str>) -> Vec<&ReviewMessage> {
+        let mut messages: Vec<&ReviewMessage> = self.messages.iter().filter(|message| match parent_id {
+            Some(parent_id) => message.parent_id.as_deref() == Some(parent_id),
+            None => message.parent_id.as_ref().is_none_or(|parent_id| !self.messages.iter().any(|parent| &parent.id == parent_id)),
+        }).collect();
+        messages.sort_by_key(|message| message.date);
+        messages
+    }
 }
+
+impl ReviewMessage {
+    pub fn is_deleted(&self) -> bool {
+        self.deletion.is_some()
+    }
+
+    pub fn can_be_edited_by(&self, discord_id: &str) -> bool {
+        !self.is_deleted() && self.discord_id == discord_id
+    }
+
+    pub fn can_be_deleted_by(&self, discord_id: &str, is_staff: bool) -> bool {
+        !self.is_deleted() && (self.discord_id == discord_id || is_staff)
+    }
+
+    /// What the players get of a deleted message
+    pub fn redact(&mut self) {
+        if self.is_deleted() {
+            self.content = String::new();
+            self.edits = vec![];
+            self.search_terms = vec![];
+        }
+    }
+}
+
+impl VoteTally {
+    pub fn is_reached(&self) -> bool {
+        self.approvals >= self.required
+    }
+}
+
+impl ApprovalQuorum {
+    pub fn required_for(&self, job: &Job) -> usize {
+        self.job_families.iter()
+            .find(|quorum| quorum.job == job.family_key())
+            .map(|quorum| quorum.approvals)
+            .unwrap_or(self.default_approvals)
+    }
+}
+
+impl Default for ApprovalQuorum {
+    fn default() -> Self {
+        ApprovalQuorum {
+            default_approvals: 1,
+            job_families: vec![],
+        }
+    }
+}
+
+impl ReviewQueue {
+    pub fn matches(&self, fiche: &FicheRP, discord_id: &str, now: u64) -> bool {
+        match self {
+            ReviewQueue::AssignedToMe => fiche.is_claimable() && fiche.active_claim(now).is_some_and(|claim| claim.discord_id == discord_id),
+            ReviewQueue::Unclaimed => fiche.is_claimable() && fiche.active_claim(now).is_none(),
+        }
+    }
+}
+
+impl CharacterStatus {
+    pub fn get_text(&self) -> &str {
+        match self {
+            CharacterStatus::Active => "ACTIF",
+            CharacterStatus::Retired => "RETRAITÉ",
+            CharacterStatus::Deceased => "DÉCÉDÉ",
+            CharacterStatus::Archived => "ARCHIVÉ",
+        }
+    }
+
+    /// Owners can only retire their character, reviewers handle deaths and comebacks, archiving is left to leads
+    pub fn can_be_set_by(&self, is_owner: bool, is_staff: bool, is_lead: bool) -> bool {
+        match self {
+            CharacterStatus::Retired => is_owner || is_staff,
+            CharacterStatus::Active | CharacterStatus::Deceased => is_staff,
+            CharacterStatus::Archived => is_lead,
+        }
+    }
+}
+
 impl FicheState {
     pub fn get_text(&self) -> &str {
         match self {
@@ -53,13 +346,14 @@ impl FicheState {
             FicheState::StaffValidated => "CONFORME",
             FicheState::Accepted => "ACCEPTÉE",
             FicheState::Refused => "REFUSÉE",
-            FicheState::Comment => "COMMENTAIRE"
+            FicheState::Comment => "COMMENTAIRE",
+            FicheState::Draft => "BROUILLON",
         }
     }
 }
 
 /**     JOB INFO STARTS HERE    **/
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, AsRefStr)]
 pub enum Job {
     Security(SecurityRole),
     Science(ScienceRole),
@@ -122,6 +416,34 @@ impl Job {
             _ => None
         }
     }
+
+    /// Every value of `family_key`, used to validate the backend configuration
+    pub const FAMILY_KEYS: [&'static str; 8] = ["Security", "Science", "ClassD", "Medic", "Mtf", "SiteDirector", "Chaos", "Other"];
+
+    // Keys are the variant names, they are what the backend configuration uses to target a job
+    pub fn family_key(&self) -> &str {
+        self.as_ref()
+    }
+
+    pub fn role_key(&self) -> Option<&str> {
+        match self {
+            Job::Security(role) => Option::from(role.as_ref()),
+            Job::Science(role) => Option::from(role.as_ref()),
+            Job::Medic(role) => Option::from(role.as_ref()),
+            Job::Mtf(role) => Option::from(role.as_ref()),
+            _ => None
+        }
+    }
+
+    pub fn rank_key(&self) -> Option<&str> {
+        match self {
+            Job::Security(role) => Option::from(role.get_security_level().as_ref()),
+            Job::Science(role) => Option::from(role.get_science_level().as_ref()),
+            Job::Medic(role) => role.get_medic_level().map(|rank| rank.as_ref()),
+            Job::Mtf(role) => Option::from(role.get_security_level().as_ref()),
+            _ => None
+        }
+    }
 }
 impl Display for Job {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -137,7 +459,7 @@ impl Display for Job {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, AsRefStr)]
 pub enum ScienceRole {
     Scientific(ScienceRank),
     Researcher(ScienceRank),
@@ -164,7 +486,7 @@ impl Display for ScienceRole {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, AsRefStr)]
 pub enum ScienceRank {
     Beginner,
     NoLevel,
@@ -180,7 +502,7 @@ impl Display for ScienceRank {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, AsRefStr)]
 pub enum SecurityRole {
     SecurityOfficier(SecurityRank),
     Gunsmith(SecurityRank),
@@ -205,7 +527,7 @@ impl Display for SecurityRole {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, EnumIter, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, EnumIter, Debug, AsRefStr)]
 pub enum SecurityRank {
     Rct,
     Sdt,
@@ -253,7 +575,7 @@ impl Display for SecurityRank {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, AsRefStr)]
 pub enum MedicRole {
     Director,
     DirectorAdj,
@@ -277,7 +599,7 @@ impl Display for MedicRole {
         }
     }
 }
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, EnumIter, AsRefStr)]
 pub enum MedicRank {
     Beginner,
     Confirmed,
@@ -305,7 +627,7 @@ impl Display for MedicRank {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, AsRefStr)]
 pub enum MtfRole {
     Omega10(SecurityRank),
 }
@@ -323,4 +645,131 @@ impl Display for MtfRole {
             MtfRole::Omega10(rank) => write!(f, "{} Omega - 10", rank)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn job_keys() {
+        let job: Job = Job::Security(SecurityRole::Gunsmith(SecurityRank::Sgt));
+        assert_eq!(job.family_key(), "Security");
+        assert_eq!(job.role_key(), Some("Gunsmith"));
+        assert_eq!(job.rank_key(), Some("Sgt"));
+
+        let job: Job = Job::Medic(MedicRole::Nurse);
+        assert_eq!(job.role_key(), Some("Nurse"));
+        assert_eq!(job.rank_key(), None);
+
+        [Job::Security(SecurityRole::Gunsmith(SecurityRank::Sgt)), Job::Science(ScienceRole::Doctor(ScienceRank::Senior)), Job::ClassD, job, Job::Mtf(MtfRole::Omega10(SecurityRank::Rct)),
+            Job::SiteDirector, Job::Chaos, Job::Other("Cuisinier".to_string())]
+            .iter()
+            .for_each(|job| assert!(Job::FAMILY_KEYS.contains(&job.family_key())));
+
+        assert_eq!(Job::SiteDirector.family_key(), "SiteDirector");
+        assert_eq!(Job::Other("Technicien".to_string()).role_key(), None);
+    }
+
+    #[test]
+    fn approval_quorum() {
+        let quorum: ApprovalQuorum = ApprovalQuorum {
+            default_approvals: 1,
+            job_families: vec![JobQuorum { job: "Science".to_string(), approvals: 2 }],
+        };
+        let mut fiche: FicheRP = fixtures::job_fiche(Job::Science(ScienceRole::Researcher(ScienceRank::Senior)), FicheState::Waiting);
+        assert_eq!(quorum.required_for(&Job::ClassD), 1);
+
+        assert!(fiche.cast_vote("1", true, 10));
+        assert!(!fiche.cast_vote("1", true, 20));
+        assert_eq!(fiche.state_after_votes(&quorum), FicheState::Waiting);
+
+        assert!(fiche.cast_vote("2", true, 30));
+        fiche.state = fiche.state_after_votes(&quorum);
+        assert_eq!(fiche.state, FicheState::StaffValidated);
+
+        assert!(fiche.cast_vote("1", false, 40));
+        assert_eq!(fiche.vote_tally(&quorum), VoteTally { approvals: 1, rejections: 1, required: 2 });
+        assert_eq!(fiche.votes[0].changes, vec![VoteChange { approve: true, date: 10 }]);
+        assert_eq!(fiche.state_after_votes(&quorum), FicheState::Waiting);
+
+        fiche.state = FicheState::Accepted;
+        assert_eq!(fiche.state_after_votes(&quorum), FicheState::Accepted);
+    }
+
+    #[test]
+    fn review_queues() {
+        let claim = |discord_id: &str, expires_at: u64| ReviewClaim { discord_id: discord_id.to_string(), claimed_at: 0, expires_at, assigned_by: None };
+        let fiche = |state: FicheState, claim: Option<ReviewClaim>| FicheRP { claim, ..fixtures::fiche(state) };
+        let now: u64 = 1000;
+
+        assert!(ReviewQueue::Unclaimed.matches(&fiche(FicheState::Waiting, None), "1", now));
+        assert!(ReviewQueue::Unclaimed.matches(&fiche(FicheState::RequestModification, Some(claim("2", now))), "1", now));
+        assert!(!ReviewQueue::Unclaimed.matches(&fiche(FicheState::Waiting, Some(claim("2", now + 1))), "1", now));
+        [FicheState::StaffValidated, FicheState::Accepted, FicheState::Refused, FicheState::Draft].into_iter().for_each(|state| {
+            assert!(!fiche(state.clone(), None).is_claimable());
+            assert!(!ReviewQueue::Unclaimed.matches(&fiche(state.clone(), None), "1", now));
+            assert!(!ReviewQueue::AssignedToMe.matches(&fiche(state, Some(claim("1", now + 1))), "1", now));
+        });
+
+        assert!(ReviewQueue::AssignedToMe.matches(&fiche(FicheState::Waiting, Some(claim("1", now + 1))), "1", now));
+        assert!(!ReviewQueue::AssignedToMe.matches(&fiche(FicheState::Waiting, Some(claim("1", now))), "1", now));
+        assert!(!ReviewQueue::AssignedToMe.matches(&fiche(FicheState::Waiting, Some(claim("2", now + 1))), "1", now));
+    }
+
+    #[test]
+    fn character_lifecycle() {
+        let mut fiche: FicheRP = fixtures::fiche(FicheState::Accepted);
+        assert_eq!(fiche.character_status(), CharacterStatus::Active);
+        assert!(fiche.is_active_character());
+
+        fiche.lifecycle.push(LifecycleChange { status: CharacterStatus::Deceased, reason: "Brèche".to_string(), date: 10, set_by: "2".to_string() });
+        assert_eq!(fiche.character_status(), CharacterStatus::Deceased);
+        assert!(!fiche.is_active_character());
+
+        assert!(CharacterStatus::Retired.can_be_set_by(true, false, false));
+        assert!(!CharacterStatus::Deceased.can_be_set_by(true, false, false));
+        assert!(!CharacterStatus::Archived.can_be_set_by(false, true, false));
+    }
+
+    #[test]
+    fn review_threads() {
+        let message = |id: &str, parent_id: Option<&str>, date: u64| ReviewMessage {
+            content: id.to_string(),
+            date,
+            id: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            ..fixtures::review_message("1", FicheState::Comment)
+        };
+        let mut fiche: FicheRP = FicheRP {
+            messages: vec![message("b", None, 20), message("a", None, 10), message("a1", Some("a"), 30), message("orphan", Some("private"), 40)],
+            ..fixtures::fiche(FicheState::Waiting)
+        };
+
+        let roots: Vec<&str> = fiche.thread(None).iter().map(|message| message.id.as_str()).collect();
+        assert_eq!(roots, vec!["a", "b", "orphan"]);
+        assert_eq!(fiche.thread(Some("a"))[0].id, "a1");
+
+        fiche.messages[2].deletion = Some(MessageDeletion { discord_id: "2".to_string(), date: 50 });
+        assert!(!fiche.messages[2].can_be_edited_by("1"));
+        assert!(!fiche.messages[1].can_be_deleted_by("2", false));
+        assert!(fiche.messages[1].can_be_deleted_by("2", true));
+        fiche.messages[2].redact();
+        assert!(fiche.messages[2].content.is_empty());
+    }
+
+    #[test]
+    fn fiche_images() {
+        let mut ficherp: FicheRP = fixtures::fiche(FicheState::Draft);
+        assert!(ficherp.portrait().is_none());
+        ficherp.images = vec![
+            FicheImage { id: "a".to_string(), kind: ImageKind::Gallery, width: 10, height: 10, uploaded_at: 0 },
+            FicheImage { id: "b".to_string(), kind: ImageKind::Portrait, width: 10, height: 10, uploaded_at: 0 },
+        ];
+        assert_eq!(ficherp.portrait().unwrap().id, "b");
+        assert_eq!(ficherp.gallery().len(), 1);
+        assert_eq!(serde_json::to_string(&ImageKind::Portrait).unwrap(), format!("\"{}\"", ImageKind::Portrait.as_ref()));
+    }
+}
@@ -0,0 +1,44 @@
+//! Fiches and review messages for the tests, the other crates of the workspace enable them with the `fixtures` feature
+
+use crate::fiche_rp::{FicheRP, FicheState, Job, ReviewMessage};
+
+/// Empty Classe-D fiche in `state`, tests fill in what they check with struct update syntax
+pub fn fiche(state: FicheState) -> FicheRP {
+    job_fiche(Job::ClassD, state)
+}
+
+pub fn job_fiche(job: Job, state: FicheState) -> FicheRP {
+    FicheRP {
+        id: String::new(),
+        name: String::new(),
+        job,
+        description: String::new(),
+        lore: String::new(),
+        submission_date: 0,
+        messages: vec![],
+        version: vec![],
+        state,
+        claim: None,
+        votes: vec![],
+        lifecycle: vec![],
+        images: vec![],
+        search_terms: vec![],
+    }
+}
+
+/// Empty public message of `discord_id`, a plain comment when `set_state` is `FicheState::Comment`
+pub fn review_message(discord_id: &str, set_state: FicheState) -> ReviewMessage {
+    ReviewMessage {
+        discord_id: discord_id.to_string(),
+        content: String::new(),
+        date: 0,
+        is_private: false,
+        is_comment: set_state == FicheState::Comment,
+        set_state,
+        id: String::new(),
+        parent_id: None,
+        edits: vec![],
+        deletion: None,
+        search_terms: vec![],
+    }
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// State of a background job of the backend, shown to the leads in the admin space
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    pub last_start: Option<u64>,
+    pub last_end: Option<u64>,
+    pub last_success: Option<u64>,
+    /// Summary returned by the last successful run
+    pub last_report: String,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub next_run: Option<u64>,
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Versions of the terms of use (`cgu.html`) and of the privacy policy (`privacy.html`). Bumping one in the configuration
+/// asks every user to accept the texts again before submitting a fiche
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct LegalVersions {
+    pub cgu: u32,
+    pub privacy: u32,
+}
+
+/// Acceptance of the texts by a user, every acceptance is kept
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TermsAcceptance {
+    pub versions: LegalVersions,
+    pub date: u64,
+}
+
+impl Default for LegalVersions {
+    fn default() -> Self {
+        LegalVersions {
+            cgu: 1,
+            privacy: 1,
+        }
+    }
+}
+
+impl LegalVersions {
+    /// Whether the latest acceptance covers these versions, a later version accepted before a rollback still counts
+    pub fn is_accepted(&self, acceptances: &[TermsAcceptance]) -> bool {
+        acceptances.last().is_some_and(|acceptance| acceptance.versions.cgu >= self.cgu && acceptance.versions.privacy >= self.privacy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terms_acceptance() {
+        let current: LegalVersions = LegalVersions { cgu: 2, privacy: 1 };
+        let accepted = |cgu: u32, privacy: u32| TermsAcceptance { versions: LegalVersions { cgu, privacy }, date: 1720000000 };
+
+        assert!(!current.is_accepted(&[]));
+        assert!(!current.is_accepted(&[accepted(1, 1)]));
+        assert!(current.is_accepted(&[accepted(1, 1), accepted(2, 1)]));
+        assert!(current.is_accepted(&[accepted(3, 1)]));
+        assert!(!current.is_accepted(&[accepted(2, 1), accepted(2, 0)]));
+    }
+}
@@ -4,10 +4,22 @@ pub mod user;
 pub mod discord;
 pub mod permissions;
 pub mod website_meta;
+pub mod stats;
+pub mod quota;
+pub mod events;
+pub mod search;
+pub mod mentions;
+pub mod markdown;
+pub mod jobs;
+pub mod audit;
+pub mod legal;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
 
 #[cfg(test)]
 mod tests {
     use crate::fiche_rp::*;
+    use crate::fixtures;
 
     #[test]
     fn fiche_rp() {
@@ -20,9 +32,9 @@ mod tests {
             submission_date: 0,
             messages: vec![],
             version: vec![],
-            state: FicheState::Waiting,
+            ..fixtures::fiche(FicheState::Waiting)
         };
 
         println!("{}", serde_json::to_string(&fiche).unwrap())
     }
-}
\ No newline at end of file
+}
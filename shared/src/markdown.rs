@@ -0,0 +1,199 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+use crate::fiche_rp::FicheRP;
+
+/// Limits applied to the markdown written by the users, checked by the backend and used by the viewer
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(default)]
+pub struct MarkdownRules {
+    /// Hosts images may be loaded from, subdomains included. Other images are replaced by their alt text
+    pub allowed_image_hosts: Vec<String>,
+    pub max_name_length: usize,
+    pub max_description_length: usize,
+    pub max_lore_length: usize,
+    pub max_comment_length: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MarkdownViolation {
+    TooLong { field: &'static str, length: usize, max: usize },
+}
+
+impl Default for MarkdownRules {
+    fn default() -> Self {
+        MarkdownRules {
+            allowed_image_hosts: vec!["intranet.projectvisualis.fr".to_string()],
+            max_name_length: 100,
+            max_description_length: 4000,
+            max_lore_length: 20000,
+            max_comment_length: 4000,
+        }
+    }
+}
+
+impl MarkdownRules {
+    /// Only https images from an allowed host, anything else would make the browser of every reader fetch it
+    pub fn is_image_allowed(&self, url: &str) -> bool {
+        let url: String = url.trim().to_lowercase();
+        let Some(rest) = url.strip_prefix("https://") else {
+            return false;
+        };
+        let authority: &str = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        if authority.contains('@') {
+            return false;
+        }
+        let host: &str = authority.split(':').next().unwrap_or_default();
+
+        !host.is_empty() && self.allowed_image_hosts.iter().any(|allowed| {
+            let allowed: String = allowed.trim().to_lowercase();
+            host == allowed || host.ends_with(&format!(".{}", allowed))
+        })
+    }
+
+    /// Normalised markdown: unix line endings, no control characters, raw HTML escaped so it shows as text,
+    /// and images from hosts that are not allowed replaced by their alt text
+    pub fn sanitize(&self, markdown: &str) -> String {
+        let normalized: String = normalize(markdown);
+        let mut replacements: Vec<(Range<usize>, String)> = vec![];
+        let mut refused_image: Option<(Range<usize>, String, usize)> = None;
+
+        for (event, range) in Parser::new_ext(&normalized, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).into_offset_iter() {
+            if let Some((image_range, alt, depth)) = &mut refused_image {
+                match event {
+                    Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                    Event::Start(Tag::Image { .. }) => *depth += 1,
+                    Event::End(TagEnd::Image) if *depth > 0 => *depth -= 1,
+                    Event::End(TagEnd::Image) => {
+                        replacements.push((image_range.clone(), escape_alt(alt)));
+                        refused_image = None;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match event {
+                Event::Start(Tag::Image { dest_url, .. }) if !self.is_image_allowed(&dest_url) => {
+                    refused_image = Some((range, String::new(), 0));
+                }
+                Event::Html(raw) | Event::InlineHtml(raw) => replacements.push((range, raw.replace('<', "\\<"))),
+                _ => {}
+            }
+        }
+
+        let mut sanitized: String = String::with_capacity(normalized.len());
+        let mut position: usize = 0;
+        replacements.sort_by_key(|(range, _)| range.start);
+        for (range, replacement) in replacements {
+            // Nested constructs are already covered by the replacement of their parent
+            if range.start < position {
+                continue;
+            }
+            sanitized.push_str(&normalized[position..range.start]);
+            sanitized.push_str(&replacement);
+            position = range.end;
+        }
+        sanitized.push_str(&normalized[position..]);
+        sanitized
+    }
+
+    /// The previous versions are sent back by the client too, they are held to the same limits
+    pub fn check_fiche(&self, fiche: &FicheRP) -> Result<(), MarkdownViolation> {
+        check_length("name", &fiche.name, self.max_name_length)?;
+        check_length("description", &fiche.description, self.max_description_length)?;
+        check_length("lore", &fiche.lore, self.max_lore_length)?;
+        fiche.version.iter().try_for_each(|version| {
+            check_length("name", &version.name, self.max_name_length)?;
+            check_length("description", &version.description, self.max_description_length)?;
+            check_length("lore", &version.lore, self.max_lore_length)
+        })
+    }
+
+    pub fn check_comment(&self, content: &str) -> Result<(), MarkdownViolation> {
+        check_length("comment", content, self.max_comment_length)
+    }
+
+    /// Sanitises the markdown of a fiche and of its previous versions
+    pub fn sanitize_fiche(&self, fiche: &mut FicheRP) {
+        fiche.description = self.sanitize(&fiche.description);
+        fiche.lore = self.sanitize(&fiche.lore);
+        fiche.version.iter_mut().for_each(|version| {
+            version.description = self.sanitize(&version.description);
+            version.lore = self.sanitize(&version.lore);
+        });
+    }
+}
+
+impl Display for MarkdownViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkdownViolation::TooLong { field, length, max } => write!(f, "The {} is too long: {} characters, {} at most", field, length, max),
+        }
+    }
+}
+
+fn check_length(field: &'static str, content: &str, max: usize) -> Result<(), MarkdownViolation> {
+    let length: usize = content.chars().count();
+    if length > max {
+        return Err(MarkdownViolation::TooLong { field, length, max });
+    }
+    Ok(())
+}
+
+fn normalize(markdown: &str) -> String {
+    markdown.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// The alt text replaces the image in the source, it must not form markdown again
+fn escape_alt(alt: &str) -> String {
+    alt.chars().fold(String::with_capacity(alt.len()), |mut escaped, c| {
+        if matches!(c, '[' | ']' | '(' | ')' | '!' | '<' | '>' | '*' | '_' | '`' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fiche_rp::{FicheState, FicheVersion, Job};
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn markdown_rules() {
+        let rules: MarkdownRules = MarkdownRules::default();
+        assert!(rules.is_image_allowed("https://intranet.projectvisualis.fr/app_img/waiting.svg"));
+        assert!(rules.is_image_allowed("https://cdn.intranet.projectvisualis.fr/a.png"));
+        assert!(!rules.is_image_allowed("http://intranet.projectvisualis.fr/a.png"));
+        assert!(!rules.is_image_allowed("https://intranet.projectvisualis.fr.evil.com/a.png"));
+        assert!(!rules.is_image_allowed("https://intranet.projectvisualis.fr@evil.com/a.png"));
+
+        let markdown: &str = "Salut <@123>\r\n![ok](https://intranet.projectvisualis.fr/a.png) ![traceur](https://evil.com/t.png)\n\n![ref][logo] <img src=\"https://evil.com/x.png\">\n\n[logo]: https://evil.com/logo.png";
+        assert_eq!(rules.sanitize(markdown), "Salut <@123>\n![ok](https://intranet.projectvisualis.fr/a.png) traceur\n\nref \\<img src=\"https://evil.com/x.png\">\n\n[logo]: https://evil.com/logo.png");
+        assert_eq!(rules.sanitize(&rules.sanitize(markdown)), rules.sanitize(markdown));
+
+        assert_eq!(rules.check_comment(&"a".repeat(4001)), Err(MarkdownViolation::TooLong { field: "comment", length: 4001, max: 4000 }));
+        assert!(rules.check_comment("court").is_ok());
+
+        let mut fiche: FicheRP = FicheRP { name: "Roger".to_string(), ..fixtures::fiche(FicheState::Waiting) };
+        assert!(rules.check_fiche(&fiche).is_ok());
+        fiche.name = "a".repeat(101);
+        assert_eq!(rules.check_fiche(&fiche), Err(MarkdownViolation::TooLong { field: "name", length: 101, max: 100 }));
+        fiche.name = "Roger".to_string();
+        fiche.version.push(FicheVersion { name: "Roger".to_string(), job: Job::ClassD, description: String::new(), lore: "a".repeat(20001), submission_date: 0 });
+        assert_eq!(rules.check_fiche(&fiche), Err(MarkdownViolation::TooLong { field: "lore", length: 20001, max: 20000 }));
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// Notification left to a user mentioned in a review message, read from the notification bell
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct MentionNotification {
+    pub id: String,
+    /// Mentioned user
+    pub discord_id: String,
+    pub author_id: String,
+    pub owner_id: String,
+    pub fiche_id: String,
+    pub message_id: String,
+    pub date: u64,
+    pub read: bool,
+}
+
+/// Discord ids mentioned in a message, each one only once. Mentions use the Discord syntax `<@id>`
+/// so they are pinged as is when the message is relayed to the webhook
+pub fn extract_mentions(content: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    let mut rest: &str = content;
+
+    while let Some(start) = rest.find("<@") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let id: &str = rest[..end].trim_start_matches('!');
+        if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) && !mentions.iter().any(|mention| mention == id) {
+            mentions.push(id.to_string());
+        }
+        rest = &rest[end..];
+    }
+    mentions
+}
+
+/// Replaces each known mention with `@name`, unknown ids are left untouched
+pub fn render_mentions(content: &str, resolve: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered: String = content.to_string();
+    extract_mentions(content).iter().for_each(|id| {
+        if let Some(name) = resolve(id) {
+            rendered = rendered.replace(&format!("<@{}>", id), &format!("@{}", name)).replace(&format!("<@!{}>", id), &format!("@{}", name));
+        }
+    });
+    rendered
+}
+
+/// Partial name typed after a trailing `@`, used to suggest accounts while writing
+pub fn pending_mention(content: &str) -> Option<&str> {
+    let start: usize = content.rfind('@')?;
+    if content[..start].chars().last().is_some_and(|c| !c.is_whitespace()) {
+        return None;
+    }
+    let partial: &str = &content[start + 1..];
+    if partial.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(partial)
+}
+
+/// Replaces the partial name being typed by the mention of the chosen account
+pub fn complete_mention(content: &str, discord_id: &str) -> String {
+    match pending_mention(content) {
+        Some(partial) => format!("{}<@{}> ", &content[..content.len() - partial.len() - 1], discord_id),
+        None => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions() {
+        let content: &str = "<@123> tu peux regarder ? cc <@!456> et <@123>, pas <@abc>";
+        assert_eq!(extract_mentions(content), vec!["123".to_string(), "456".to_string()]);
+        assert_eq!(render_mentions("<@123> et <@789>", |id| (id == "123").then(|| "Roger".to_string())), "@Roger et <@789>");
+
+        assert_eq!(pending_mention("Salut @Rog"), Some("Rog"));
+        assert_eq!(pending_mention("Salut @"), Some(""));
+        assert_eq!(pending_mention("mail@exemple"), None);
+        assert_eq!(pending_mention("@Roger ça va"), None);
+        assert_eq!(complete_mention("Salut @Rog", "123"), "Salut <@123> ");
+    }
+}
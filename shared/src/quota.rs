@@ -0,0 +1,139 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fiche_rp::{FicheRP, FicheState, Job};
+
+/// Character slots per user and global quotas per job.
+/// `job`, `role` and `rank` are the variant names of the job enums (e.g. "Mtf", "Omega10", "Sgt"), a missing `role` or `rank` matches any value.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct QuotaRules {
+    /// Characters a user may hold at once, `None` for no limit
+    pub max_characters: Option<usize>,
+    pub department_slots: Vec<DepartmentSlots>,
+    pub job_quotas: Vec<JobQuota>,
+}
+
+/// Characters of a job family a single user may hold at once
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DepartmentSlots {
+    pub job: String,
+    pub max: usize,
+}
+
+/// Characters of a job (or role, or rank) held by all users together
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JobQuota {
+    pub job: String,
+    pub role: Option<String>,
+    pub rank: Option<String>,
+    pub max: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct QuotaUsage {
+    pub quota: JobQuota,
+    pub used: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct QuotaOverview {
+    pub rules: QuotaRules,
+    pub usages: Vec<QuotaUsage>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum QuotaViolation {
+    MaxCharacters(usize),
+    Department(DepartmentSlots),
+    Job(JobQuota),
+}
+
+impl FicheRP {
+    /// Fiches taking a slot: the ones under review and the active characters
+    pub fn holds_slot(&self) -> bool {
+        match self.state {
+            FicheState::Waiting | FicheState::RequestModification | FicheState::StaffValidated => true,
+            FicheState::Accepted => self.is_active_character(),
+            FicheState::Refused | FicheState::Comment | FicheState::Draft => false,
+        }
+    }
+}
+
+impl JobQuota {
+    pub fn matches(&self, job: &Job) -> bool {
+        self.job == job.family_key()
+            && self.role.as_deref().is_none_or(|role| job.role_key() == Some(role))
+            && self.rank.as_deref().is_none_or(|rank| job.rank_key() == Some(rank))
+    }
+
+    pub fn label(&self) -> String {
+        [Some(self.job.as_str()), self.role.as_deref(), self.rank.as_deref()].iter().flatten().cloned().collect::<Vec<&str>>().join(" / ")
+    }
+}
+
+impl QuotaRules {
+    /// Checks a new (or modified) fiche, neither `owner_fiches` nor `all_fiches` should contain it
+    pub fn check(&self, fiche: &FicheRP, owner_fiches: &[&FicheRP], all_fiches: &[&FicheRP]) -> Result<(), QuotaViolation> {
+        let owner_slots: Vec<&&FicheRP> = owner_fiches.iter().filter(|owner_fiche| owner_fiche.holds_slot()).collect();
+
+        if let Some(max) = self.max_characters.filter(|max| owner_slots.len() >= *max) {
+            return Err(QuotaViolation::MaxCharacters(max));
+        }
+
+        if let Some(slots) = self.department_slots.iter().find(|slots| slots.job == fiche.job.family_key()) {
+            if owner_slots.iter().filter(|owner_fiche| owner_fiche.job.family_key() == slots.job).count() >= slots.max {
+                return Err(QuotaViolation::Department(slots.clone()));
+            }
+        }
+
+        self.usages(all_fiches).into_iter()
+            .find(|usage| usage.quota.matches(&fiche.job) && usage.used >= usage.quota.max)
+            .map_or(Ok(()), |usage| Err(QuotaViolation::Job(usage.quota)))
+    }
+
+    pub fn usages(&self, all_fiches: &[&FicheRP]) -> Vec<QuotaUsage> {
+        self.job_quotas.iter().map(|quota| QuotaUsage {
+            quota: quota.clone(),
+            used: all_fiches.iter().filter(|fiche| fiche.holds_slot() && quota.matches(&fiche.job)).count(),
+        }).collect()
+    }
+}
+
+impl Display for QuotaViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaViolation::MaxCharacters(max) => write!(f, "Character limit reached: {} character(s) per user, including the fiches under review", max),
+            QuotaViolation::Department(slots) => write!(f, "Department limit reached: {} {} character(s) per user", slots.max, slots.job),
+            QuotaViolation::Job(quota) => write!(f, "Job quota reached: {} place(s) for {}", quota.max, quota.label()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fiche_rp::{SecurityRank, SecurityRole};
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn quotas() {
+        let rules: QuotaRules = QuotaRules {
+            max_characters: Some(2),
+            department_slots: vec![DepartmentSlots { job: "Security".to_string(), max: 1 }],
+            job_quotas: vec![JobQuota { job: "SiteDirector".to_string(), role: None, rank: None, max: 1 }],
+        };
+
+        let guard: FicheRP = fixtures::job_fiche(Job::Security(SecurityRole::Gunsmith(SecurityRank::Sgt)), FicheState::Waiting);
+        let refused: FicheRP = fixtures::job_fiche(Job::ClassD, FicheState::Refused);
+        let director: FicheRP = fixtures::job_fiche(Job::SiteDirector, FicheState::Accepted);
+
+        assert_eq!(rules.check(&fixtures::job_fiche(Job::ClassD, FicheState::Waiting), &[&guard, &refused], &[&guard, &refused]), Ok(()));
+        assert_eq!(rules.check(&fixtures::job_fiche(Job::Security(SecurityRole::SecurityOfficier(SecurityRank::Rct)), FicheState::Waiting), &[&guard], &[&guard]), Err(QuotaViolation::Department(rules.department_slots[0].clone())));
+        assert_eq!(rules.check(&fixtures::job_fiche(Job::ClassD, FicheState::Waiting), &[&guard, &director], &[&guard, &director]), Err(QuotaViolation::MaxCharacters(2)));
+        assert_eq!(rules.check(&fixtures::job_fiche(Job::SiteDirector, FicheState::Waiting), &[], &[&director]), Err(QuotaViolation::Job(rules.job_quotas[0].clone())));
+        assert_eq!(rules.usages(&[&director, &guard])[0].used, 1);
+    }
+}
@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fiche_rp::{FicheRP, FicheState, ReviewMessage};
+
+/// Longest query handled, the extra terms are ignored
+pub const MAX_TERMS: usize = 8;
+/// Characters kept around a match in a snippet
+const SNIPPET_CONTEXT: usize = 60;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SearchHit {
+    pub owner_id: String,
+    pub fiche_id: String,
+    pub name: String,
+    pub state: FicheState,
+    pub snippets: Vec<Snippet>,
+    pub score: usize,
+}
+
+/// Excerpt of a field around its first match, `matched` is the highlighted word
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Snippet {
+    pub field: SearchField,
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SearchField {
+    Name,
+    Description,
+    Lore,
+    ReviewMessage,
+}
+
+impl SearchField {
+    pub fn get_text(&self) -> &'static str {
+        match self {
+            SearchField::Name => "Nom",
+            SearchField::Description => "Description",
+            SearchField::Lore => "Lore",
+            SearchField::ReviewMessage => "Message",
+        }
+    }
+
+    fn weight(&self) -> usize {
+        match self {
+            SearchField::Name => 10,
+            SearchField::Description => 3,
+            SearchField::Lore | SearchField::ReviewMessage => 1,
+        }
+    }
+}
+
+/// Lowercase without the French accents and ligatures, "Éloïse" and "eloise" compare equal
+pub fn fold(text: &str) -> String {
+    text.chars().flat_map(char::to_lowercase).fold(String::with_capacity(text.len()), |mut folded, c| {
+        match c {
+            'à' | 'â' | 'ä' | 'á' => folded.push('a'),
+            'é' | 'è' | 'ê' | 'ë' => folded.push('e'),
+            'î' | 'ï' | 'í' => folded.push('i'),
+            'ô' | 'ö' | 'ó' => folded.push('o'),
+            'ù' | 'û' | 'ü' | 'ú' => folded.push('u'),
+            'ÿ' => folded.push('y'),
+            'ç' => folded.push('c'),
+            'ñ' => folded.push('n'),
+            'œ' => folded.push_str("oe"),
+            'æ' => folded.push_str("ae"),
+            _ => folded.push(c),
+        }
+        folded
+    })
+}
+
+/// Folded words of the query, one letter words and duplicates dropped
+pub fn parse_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    fold(query).split(|c: char| !c.is_alphanumeric()).filter(|term| term.chars().count() > 1).for_each(|term| {
+        if !terms.iter().any(|known| known == term) && terms.len() < MAX_TERMS {
+            terms.push(term.to_string());
+        }
+    });
+    terms
+}
+
+/// A term matches the start of a word, or a word it extends by a few letters ("personnages" finds "personnage")
+fn word_matches(word: &str, term: &str) -> bool {
+    word.starts_with(term) || (word.chars().count() >= 4 && term.starts_with(word) && term.len() - word.len() <= 2)
+}
+
+/// Words shorter than `term` that it still matches, the index lookup lists them next to the prefix of `term`
+pub fn shorter_matches(term: &str) -> Vec<&str> {
+    (1..=2).filter_map(|cut| term.len().checked_sub(cut).and_then(|end| term.get(..end)))
+           .filter(|word| word.chars().count() >= 4)
+           .collect()
+}
+
+/// Folded words of `texts` without duplicates, sorted. One letter words are left out since no term matches them
+pub fn index_terms(texts: &[&str]) -> Vec<String> {
+    let mut terms: Vec<String> = texts.iter()
+                                      .flat_map(|text| fold(text).split(|c: char| !c.is_alphanumeric()).filter(|word| word.chars().count() > 1).map(str::to_string).collect::<Vec<String>>())
+                                      .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// Byte ranges of the words of `text`
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words: Vec<(usize, usize)> = vec![];
+    let mut start: Option<usize> = None;
+    text.char_indices().for_each(|(index, c)| {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(word_start)) => {
+                words.push((word_start, index));
+                start = None;
+            }
+            _ => {}
+        }
+    });
+    if let Some(word_start) = start {
+        words.push((word_start, text.len()));
+    }
+    words
+}
+
+/// Cuts `text` to at most `max` characters, from its end when `from_end` is set
+fn cut(text: &str, max: usize, from_end: bool) -> String {
+    let count: usize = text.chars().count();
+    if count <= max {
+        return text.to_string();
+    }
+    if from_end {
+        format!("…{}", text.chars().skip(count - max).collect::<String>())
+    } else {
+        format!("{}…", text.chars().take(max).collect::<String>())
+    }
+}
+
+/// Terms found in `text` along with the snippet of the first match
+fn search_text(field: SearchField, text: &str, terms: &[String]) -> Option<(Vec<usize>, Snippet)> {
+    let mut found: Vec<usize> = vec![];
+    let mut first_match: Option<(usize, usize)> = None;
+
+    words(text).into_iter().for_each(|(start, end)| {
+        let word: String = fold(&text[start..end]);
+        terms.iter().enumerate().filter(|(_, term)| word_matches(&word, term)).for_each(|(index, _)| {
+            if !found.contains(&index) {
+                found.push(index);
+            }
+            first_match.get_or_insert((start, end));
+        });
+    });
+
+    first_match.map(|(start, end)| (found, Snippet {
+        field,
+        before: cut(&text[..start], SNIPPET_CONTEXT, true),
+        matched: text[start..end].to_string(),
+        after: cut(&text[end..], SNIPPET_CONTEXT, false),
+    }))
+}
+
+impl FicheRP {
+    /// Indexes the current name, description, lore and review messages, called before every write of the fiche
+    pub fn refresh_search_terms(&mut self) {
+        self.search_terms = index_terms(&[&self.name, &self.description, &self.lore]);
+        self.messages.iter_mut().for_each(ReviewMessage::refresh_search_terms);
+    }
+
+    /// Every term has to be found in the fiche, the review messages are searched for the staff only
+    pub fn search(&self, owner_id: &str, terms: &[String], include_messages: bool) -> Option<SearchHit> {
+        let mut fields: Vec<(SearchField, &str)> = vec![(SearchField::Name, &self.name), (SearchField::Description, &self.description), (SearchField::Lore, &self.lore)];
+        if include_messages {
+            self.messages.iter().filter(|message| !message.is_deleted()).for_each(|message| fields.push((SearchField::ReviewMessage, &message.content)));
+        }
+
+        let mut found: Vec<usize> = vec![];
+        let mut snippets: Vec<Snippet> = vec![];
+        let mut score: usize = 0;
+        fields.into_iter().for_each(|(field, text)| {
+            if let Some((field_found, snippet)) = search_text(field, text, terms) {
+                score += field.weight() * field_found.len();
+                field_found.into_iter().for_each(|index| {
+                    if !found.contains(&index) {
+                        found.push(index);
+                    }
+                });
+                if !snippets.iter().any(|known: &Snippet| known.field == field) {
+                    snippets.push(snippet);
+                }
+            }
+        });
+
+        (!terms.is_empty() && found.len() == terms.len()).then(|| SearchHit {
+            owner_id: owner_id.to_string(),
+            fiche_id: self.id.clone(),
+            name: self.name.clone(),
+            state: self.state.clone(),
+            snippets,
+            score,
+        })
+    }
+}
+
+impl ReviewMessage {
+    /// Deleted messages are left out of the index, their content is hidden
+    pub fn refresh_search_terms(&mut self) {
+        self.search_terms = if self.is_deleted() { vec![] } else { index_terms(&[&self.content]) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fiche_rp::MessageDeletion;
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn search() {
+        let fiche: FicheRP = FicheRP {
+            id: "fiche".to_string(),
+            name: "Éloïse Moreau".to_string(),
+            description: "Ancienne chercheuse reconvertie".to_string(),
+            lore: "Elle a survécu à la brèche du secteur B.".to_string(),
+            messages: vec![ReviewMessage {
+                content: "Le lore manque de détails".to_string(),
+                is_private: true,
+                ..fixtures::review_message("reviewer", FicheState::Comment)
+            }],
+            ..fixtures::fiche(FicheState::Waiting)
+        };
+
+        assert_eq!(parse_terms("Éloïse, BRECHE e"), vec!["eloise".to_string(), "breche".to_string()]);
+
+        let hit: SearchHit = fiche.search("owner", &parse_terms("eloise breche"), false).unwrap();
+        assert_eq!(hit.snippets[0].field, SearchField::Name);
+        assert_eq!(hit.snippets[0].matched, "Éloïse");
+        assert_eq!(hit.snippets[1].before, "Elle a survécu à la ");
+        assert_eq!(hit.snippets[1].matched, "brèche");
+        assert!(fiche.search("owner", &parse_terms("chercheuses"), false).is_some());
+        assert!(fiche.search("owner", &parse_terms("cherch"), false).is_some());
+
+        // Review messages are only searched for the staff, every term has to match
+        assert!(fiche.search("owner", &parse_terms("details"), false).is_none());
+        assert!(fiche.search("owner", &parse_terms("details"), true).is_some());
+        assert!(fiche.search("owner", &parse_terms("eloise dupont"), true).is_none());
+    }
+    #[test]
+    fn search_index() {
+        assert_eq!(index_terms(&["Éloïse Moreau", "L'élève ÉLOÏSE, à 2 ans"]), vec!["ans", "eleve", "eloise", "moreau"]);
+
+        // The index lookup finds every word a term matches
+        assert_eq!(shorter_matches("personnages"), vec!["personnage", "personnag"]);
+        assert_eq!(shorter_matches("chats"), vec!["chat"]);
+        assert!(shorter_matches("ours").is_empty());
+        ["personnage", "personnages", "personnageux", "personne", "perso"].into_iter().filter(|word| word_matches(word, "personnages")).for_each(|word| {
+            assert!(word.starts_with("personnages") || shorter_matches("personnages").contains(&word));
+        });
+
+        let mut message: ReviewMessage = ReviewMessage { content: "Très bien".to_string(), ..fixtures::review_message("1", FicheState::Comment) };
+        message.refresh_search_terms();
+        assert_eq!(message.search_terms, vec!["bien", "tres"]);
+        message.deletion = Some(MessageDeletion { discord_id: "1".to_string(), date: 10 });
+        message.refresh_search_terms();
+        assert!(message.search_terms.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fiche_rp::{FicheRP, FicheState};
+use crate::user::FrontAccount;
+
+pub const WEEK_SECS: u64 = 7 * 86400;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct StaffStats {
+    pub generated_at: u64,
+    /// Oldest week first, weeks without submission are kept with a count of 0
+    pub weekly_submissions: Vec<WeeklyCount>,
+    /// Median delay between the first submission and the first final decision, over decided fiches
+    pub median_decision_secs: Option<u64>,
+    /// Fiches waiting on the staff (`Waiting` or `StaffValidated`) per job family
+    pub backlog: Vec<JobBacklog>,
+    pub accepted: usize,
+    pub refused: usize,
+    pub reviewers: Vec<ReviewerActivity>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WeeklyCount {
+    pub week_start: u64,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JobBacklog {
+    pub job_family: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct ReviewerActivity {
+    pub discord_id: String,
+    pub global_name: String,
+    pub comments: usize,
+    pub modification_requests: usize,
+    pub validations: usize,
+    pub acceptances: usize,
+    pub refusals: usize,
+    pub last_activity: u64,
+}
+
+impl ReviewerActivity {
+    pub fn total(&self) -> usize {
+        self.comments + self.modification_requests + self.validations + self.acceptances + self.refusals
+    }
+}
+
+impl StaffStats {
+    pub fn compute(accounts: &[FrontAccount], now: u64, weeks: usize) -> StaffStats {
+        let fiches: Vec<(&FrontAccount, &FicheRP)> = accounts.iter()
+                                                             .flat_map(|account| account.fiches.iter().map(move |fiche| (account, fiche)))
+                                                             .filter(|(_, fiche)| fiche.state != FicheState::Draft)
+                                                             .collect();
+
+        let current_week: u64 = week_start(now);
+        let mut weekly_submissions: Vec<WeeklyCount> = (0..weeks as u64).rev()
+                                                                         .map(|offset| WeeklyCount { week_start: current_week.saturating_sub(offset * WEEK_SECS), count: 0 })
+                                                                         .collect();
+        fiches.iter().for_each(|(_, fiche)| {
+            let submission_week: u64 = week_start(first_submission(fiche));
+            if let Some(week) = weekly_submissions.iter_mut().find(|week| week.week_start == submission_week) {
+                week.count += 1;
+            }
+        });
+
+        let mut decision_delays: Vec<u64> = fiches.iter().filter_map(|(_, fiche)| {
+            fiche.messages.iter()
+                 .find(|message| message.set_state == FicheState::Accepted || message.set_state == FicheState::Refused)
+                 .map(|message| message.date.saturating_sub(first_submission(fiche)))
+        }).collect();
+        decision_delays.sort_unstable();
+
+        let mut backlog: HashMap<String, usize> = HashMap::new();
+        fiches.iter()
+              .filter(|(_, fiche)| fiche.state == FicheState::Waiting || fiche.state == FicheState::StaffValidated)
+              .for_each(|(_, fiche)| *backlog.entry(fiche.job.family_key().to_string()).or_default() += 1);
+        let mut backlog: Vec<JobBacklog> = backlog.into_iter().map(|(job_family, count)| JobBacklog { job_family, count }).collect();
+        backlog.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.job_family.cmp(&b.job_family)));
+
+        // Messages written by the owner of the fiche are answers, not reviews
+        let mut reviewers: HashMap<String, ReviewerActivity> = HashMap::new();
+        fiches.iter().for_each(|(owner, fiche)| {
+            fiche.messages.iter().filter(|message| message.discord_id != owner.discord_user.id).for_each(|message| {
+                let activity: &mut ReviewerActivity = reviewers.entry(message.discord_id.clone()).or_insert_with(|| ReviewerActivity {
+                    discord_id: message.discord_id.clone(),
+                    global_name: accounts.iter().find(|account| account.discord_user.id == message.discord_id).map(|account| account.discord_user.global_name.clone()).unwrap_or_default(),
+                    ..ReviewerActivity::default()
+                });
+                match message.set_state {
+                    FicheState::Comment | FicheState::Waiting => activity.comments += 1,
+                    FicheState::RequestModification => activity.modification_requests += 1,
+                    FicheState::StaffValidated => activity.validations += 1,
+                    FicheState::Accepted => activity.acceptances += 1,
+                    FicheState::Refused => activity.refusals += 1,
+                    FicheState::Draft => {}
+                }
+                activity.last_activity = activity.last_activity.max(message.date);
+            });
+            // Fiches become CONFORME through votes, approvals count as validations
+            fiche.votes.iter().filter(|vote| vote.approve).for_each(|vote| {
+                let activity: &mut ReviewerActivity = reviewers.entry(vote.discord_id.clone()).or_insert_with(|| ReviewerActivity {
+                    discord_id: vote.discord_id.clone(),
+                    global_name: accounts.iter().find(|account| account.discord_user.id == vote.discord_id).map(|account| account.discord_user.global_name.clone()).unwrap_or_default(),
+                    ..ReviewerActivity::default()
+                });
+                activity.validations += 1;
+                activity.last_activity = activity.last_activity.max(vote.date);
+            });
+        });
+        let mut reviewers: Vec<ReviewerActivity> = reviewers.into_values().collect();
+        reviewers.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.discord_id.cmp(&b.discord_id)));
+
+        StaffStats {
+            generated_at: now,
+            weekly_submissions,
+            median_decision_secs: median(&decision_delays),
+            backlog,
+            accepted: fiches.iter().filter(|(_, fiche)| fiche.state == FicheState::Accepted).count(),
+            refused: fiches.iter().filter(|(_, fiche)| fiche.state == FicheState::Refused).count(),
+            reviewers,
+        }
+    }
+
+    pub fn acceptance_rate(&self) -> Option<f32> {
+        let decided: usize = self.accepted + self.refused;
+        (decided > 0).then(|| self.accepted as f32 / decided as f32)
+    }
+}
+
+/// Monday 00:00 UTC of the week containing `timestamp`
+pub fn week_start(timestamp: u64) -> u64 {
+    let day: u64 = timestamp / 86400;
+    // 1970-01-01 was a Thursday
+    day.saturating_sub((day + 3) % 7) * 86400
+}
+
+fn first_submission(fiche: &FicheRP) -> u64 {
+    fiche.version.first().map(|version| version.submission_date).unwrap_or(fiche.submission_date)
+}
+
+fn median(sorted: &[u64]) -> Option<u64> {
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[len / 2 - 1] + sorted[len / 2]) / 2),
+        len => Some(sorted[len / 2]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discord::User;
+    use crate::fiche_rp::{Job, MedicRole, ReviewMessage};
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn staff_stats() {
+        // Monday 2024-07-01 00:00 UTC
+        let monday: u64 = 1719792000;
+        assert_eq!(week_start(monday + 3 * 86400 + 3600), monday);
+
+        let message = |discord_id: &str, date: u64, set_state: FicheState| ReviewMessage { date, ..fixtures::review_message(discord_id, set_state) };
+        let fiche = |id: &str, job: Job, submission_date: u64, state: FicheState, messages: Vec<ReviewMessage>| FicheRP {
+            id: id.to_string(),
+            submission_date,
+            messages,
+            ..fixtures::job_fiche(job, state)
+        };
+
+        let player: FrontAccount = FrontAccount {
+            discord_user: User { id: "1".to_string(), global_name: "Joueur".to_string(), avatar: "".to_string() },
+            fiches: vec![
+                fiche("a", Job::ClassD, monday, FicheState::Accepted, vec![message("1", monday + 10, FicheState::Comment), message("2", monday + 100, FicheState::Accepted)]),
+                fiche("b", Job::ClassD, monday + WEEK_SECS, FicheState::Refused, vec![message("2", monday + WEEK_SECS + 300, FicheState::Refused)]),
+                fiche("c", Job::Medic(MedicRole::Nurse), monday + WEEK_SECS, FicheState::Waiting, vec![]),
+            ],
+            ..FrontAccount::default()
+        };
+        let staff: FrontAccount = FrontAccount {
+            discord_user: User { id: "2".to_string(), global_name: "Lead".to_string(), avatar: "".to_string() },
+            ..FrontAccount::default()
+        };
+
+        let stats: StaffStats = StaffStats::compute(&[player, staff], monday + WEEK_SECS + 1000, 3);
+
+        assert_eq!(stats.weekly_submissions.iter().map(|week| week.count).collect::<Vec<usize>>(), vec![0, 1, 2]);
+        assert_eq!(stats.median_decision_secs, Some(200));
+        assert_eq!(stats.backlog.len(), 1);
+        assert_eq!(stats.backlog[0].job_family, "Medic");
+        assert_eq!(stats.acceptance_rate(), Some(0.5));
+        assert_eq!(stats.reviewers.len(), 1);
+        assert_eq!(stats.reviewers[0].global_name, "Lead");
+        assert_eq!((stats.reviewers[0].acceptances, stats.reviewers[0].refusals, stats.reviewers[0].comments), (1, 1, 0));
+    }
+}
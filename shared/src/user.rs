@@ -4,18 +4,33 @@ use serde::{Deserialize, Serialize};
 
 use crate::discord::User;
 use crate::fiche_rp::FicheRP;
+#[cfg(target_arch = "x86_64")]
+use crate::legal::TermsAcceptance;
 
 #[cfg(target_arch = "x86_64")]
 #[derive(Serialize, Deserialize)]
 pub struct Account {
     pub discord_user: User,
     pub discord_roles: Vec<String>,
-    pub auth_id: String,
+    /// One per logged in device, the `auth_id` cookie identifies the session
+    #[serde(default)]
+    pub sessions: Vec<AuthSession>,
     pub token: BasicTokenResponse,
     pub last_renewal: u64,
     pub fiches: Vec<FicheRP>,
     pub creation_date: u64,
     pub banned: bool,
+    #[serde(default)]
+    pub needs_relogin: bool,
+    /// Terms of use and privacy policy acceptances, oldest first
+    #[serde(default)]
+    pub terms_acceptances: Vec<TermsAcceptance>,
+    /// Consecutive failures of the background renewal and Discord sync, reset by a successful one or a login
+    #[serde(default)]
+    pub sync_failures: u32,
+    /// The account is skipped by the background sync until then, backoff after failures
+    #[serde(default)]
+    pub next_sync: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
@@ -25,4 +40,86 @@ pub struct FrontAccount {
     pub fiches: Vec<FicheRP>,
     pub creation_date: u64,
     pub banned: bool,
-}
\ No newline at end of file
+    /// The Discord token can't be renewed anymore, the user has to log in again before it expires
+    #[serde(default)]
+    pub needs_relogin: bool,
+}
+
+/// Account of the logged in user, the terms acceptances themselves stay on the backend
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AuthAccount {
+    #[serde(flatten)]
+    pub account: FrontAccount,
+    /// The current terms of use and privacy policy are accepted
+    pub terms_accepted: bool,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Account {
+    /// The account without its sessions, token and terms acceptances
+    pub fn front(&self) -> FrontAccount {
+        FrontAccount {
+            discord_user: self.discord_user.clone(),
+            discord_roles: self.discord_roles.clone(),
+            fiches: self.fiches.clone(),
+            creation_date: self.creation_date,
+            banned: self.banned,
+            needs_relogin: self.needs_relogin,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuthSession {
+    /// Public id used to revoke the session, `auth_id` is the secret stored in the cookie and never leaves the backend
+    pub id: String,
+    pub auth_id: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub user_agent: String,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl AuthSession {
+    pub fn info(&self, current_auth_id: &str) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            user_agent: self.user_agent.clone(),
+            current: self.auth_id == current_auth_id,
+        }
+    }
+}
+
+/// Session as listed in "Mes sessions"
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub user_agent: String,
+    /// Session of the device making the request
+    pub current: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_info() {
+        let session: AuthSession = AuthSession {
+            id: "session".to_string(),
+            auth_id: "secret".to_string(),
+            created_at: 1720000000,
+            expires_at: 1722419200,
+            user_agent: "Firefox".to_string(),
+        };
+
+        assert!(session.info("secret").current);
+        assert!(!session.info("other").current);
+        assert!(!serde_json::to_string(&session.info("secret")).unwrap().contains("secret"));
+    }
+}
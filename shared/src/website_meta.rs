@@ -1,13 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct WebsiteMeta {
+    #[serde(default)]
     pub whitelist: Vec<String>,
+    #[serde(default)]
+    pub schema_version: u32,
 }
-impl Default for WebsiteMeta {
-    fn default() -> Self {
-        WebsiteMeta {
-            whitelist: vec![],
-        }
-    }
-}
\ No newline at end of file